@@ -0,0 +1,178 @@
+//! Creates a [message-only window](https://learn.microsoft.com/en-us/windows/win32/winmsg/window-features#message-only-windows)
+//! whose window procedure is an [`AtlThunk`](atl_thunk::AtlThunk) carrying a Rust counter object, posts some messages
+//! to it, then prints the accumulated state.
+
+#[cfg(windows)]
+mod message_window {
+    use atl_thunk::AtlThunk;
+    use core::cell::Cell;
+    use core::ptr;
+    use windows::core::{w, Error, Result, PCWSTR};
+    use windows::Win32::Foundation::{HMODULE, HWND, LPARAM, LRESULT, WPARAM};
+    use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+    #[cfg(target_pointer_width = "64")]
+    use windows::Win32::UI::WindowsAndMessaging::SetWindowLongPtrW;
+    #[cfg(target_pointer_width = "32")]
+    use windows::Win32::UI::WindowsAndMessaging::SetWindowLongW as SetWindowLongPtrW;
+    use windows::Win32::UI::WindowsAndMessaging::{
+        CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, GetMessageW, PostMessageW, PostQuitMessage,
+        RegisterClassW, UnregisterClassW, CREATESTRUCTW, GWLP_WNDPROC, HMENU, HWND_MESSAGE, MSG, WINDOW_EX_STYLE,
+        WINDOW_STYLE, WM_APP, WM_CLOSE, WM_DESTROY, WM_NCCREATE, WNDCLASSW,
+    };
+
+    const CLASS_NAME: PCWSTR = w!("AtlThunkMessageWindow");
+    const WM_ADD: u32 = WM_APP;
+
+    #[derive(Default)]
+    struct Counter {
+        window: Cell<HWND>,
+        messages: Cell<usize>,
+        sum: Cell<usize>,
+    }
+
+    /// Passed to [`CreateWindowExW`] as the creation parameter, so that [`start_window_procedure`] can bind the window.
+    struct Binding<'a> {
+        counter: &'a Counter,
+        thunk: &'a AtlThunk,
+    }
+
+    /// The window procedure of the window class. The thunk needs to know the window handle for default message
+    /// processing, but the handle is unknown until the window receives its first message. So this procedure stores the
+    /// window handle into the counter on `WM_NCCREATE`, replaces itself with the thunk, then forwards the message to
+    /// the thunk. No message with creation parameters arrives before `WM_NCCREATE`, earlier messages are default
+    /// processed.
+    unsafe extern "system" fn start_window_procedure(
+        window: HWND,
+        message: u32,
+        w_param: WPARAM,
+        l_param: LPARAM,
+    ) -> LRESULT {
+        if message == WM_NCCREATE {
+            let binding = unsafe { &*(*(l_param.0 as *const CREATESTRUCTW)).lpCreateParams.cast::<Binding>() };
+            let window_procedure = binding.thunk.as_window_procedure();
+
+            binding.counter.window.set(window);
+
+            unsafe { SetWindowLongPtrW(window, GWLP_WNDPROC, window_procedure as usize as _) };
+
+            unsafe { window_procedure(window, message, w_param, l_param) }
+        } else {
+            unsafe { DefWindowProcW(window, message, w_param, l_param) }
+        }
+    }
+
+    /// The window procedure wrapped by the thunk, which receives the counter instead of the window handle.
+    unsafe extern "system" fn counter_window_procedure(
+        counter: HWND,
+        message: u32,
+        w_param: WPARAM,
+        l_param: LPARAM,
+    ) -> LRESULT {
+        let counter = unsafe { &*counter.0.cast::<Counter>() };
+
+        match message {
+            WM_ADD => {
+                counter.messages.set(counter.messages.get() + 1);
+                counter.sum.set(counter.sum.get() + w_param.0);
+
+                LRESULT(0)
+            }
+            WM_DESTROY => {
+                unsafe { PostQuitMessage(0) };
+
+                LRESULT(0)
+            }
+            _ => unsafe { DefWindowProcW(counter.window.get(), message, w_param, l_param) },
+        }
+    }
+
+    fn post_messages(window: HWND) -> Result<()> {
+        for value in 1..=10 {
+            unsafe { PostMessageW(window, WM_ADD, WPARAM(value), LPARAM(0)) }?;
+        }
+
+        unsafe { PostMessageW(window, WM_CLOSE, WPARAM(0), LPARAM(0)) }
+    }
+
+    fn run_message_loop() -> Result<()> {
+        let mut message = MSG::default();
+
+        loop {
+            match unsafe { GetMessageW(&mut message, HWND::default(), 0, 0) }.0 {
+                -1 => return Err(Error::from_win32()),
+                0 => return Ok(()),
+                _ => unsafe { DispatchMessageW(&message) },
+            };
+        }
+    }
+
+    fn run(counter: &Counter, instance: HMODULE) -> Result<()> {
+        let thunk = AtlThunk::try_new_with(counter_window_procedure, HWND(ptr::from_ref(counter).cast_mut().cast()))?;
+        let binding = Binding { counter, thunk: &thunk };
+
+        let window = unsafe {
+            CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                CLASS_NAME,
+                PCWSTR::null(),
+                WINDOW_STYLE::default(),
+                0,
+                0,
+                0,
+                0,
+                HWND_MESSAGE,
+                HMENU::default(),
+                instance,
+                Some(ptr::from_ref(&binding).cast()),
+            )
+        }?;
+
+        // The window must be destroyed before the thunk is dropped, otherwise messages may be dispatched to freed
+        // memory. Normally, the window destroys itself when processing `WM_CLOSE`.
+        post_messages(window)
+            .and_then(|()| run_message_loop())
+            .inspect_err(|_| {
+                _ = unsafe { DestroyWindow(window) };
+            })
+    }
+
+    pub fn main() -> Result<()> {
+        let instance = unsafe { GetModuleHandleW(None) }?;
+
+        let class = WNDCLASSW {
+            lpfnWndProc: Some(start_window_procedure),
+            hInstance: instance.into(),
+            lpszClassName: CLASS_NAME,
+            ..WNDCLASSW::default()
+        };
+
+        if unsafe { RegisterClassW(&class) } == 0 {
+            return Err(Error::from_win32());
+        }
+
+        let counter = Counter::default();
+        let result = run(&counter, instance);
+
+        unsafe { UnregisterClassW(CLASS_NAME, instance) }?;
+
+        result?;
+
+        println!(
+            "Received {} messages, sum of values is {}.",
+            counter.messages.get(),
+            counter.sum.get(),
+        );
+
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+fn main() -> windows::core::Result<()> {
+    message_window::main()
+}
+
+#[cfg(not(windows))]
+fn main() {
+    eprintln!("This example only runs on Windows.");
+}
@@ -0,0 +1,104 @@
+//! Creates a standard `BUTTON` control, subclasses it with a [`ContextThunk`](atl_thunk::ContextThunk) carrying a Rust
+//! click counter, sends it some clicks, then restores the original window procedure before the thunk is freed, and
+//! prints the number of clicks.
+
+#[cfg(windows)]
+mod subclass_button {
+    use atl_thunk::ContextThunk;
+    use core::cell::Cell;
+    use core::mem;
+    use windows::core::{w, Result};
+    use windows::Win32::Foundation::{HMODULE, HWND, LPARAM, WPARAM};
+    use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+    #[cfg(target_pointer_width = "64")]
+    use windows::Win32::UI::WindowsAndMessaging::GetWindowLongPtrW;
+    #[cfg(target_pointer_width = "32")]
+    use windows::Win32::UI::WindowsAndMessaging::GetWindowLongW as GetWindowLongPtrW;
+    use windows::Win32::UI::WindowsAndMessaging::{
+        CreateWindowExW, DestroyWindow, GetWindowTextW, SendMessageW, GWLP_WNDPROC, HMENU, HWND_MESSAGE,
+        WINDOW_EX_STYLE, WINDOW_STYLE, WM_LBUTTONDOWN, WM_LBUTTONUP, WNDPROC,
+    };
+
+    fn run(window: HWND) -> Result<()> {
+        let base = unsafe { mem::transmute::<isize, WNDPROC>(GetWindowLongPtrW(window, GWLP_WNDPROC) as isize) };
+        let clicks = Cell::new(0_usize);
+
+        // Clicks are counted, then still forwarded to the original window procedure of the button, so the button keeps
+        // working as usual. Returning `Some` instead would swallow the message.
+        let thunk = ContextThunk::from_handler_forwarding(base, |message: u32, _: WPARAM, _: LPARAM| {
+            if message == WM_LBUTTONDOWN {
+                clicks.set(clicks.get() + 1);
+            }
+
+            None
+        })?;
+
+        // The original window procedure needs the real window handle.
+        thunk.bind_window(window);
+
+        // The thunk must be uninstalled before it is dropped, so it is only installed once nothing can fail before the
+        // matching `uninstall` call.
+        assert!(unsafe { thunk.install() });
+
+        for _ in 0..3 {
+            unsafe { SendMessageW(window, WM_LBUTTONDOWN, WPARAM(0), LPARAM(0)) };
+            unsafe { SendMessageW(window, WM_LBUTTONUP, WPARAM(0), LPARAM(0)) };
+        }
+
+        // Other messages, like the `WM_GETTEXT` message sent by `GetWindowTextW`, are handled by the original window
+        // procedure.
+        let mut text = [0; 16];
+        let length = unsafe { GetWindowTextW(window, &mut text) } as usize;
+
+        assert_eq!(String::from_utf16_lossy(&text[..length]), "Click me");
+
+        assert!(thunk.uninstall());
+
+        // Messages that arrive after uninstalling, like the ones sent when the window is destroyed, go to the original
+        // window procedure directly.
+        drop(thunk);
+
+        println!("The button has been clicked {} times.", clicks.get());
+
+        Ok(())
+    }
+
+    fn create_button(instance: HMODULE) -> Result<HWND> {
+        unsafe {
+            CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                w!("BUTTON"),
+                w!("Click me"),
+                WINDOW_STYLE::default(),
+                0,
+                0,
+                100,
+                30,
+                HWND_MESSAGE,
+                HMENU::default(),
+                instance,
+                None,
+            )
+        }
+    }
+
+    pub fn main() -> Result<()> {
+        let instance = unsafe { GetModuleHandleW(None) }?;
+        let window = create_button(instance)?;
+        let result = run(window);
+
+        unsafe { DestroyWindow(window) }?;
+
+        result
+    }
+}
+
+#[cfg(windows)]
+fn main() -> windows::core::Result<()> {
+    subclass_button::main()
+}
+
+#[cfg(not(windows))]
+fn main() {
+    eprintln!("This example only runs on Windows.");
+}
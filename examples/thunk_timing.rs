@@ -0,0 +1,57 @@
+//! Measures the average time of creating, updating and dropping [`AtlThunk`](atl_thunk::AtlThunk) objects, as a
+//! rough benchmark that needs no benchmarking framework. Run it with `cargo run --release --example thunk_timing`.
+
+#[cfg(windows)]
+mod thunk_timing {
+    use atl_thunk::AtlThunk;
+    use std::hint;
+    use std::time::Instant;
+    use windows::core::Result;
+    use windows::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+
+    const ITERATIONS: u32 = 1_000_000;
+
+    unsafe extern "system" fn callback(first_parameter: HWND, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+        LRESULT(first_parameter.0 as isize + message as isize)
+    }
+
+    /// Runs `f` for [`ITERATIONS`] times, and prints the average time of each run.
+    fn measure(name: &str, mut f: impl FnMut(u32) -> Result<()>) -> Result<()> {
+        // Warm up the allocator of `atlthunk.dll`.
+        (0..ITERATIONS / 10).try_for_each(&mut f)?;
+
+        let start = Instant::now();
+
+        (0..ITERATIONS).try_for_each(&mut f)?;
+
+        println!("{name}: {:?} per iteration", start.elapsed() / ITERATIONS);
+
+        Ok(())
+    }
+
+    pub fn main() -> Result<()> {
+        measure("try_new_with + drop", |i| {
+            drop(hint::black_box(AtlThunk::try_new_with(callback, HWND(i as _))?));
+
+            Ok(())
+        })?;
+
+        let mut thunk = AtlThunk::try_new()?;
+
+        measure("set_data", |i| {
+            hint::black_box(&mut thunk).set_data(callback, HWND(i as _));
+
+            Ok(())
+        })
+    }
+}
+
+#[cfg(windows)]
+fn main() -> windows::core::Result<()> {
+    thunk_timing::main()
+}
+
+#[cfg(not(windows))]
+fn main() {
+    eprintln!("This example only runs on Windows.");
+}
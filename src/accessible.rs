@@ -0,0 +1,103 @@
+//! Routing of `WM_GETOBJECT` to accessibility providers carried by context objects.
+
+use crate::context::{self, ContextThunk};
+use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use crate::sys::Win32::UI::WindowsAndMessaging::{OBJECT_IDENTIFIER, WM_GETOBJECT};
+use crate::{Message, TypedWindowHandler};
+
+/// A [`TypedWindowHandler`] that also provides accessibility objects of the window, see
+/// [`ContextThunk::for_accessible_handler`].
+pub trait AccessibleProvider: TypedWindowHandler {
+    /// Handles a [`WM_GETOBJECT`](https://learn.microsoft.com/en-us/windows/win32/winauto/wm-getobject) message.
+    /// `flags` and `object_id` are the decoded `WPARAM` and `LPARAM` parameters. The result is usually produced by
+    /// [`UiaReturnRawElementProvider`](https://learn.microsoft.com/en-us/windows/win32/api/uiautomationcoreapi/nf-uiautomationcoreapi-uiareturnrawelementprovider)
+    /// or [`LresultFromObject`](https://learn.microsoft.com/en-us/windows/win32/api/oleacc/nf-oleacc-lresultfromobject).
+    /// Returning [`None`] passes the message to the default window procedure, which provides the default accessibility
+    /// objects.
+    fn get_object(&self, flags: u32, object_id: OBJECT_IDENTIFIER) -> Option<LRESULT>;
+}
+
+unsafe extern "system" fn accessible_handler_window_procedure<H>(
+    handler: HWND,
+    message: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> LRESULT
+where
+    H: AccessibleProvider,
+{
+    let handler = handler.0.cast::<H>().cast_const();
+    let header = unsafe { context::context_header(handler) };
+
+    let result = header.dispatch(|| {
+        let handler = unsafe { &*handler };
+
+        if message == WM_GETOBJECT {
+            handler.get_object(w_param.0 as _, OBJECT_IDENTIFIER(l_param.0 as _))
+        } else {
+            handler.handle(Message::decode(message, w_param, l_param))
+        }
+    });
+
+    match result {
+        None => header.default_process(message, w_param, l_param),
+        Some(result) => result,
+    }
+}
+
+impl<H> ContextThunk<H>
+where
+    H: AccessibleProvider,
+{
+    /// Same as [`ContextThunk::for_typed_handler`], but passes `WM_GETOBJECT` messages to
+    /// [`AccessibleProvider::get_object`] instead of [`TypedWindowHandler::handle`].
+    #[track_caller]
+    pub fn for_accessible_handler(handler: H) -> crate::sys::core::Result<Self> {
+        unsafe { Self::try_new_with_procedure(handler, accessible_handler_window_procedure::<H>) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AccessibleProvider;
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use crate::sys::Win32::UI::WindowsAndMessaging::{OBJECT_IDENTIFIER, OBJID_CLIENT, WM_CLOSE, WM_GETOBJECT};
+    use crate::test_support::DEFAULT_RESULT;
+    use crate::{ContextThunk, Message, TypedWindowHandler};
+
+    struct Provider;
+
+    impl TypedWindowHandler for Provider {
+        fn handle(&self, message: Message) -> Option<LRESULT> {
+            (message == Message::Close).then_some(LRESULT(1))
+        }
+    }
+
+    impl AccessibleProvider for Provider {
+        fn get_object(&self, flags: u32, object_id: OBJECT_IDENTIFIER) -> Option<LRESULT> {
+            (object_id == OBJID_CLIENT).then_some(LRESULT(flags as _))
+        }
+    }
+
+    #[test]
+    fn test_context_thunk_for_accessible_handler() {
+        let thunk = ContextThunk::for_accessible_handler(Provider).unwrap();
+        let window_procedure = thunk.as_window_procedure();
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_GETOBJECT, WPARAM(7), LPARAM(OBJID_CLIENT.0 as _)) }.0,
+            7,
+        );
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_CLOSE, WPARAM(0), LPARAM(0)) }.0,
+            1,
+        );
+
+        // Other objects are passed to the default window procedure.
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_GETOBJECT, WPARAM(7), LPARAM(0)) }.0,
+            DEFAULT_RESULT,
+        );
+    }
+}
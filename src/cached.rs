@@ -0,0 +1,146 @@
+//! ATL thunks that cache their window procedures.
+
+use crate::{AtlThunk, FirstParameter, WindowProcedure};
+use core::ffi::c_void;
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+/// An [`AtlThunk`] that caches its window procedure after the first query, so that repeated queries, like the ones
+/// from lazily initialized statics, are cheap.
+///
+/// The window procedure of an ATL thunk only depends on the ATL thunk allocation, not the associated data, so the
+/// cached value stays valid after [`AtlThunk::set_data`].
+///
+/// The cache is a single atomic pointer, so it works without `std`, and reading a cached value takes no lock.
+pub struct CachedAtlThunk {
+    thunk: AtlThunk,
+    /// The cached window procedure, or null if not queried yet.
+    code: AtomicPtr<c_void>,
+}
+
+impl CachedAtlThunk {
+    /// Creates a new [`CachedAtlThunk`] object that takes ownership of `thunk`.
+    pub fn new(thunk: AtlThunk) -> Self {
+        Self {
+            thunk,
+            code: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Returns the wrapped window procedure, see [`AtlThunk::as_window_procedure`]. Only the first call queries the ATL
+    /// thunk, following calls return the cached value. This is thread safe.
+    pub fn code_once(&self) -> WindowProcedure {
+        // Every query returns the same value, so threads racing to fill the cache store the same value, and no other
+        // memory needs to be synchronized with it.
+        let code = self.code.load(Ordering::Relaxed);
+
+        if code.is_null() {
+            let code = self.thunk.as_window_procedure();
+
+            self.code.store(code as *mut c_void, Ordering::Relaxed);
+
+            code
+        } else {
+            unsafe { mem::transmute::<*mut c_void, WindowProcedure>(code) }
+        }
+    }
+
+    /// Returns the address of the wrapped window procedure, which is the cached value of [`CachedAtlThunk::code_once`].
+    /// The address does not change for the lifetime of the ATL thunk, including after [`CachedAtlThunk::set_data`].
+    pub fn code_address(&self) -> usize {
+        self.code_once() as usize
+    }
+
+    /// Creates a new [`CachedAtlThunk`] object with the same window procedure and `first_parameter`, see
+    /// [`AtlThunk::clone_with`]. The new ATL thunk has its own window procedure, which is cached separately.
+    ///
+    /// # Panics
+    ///
+    /// Panics if [`AtlThunk::data`] returns [`None`] for the wrapped object.
+    #[cfg(feature = "metadata")]
+    #[track_caller]
+    pub fn clone_with(&self, first_parameter: impl Into<FirstParameter>) -> crate::sys::core::Result<Self> {
+        self.thunk.clone_with(first_parameter).map(Self::new)
+    }
+
+    /// Returns a reference to the wrapped [`AtlThunk`] object.
+    pub fn thunk(&self) -> &AtlThunk {
+        &self.thunk
+    }
+
+    /// Updates the associated window procedure and data, see [`AtlThunk::set_data`]. A mutable reference to the wrapped
+    /// [`AtlThunk`] object is not provided, because replacing it would invalidate the cached value.
+    pub fn set_data(&mut self, window_procedure: WindowProcedure, first_parameter: impl Into<FirstParameter>) {
+        self.thunk.set_data(window_procedure, first_parameter);
+    }
+
+    /// Returns the wrapped [`AtlThunk`] object.
+    pub fn into_inner(self) -> AtlThunk {
+        self.thunk
+    }
+}
+
+impl From<AtlThunk> for CachedAtlThunk {
+    fn from(value: AtlThunk) -> Self {
+        Self::new(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CachedAtlThunk;
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use crate::AtlThunk;
+
+    #[test]
+    fn test_cached_thunk_code_once() {
+        unsafe extern "system" fn callback(first_parameter: HWND, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+            LRESULT(first_parameter.0 as isize + message as isize)
+        }
+
+        let mut thunk = CachedAtlThunk::new(AtlThunk::try_new_with(callback, HWND(2 as _)).unwrap());
+        let code = thunk.code_once();
+
+        assert_eq!(code as usize, thunk.thunk().as_window_procedure() as usize);
+
+        thunk.set_data(callback, HWND(5 as _));
+
+        assert_eq!(thunk.code_once() as usize, code as usize);
+
+        assert_eq!(
+            unsafe { thunk.code_once()(HWND::default(), 3, WPARAM(0), LPARAM(0)) }.0,
+            8,
+        );
+    }
+
+    #[test]
+    fn test_cached_thunk_code_address() {
+        unsafe extern "system" fn callback(first_parameter: HWND, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+            LRESULT(first_parameter.0 as isize * message as isize)
+        }
+
+        let mut thunk = CachedAtlThunk::from(AtlThunk::try_new_with(callback, HWND(2 as _)).unwrap());
+        let code_address = thunk.code_address();
+
+        assert_eq!(code_address, thunk.thunk().as_window_procedure() as usize);
+
+        for first_parameter in 3..6 {
+            thunk.set_data(callback, HWND(first_parameter as _));
+
+            assert_eq!(thunk.code_address(), code_address);
+        }
+
+        #[cfg(feature = "metadata")]
+        {
+            let clone = thunk.clone_with(HWND(7 as _)).unwrap();
+
+            assert_ne!(clone.code_address(), code_address);
+
+            assert_eq!(
+                unsafe { clone.code_once()(HWND::default(), 3, WPARAM(0), LPARAM(0)) }.0,
+                21,
+            );
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Chains of handlers that each may consume a message or leave it to the next one.
+
+use crate::context::ContextThunk;
+use crate::sys::Win32::Foundation::{LPARAM, LRESULT, WPARAM};
+use crate::OptionalWindowHandler;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+/// A [`ContextThunk`] that dispatches messages through a [`HandlerChain`], see [`HandlerChain::build_thunk`].
+pub type ChainThunk<'a> = ContextThunk<HandlerChain<'a>>;
+
+/// An ordered list of [`OptionalWindowHandler`] objects. A message is passed to the handlers in order until one of
+/// them returns [`Some`], so reusable behaviors can be combined on a single window.
+#[derive(Default)]
+pub struct HandlerChain<'a> {
+    handlers: Vec<Box<dyn OptionalWindowHandler + 'a>>,
+}
+
+impl<'a> HandlerChain<'a> {
+    /// Creates a new empty [`HandlerChain`] object.
+    pub fn new() -> Self {
+        Self { handlers: Vec::new() }
+    }
+
+    /// Appends `handler` to the end of the chain, so it only receives messages that previous handlers did not consume.
+    #[must_use]
+    pub fn then(mut self, handler: impl OptionalWindowHandler + 'a) -> Self {
+        self.push(handler);
+
+        self
+    }
+
+    /// Same as [`HandlerChain::then`], but takes the chain by mutable reference.
+    pub fn push(&mut self, handler: impl OptionalWindowHandler + 'a) {
+        self.handlers.push(Box::new(handler));
+    }
+
+    /// Returns the number of handlers in the chain.
+    pub fn len(&self) -> usize {
+        self.handlers.len()
+    }
+
+    /// Returns whether the chain has no handlers.
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+
+    /// Creates a new [`ChainThunk`] object that owns the chain. Messages not consumed by any handler are passed to
+    /// [`DefWindowProcW`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-defwindowprocw), which
+    /// needs the window bound with [`ContextThunk::bind_window`].
+    #[track_caller]
+    pub fn build_thunk(self) -> crate::sys::core::Result<ChainThunk<'a>> {
+        ContextThunk::for_optional_handler(self)
+    }
+}
+
+impl OptionalWindowHandler for HandlerChain<'_> {
+    fn handle(&self, message: u32, w_param: WPARAM, l_param: LPARAM) -> Option<LRESULT> {
+        self.handlers
+            .iter()
+            .find_map(|handler| handler.handle(message, w_param, l_param))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HandlerChain;
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use crate::test_support::DEFAULT_RESULT;
+    use core::cell::Cell;
+
+    #[test]
+    fn test_handler_chain_build_thunk() {
+        let visited = Cell::new(0);
+
+        let thunk = HandlerChain::new()
+            .then(|message: u32, _: WPARAM, _: LPARAM| {
+                visited.set(visited.get() + 1);
+
+                (message == 1).then_some(LRESULT(10))
+            })
+            .then(|message: u32, _: WPARAM, _: LPARAM| {
+                visited.set(visited.get() + 10);
+
+                (message <= 2).then_some(LRESULT(20))
+            })
+            .build_thunk()
+            .unwrap();
+
+        let window_procedure = thunk.as_window_procedure();
+
+        assert_eq!(thunk.context().len(), 2);
+
+        // The first handler consumes the message, so the second one is not called.
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), 1, WPARAM(0), LPARAM(0)) }.0,
+            10,
+        );
+
+        assert_eq!(visited.get(), 1);
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), 2, WPARAM(0), LPARAM(0)) }.0,
+            20,
+        );
+
+        assert_eq!(visited.get(), 12);
+
+        // Messages not consumed by any handler are passed to the default window procedure.
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), 3, WPARAM(0), LPARAM(0)) }.0,
+            DEFAULT_RESULT,
+        );
+
+        assert_eq!(visited.get(), 23);
+    }
+}
@@ -0,0 +1,317 @@
+//! Window procedures that forward messages to channels, for handling messages in asynchronous code.
+
+use crate::context::{self, ContextHeader, ContextThunk};
+use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use crate::{MessageFilter, WindowProcedure};
+use std::sync::mpsc::{self, Sender, SyncSender};
+use std::time::Duration;
+
+/// A window message sent by a thunk created by [`ContextThunk::from_sender`].
+///
+/// Messages are received after the window procedure has returned, unless a reply is requested, so pointers passed in
+/// `w_param` or `l_param` may no longer be valid when the message is received.
+#[derive(Debug)]
+pub struct WindowMessage {
+    /// The window bound with [`ContextThunk::bind_window`], or a null handle if no window is bound.
+    pub window: HWND,
+    /// The message.
+    pub message: u32,
+    /// The `WPARAM` argument.
+    pub w_param: WPARAM,
+    /// The `LPARAM` argument.
+    pub l_param: LPARAM,
+    reply: Option<SyncSender<LRESULT>>,
+}
+
+impl WindowMessage {
+    /// Returns whether the window procedure is waiting for a result sent with [`WindowMessage::reply`].
+    pub fn needs_reply(&self) -> bool {
+        self.reply.is_some()
+    }
+
+    /// Sends `result` back to the waiting window procedure. Returns whether the result is received, which is not the
+    /// case if the message does not need a reply, or if the window procedure has stopped waiting.
+    pub fn reply(self, result: LRESULT) -> bool {
+        self.reply.is_some_and(|reply| reply.try_send(result).is_ok())
+    }
+
+    /// Calls `window_procedure` with the window and the arguments of the message, which can be used to replay
+    /// received messages, like passing them to the default window procedure. The result is not sent back, use
+    /// [`WindowMessage::reply`] for that.
+    ///
+    /// # Safety
+    ///
+    /// The same as calling `window_procedure` directly. Pointers passed in `w_param` or `l_param` may no longer be
+    /// valid, see [`WindowMessage`].
+    pub unsafe fn dispatch_to(&self, window_procedure: WindowProcedure) -> LRESULT {
+        unsafe { window_procedure(self.window, self.message, self.w_param, self.l_param) }
+    }
+}
+
+impl From<(HWND, u32, WPARAM, LPARAM)> for WindowMessage {
+    /// Creates a [`WindowMessage`] object from the window procedure arguments, which does not need a reply.
+    fn from((window, message, w_param, l_param): (HWND, u32, WPARAM, LPARAM)) -> Self {
+        Self {
+            window,
+            message,
+            w_param,
+            l_param,
+            reply: None,
+        }
+    }
+}
+
+impl From<WindowMessage> for (HWND, u32, WPARAM, LPARAM) {
+    /// Returns the window procedure arguments of the message. If the message needs a reply, the waiting window
+    /// procedure stops waiting and returns its default result.
+    fn from(value: WindowMessage) -> Self {
+        (value.window, value.message, value.w_param, value.l_param)
+    }
+}
+
+// Window handles and message parameters are plain values, which can be used from any thread.
+unsafe impl Send for WindowMessage {}
+
+/// Context object that forwards messages to a channel, see [`ContextThunk::from_sender`].
+pub struct MessageSender {
+    sender: Sender<WindowMessage>,
+    /// The result returned for messages that are not replied, or [`None`] for passing them to the default window
+    /// procedure.
+    default_result: Option<LRESULT>,
+    reply: Option<(MessageFilter, Duration)>,
+}
+
+impl MessageSender {
+    fn send(&self, header: &ContextHeader, message: u32, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+        let mut window_message = WindowMessage {
+            window: header.window(),
+            message,
+            w_param,
+            l_param,
+            reply: None,
+        };
+
+        match self.reply {
+            Some((filter, timeout)) if filter.contains(message) => {
+                let (reply_sender, reply_receiver) = mpsc::sync_channel(1);
+
+                window_message.reply = Some(reply_sender);
+
+                if self.sender.send(window_message).is_ok() {
+                    if let Ok(result) = reply_receiver.recv_timeout(timeout) {
+                        return result;
+                    }
+                }
+            }
+            _ => _ = self.sender.send(window_message),
+        }
+
+        match self.default_result {
+            None => header.default_process(message, w_param, l_param),
+            Some(default_result) => default_result,
+        }
+    }
+}
+
+unsafe extern "system" fn sender_window_procedure(
+    sender: HWND,
+    message: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> LRESULT {
+    let sender = sender.0.cast::<MessageSender>().cast_const();
+    let header = unsafe { context::context_header(sender) };
+
+    header.dispatch(|| unsafe { &*sender }.send(header, message, w_param, l_param))
+}
+
+impl ContextThunk<MessageSender> {
+    /// Creates a new [`ContextThunk`] object that sends every message to `sender` as a [`WindowMessage`], and returns
+    /// `default_result` to the operating system right away. Messages are still sent when the receiver is dropped,
+    /// in which case they are discarded.
+    #[track_caller]
+    pub fn from_sender(sender: Sender<WindowMessage>, default_result: LRESULT) -> crate::sys::core::Result<Self> {
+        Self::from_message_sender(MessageSender {
+            sender,
+            default_result: Some(default_result),
+            reply: None,
+        })
+    }
+
+    /// Same as [`ContextThunk::from_sender`], but for messages in `reply_messages`, the window procedure blocks until
+    /// the receiver replies with [`WindowMessage::reply`], or `timeout` elapses, in which case `default_result` is
+    /// returned.
+    ///
+    /// The window procedure runs on the thread of the window, so the receiver must not wait for that thread while
+    /// handling messages that need a reply.
+    #[track_caller]
+    pub fn from_sender_with_reply(
+        sender: Sender<WindowMessage>,
+        default_result: LRESULT,
+        reply_messages: MessageFilter,
+        timeout: Duration,
+    ) -> crate::sys::core::Result<Self> {
+        Self::from_message_sender(MessageSender {
+            sender,
+            default_result: Some(default_result),
+            reply: Some((reply_messages, timeout)),
+        })
+    }
+
+    /// Same as [`ContextThunk::from_sender_with_reply`], but messages that are not replied in time, and messages not in
+    /// `reply_messages`, are passed to the default window procedure, see [`ContextThunk::set_char_set`]. This way, a
+    /// stuck receiver delays the window by at most `timeout` for each message, and the window keeps its default
+    /// behavior meanwhile.
+    ///
+    /// Messages that are not waited for are still sent to the receiver, so the receiver must not handle them again
+    /// with the default window procedure.
+    #[track_caller]
+    pub fn from_sender_with_default_procedure(
+        sender: Sender<WindowMessage>,
+        reply_messages: MessageFilter,
+        timeout: Duration,
+    ) -> crate::sys::core::Result<Self> {
+        Self::from_message_sender(MessageSender {
+            sender,
+            default_result: None,
+            reply: Some((reply_messages, timeout)),
+        })
+    }
+
+    #[track_caller]
+    fn from_message_sender(sender: MessageSender) -> crate::sys::core::Result<Self> {
+        unsafe { Self::try_new_with_procedure(sender, sender_window_procedure) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use crate::sys::Win32::UI::WindowsAndMessaging::{WM_GETMINMAXINFO, WM_USER};
+    use crate::test_support::DEFAULT_RESULT;
+    use crate::{ContextThunk, MessageFilter, WindowMessage};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_context_thunk_from_sender() {
+        let (sender, receiver) = mpsc::channel();
+        let thunk = ContextThunk::from_sender(sender, LRESULT(-1)).unwrap();
+
+        thunk.bind_window(HWND(2 as _));
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), WM_USER, WPARAM(3), LPARAM(5)) }.0,
+            -1,
+        );
+
+        let message = receiver.recv().unwrap();
+
+        assert_eq!(
+            (message.window, message.message, message.w_param, message.l_param),
+            (HWND(2 as _), WM_USER, WPARAM(3), LPARAM(5)),
+        );
+
+        assert!(!message.needs_reply());
+        assert!(!message.reply(LRESULT(0)));
+    }
+
+    #[test]
+    fn test_window_message_conversions() {
+        unsafe extern "system" fn callback(window: HWND, message: u32, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+            LRESULT(window.0 as isize + message as isize * w_param.0 as isize * l_param.0)
+        }
+
+        let arguments = (HWND(2 as _), WM_USER, WPARAM(3), LPARAM(5));
+        let message = WindowMessage::from(arguments);
+
+        assert!(!message.needs_reply());
+        assert_eq!(unsafe { message.dispatch_to(callback) }.0, 2 + WM_USER as isize * 15);
+        assert_eq!(<(HWND, u32, WPARAM, LPARAM)>::from(message), arguments);
+    }
+
+    #[test]
+    fn test_context_thunk_from_sender_with_reply() {
+        static REPLY_MESSAGES: MessageFilter = MessageFilter::new(&[WM_USER]);
+
+        let (sender, receiver) = mpsc::channel();
+
+        let thunk =
+            ContextThunk::from_sender_with_reply(sender, LRESULT(-1), REPLY_MESSAGES, Duration::from_secs(60)).unwrap();
+
+        let handler = thread::spawn(move || {
+            for message in receiver {
+                if message.needs_reply() {
+                    let result = LRESULT(message.w_param.0 as _);
+
+                    assert!(message.reply(result));
+                }
+            }
+        });
+
+        let window_procedure = thunk.as_window_procedure();
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_USER, WPARAM(7), LPARAM(0)) }.0,
+            7,
+        );
+
+        // Other messages are not waited for.
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_GETMINMAXINFO, WPARAM(7), LPARAM(0)) }.0,
+            -1,
+        );
+
+        // Dropping the thunk drops the sender, which stops the handler thread.
+        drop(thunk);
+
+        handler.join().unwrap();
+    }
+
+    #[test]
+    fn test_context_thunk_from_sender_with_default_procedure() {
+        static REPLY_MESSAGES: MessageFilter = MessageFilter::new(&[WM_USER]);
+
+        let (sender, receiver) = mpsc::channel();
+
+        let thunk = ContextThunk::from_sender_with_default_procedure(sender, REPLY_MESSAGES, Duration::from_millis(10))
+            .unwrap();
+
+        let window_procedure = thunk.as_window_procedure();
+
+        // The receiver never replies, so the message is passed to the default window procedure after the timeout.
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_USER, WPARAM(7), LPARAM(0)) }.0,
+            DEFAULT_RESULT,
+        );
+
+        // Other messages are passed to the default window procedure without waiting.
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_USER + 1, WPARAM(7), LPARAM(0)) }.0,
+            DEFAULT_RESULT,
+        );
+
+        let messages = receiver.try_iter().collect::<std::vec::Vec<_>>();
+
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].needs_reply());
+
+        // The window procedure has stopped waiting.
+        assert!(!messages.into_iter().next().unwrap().reply(LRESULT(1)));
+    }
+
+    #[test]
+    fn test_message_sender_while_mutably_borrowed() {
+        crate::test_support::assert_aborts("channel::tests::test_message_sender_while_mutably_borrowed", || {
+            use crate::sys::Win32::UI::WindowsAndMessaging::WM_SETTEXT;
+
+            let (sender, _receiver) = mpsc::channel();
+            let mut thunk = ContextThunk::from_sender(sender, LRESULT(-1)).unwrap();
+            let window_procedure = thunk.as_window_procedure();
+            let _context = thunk.get_mut();
+
+            unsafe { window_procedure(HWND::default(), WM_SETTEXT, WPARAM(0), LPARAM(0)) };
+        });
+    }
+}
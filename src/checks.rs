@@ -0,0 +1,196 @@
+//! Debug checks for lifecycle bugs, enabled by the `debug-checks` feature in debug builds.
+
+use crate::context::CharSet;
+use crate::subclass;
+use crate::sys::Win32::Foundation::HWND;
+use crate::sys::Win32::System::Diagnostics::Debug::OutputDebugStringA;
+use crate::sys::Win32::System::Memory::AtlThunkData_t;
+use crate::sys::Win32::System::Threading::GetCurrentThreadId;
+use crate::sys::Win32::UI::WindowsAndMessaging::IsWindow;
+use crate::{FirstParameter, WindowProcedure};
+use alloc::collections::BTreeSet;
+use core::cell::UnsafeCell;
+use core::hint;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Panics if `window_procedure` is still the window procedure of `window`, which would dangle once the ATL thunk is
+/// freed. Destroyed windows report no window procedure, so they pass the check.
+pub(crate) fn assert_not_installed(window: HWND, ansi: bool, window_procedure: WindowProcedure) {
+    if !window.is_invalid() {
+        assert_ne!(
+            subclass::current_window_procedure(window, if ansi { CharSet::Ansi } else { CharSet::Wide }),
+            window_procedure as usize,
+            "the ATL thunk is freed while it is still the window procedure of the bound window",
+        );
+    }
+}
+
+/// Reports through
+/// [`OutputDebugStringA`](https://learn.microsoft.com/en-us/windows/win32/api/debugapi/nf-debugapi-outputdebugstringa)
+/// if `first_parameter` is a valid window handle, which is usually passed by mistake, expecting the window procedure to
+/// receive the window it is called with. Returns whether the warning is reported. This is only a heuristic, since a
+/// context pointer may happen to have the same value as a window handle.
+pub(crate) fn warn_if_window(first_parameter: FirstParameter) -> bool {
+    let is_window = first_parameter.0 != 0 && unsafe { IsWindow(HWND(first_parameter.0 as _)) }.as_bool();
+
+    if is_window {
+        unsafe {
+            OutputDebugStringA(crate::sys::core::s!(
+                "atl-thunk: the first parameter is a window handle, which replaces the window handle the window \
+                 procedure is called with, rather than being the window that receives messages\n"
+            ));
+        }
+    }
+
+    is_window
+}
+
+/// Returns the identifier of the current thread, which [`ThunkToken`](crate::ThunkToken) objects record when they are
+/// packed.
+pub(crate) fn current_thread_id() -> u32 {
+    unsafe { GetCurrentThreadId() }
+}
+
+/// Reports through
+/// [`OutputDebugStringA`](https://learn.microsoft.com/en-us/windows/win32/api/debugapi/nf-debugapi-outputdebugstringa)
+/// if a [`ThunkToken`](crate::ThunkToken) is claimed on the thread `source_thread_id` that packed it, which transfers
+/// nothing and usually means the token is claimed on the wrong side of the handoff. Returns whether the warning is
+/// reported.
+pub(crate) fn warn_if_claimed_on_source_thread(source_thread_id: u32) -> bool {
+    let same_thread = current_thread_id() == source_thread_id;
+
+    if same_thread {
+        unsafe {
+            OutputDebugStringA(crate::sys::core::s!(
+                "atl-thunk: a `ThunkToken` is claimed on the thread that created it, so no ownership is transferred\n"
+            ));
+        }
+    }
+
+    same_thread
+}
+
+/// Raw ATL thunk pointers currently owned by [`AtlThunk`](crate::AtlThunk) objects, protected by a spin lock so it
+/// works without `std`.
+struct LiveThunks {
+    locked: AtomicBool,
+    addresses: UnsafeCell<BTreeSet<usize>>,
+}
+
+unsafe impl Sync for LiveThunks {}
+
+impl LiveThunks {
+    fn update<R>(&self, f: impl FnOnce(&mut BTreeSet<usize>) -> R) -> R {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            hint::spin_loop();
+        }
+
+        // `BTreeSet` operations used here only panic on allocation failures, which abort anyway.
+        let result = f(unsafe { &mut *self.addresses.get() });
+
+        self.locked.store(false, Ordering::Release);
+
+        result
+    }
+}
+
+static LIVE_THUNKS: LiveThunks = LiveThunks {
+    locked: AtomicBool::new(false),
+    addresses: UnsafeCell::new(BTreeSet::new()),
+};
+
+/// Records that an [`AtlThunk`](crate::AtlThunk) object takes the ownership of `raw_thunk_ptr`. Panics if another
+/// object already owns it, which would free it twice.
+#[track_caller]
+pub(crate) fn track_thunk(raw_thunk_ptr: NonNull<AtlThunkData_t>) {
+    assert!(
+        LIVE_THUNKS.update(|addresses| addresses.insert(raw_thunk_ptr.as_ptr() as usize)),
+        "the ATL thunk {raw_thunk_ptr:?} is already owned by another `AtlThunk` object",
+    );
+}
+
+/// Records that an [`AtlThunk`](crate::AtlThunk) object gives up the ownership of `raw_thunk_ptr`. Panics if no object
+/// owns it, which means it has been freed or released already.
+#[track_caller]
+pub(crate) fn untrack_thunk(raw_thunk_ptr: NonNull<AtlThunkData_t>) {
+    assert!(
+        LIVE_THUNKS.update(|addresses| addresses.remove(&(raw_thunk_ptr.as_ptr() as usize))),
+        "the ATL thunk {raw_thunk_ptr:?} is not owned by any `AtlThunk` object, it may have been freed already",
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sys::core::w;
+    use crate::sys::Win32::UI::WindowsAndMessaging::{
+        CreateWindowExW, DestroyWindow, HWND_MESSAGE, WINDOW_EX_STYLE, WINDOW_STYLE,
+    };
+    use crate::{AtlThunk, FirstParameter};
+    use core::mem::ManuallyDrop;
+    use core::ptr;
+
+    #[test]
+    fn test_thunk_tracking() {
+        let thunk = AtlThunk::try_new().unwrap();
+        let raw_thunk_ptr = thunk.into_raw();
+        let thunk = unsafe { AtlThunk::from_raw(raw_thunk_ptr) };
+
+        assert_eq!(thunk.into_raw(), raw_thunk_ptr);
+
+        drop(unsafe { AtlThunk::from_raw(raw_thunk_ptr) });
+    }
+
+    #[test]
+    #[should_panic(expected = "already owned")]
+    fn test_thunk_from_raw_alias() {
+        // The original object is not dropped, so the ATL thunk is not freed twice if the check fails.
+        let thunk = ManuallyDrop::new(AtlThunk::try_new().unwrap());
+
+        _ = ManuallyDrop::new(unsafe { AtlThunk::from_raw(thunk.raw_thunk_ptr) });
+    }
+
+    #[test]
+    #[should_panic(expected = "not owned")]
+    fn test_thunk_double_free() {
+        let thunk = AtlThunk::try_new().unwrap();
+        let copy = unsafe { ptr::read(&thunk) };
+
+        drop(thunk);
+
+        // The check panics before the ATL thunk is freed again.
+        drop(copy);
+    }
+
+    #[test]
+    fn test_warn_if_window() {
+        let window = unsafe {
+            CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                w!("STATIC"),
+                None,
+                WINDOW_STYLE::default(),
+                0,
+                0,
+                0,
+                0,
+                HWND_MESSAGE,
+                None,
+                None,
+                None,
+            )
+        }
+        .unwrap();
+
+        assert!(super::warn_if_window(window.into()));
+        assert!(!super::warn_if_window(FirstParameter::default()));
+
+        unsafe { DestroyWindow(window) }.unwrap();
+
+        assert!(!super::warn_if_window(window.into()));
+    }
+}
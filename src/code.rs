@@ -0,0 +1,68 @@
+//! Borrowed window procedures of ATL thunks.
+
+use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use crate::{AtlThunk, WindowProcedure};
+use core::marker::PhantomData;
+use core::ops::Deref;
+
+/// The window procedure of an [`AtlThunk`], borrowing the originating object. It dereferences to the window procedure,
+/// so it can be called directly, see [`AtlThunk::code`].
+#[derive(Clone, Copy, Debug)]
+pub struct ThunkCode<'a> {
+    window_procedure: WindowProcedure,
+    thunk: PhantomData<&'a AtlThunk>,
+}
+
+impl ThunkCode<'_> {
+    /// Calls the window procedure.
+    ///
+    /// # Safety
+    ///
+    /// The associated data of the originating [`AtlThunk`] object must have been set, and calling the associated
+    /// window procedure with the associated first parameter and specified arguments must be safe.
+    #[inline(always)]
+    pub unsafe fn call(&self, window: HWND, message: u32, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+        unsafe { (self.window_procedure)(window, message, w_param, l_param) }
+    }
+}
+
+impl Deref for ThunkCode<'_> {
+    type Target = WindowProcedure;
+
+    fn deref(&self) -> &Self::Target {
+        &self.window_procedure
+    }
+}
+
+impl AtlThunk {
+    /// Returns the wrapped window procedure as a [`ThunkCode`] object, which borrows the [`AtlThunk`] object so that
+    /// the borrow checker rejects uses after the [`AtlThunk`] object is dropped. The same validity requirements as the
+    /// ones of [`AtlThunk::as_window_procedure`] apply.
+    #[inline(always)]
+    pub fn code(&self) -> ThunkCode<'_> {
+        ThunkCode {
+            window_procedure: self.as_window_procedure(),
+            thunk: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use crate::AtlThunk;
+
+    #[test]
+    fn test_thunk_code() {
+        unsafe extern "system" fn callback(first_parameter: HWND, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+            LRESULT(first_parameter.0 as isize + message as isize)
+        }
+
+        let thunk = AtlThunk::try_new_with(callback, HWND(2 as _)).unwrap();
+        let code = thunk.code();
+
+        assert_eq!(*code as usize, thunk.as_window_procedure() as usize);
+        assert_eq!(unsafe { code(HWND::default(), 3, WPARAM(0), LPARAM(0)) }.0, 5);
+        assert_eq!(unsafe { code.call(HWND::default(), 7, WPARAM(0), LPARAM(0)) }.0, 9);
+    }
+}
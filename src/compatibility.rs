@@ -0,0 +1,79 @@
+//! Runtime check that the deployed `atlthunk.dll` behaves as expected, only performed in debug builds.
+
+use crate::sys::Win32::Foundation::{E_UNEXPECTED, HWND, LPARAM, LRESULT, WPARAM};
+use core::ffi::c_void;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+const UNCHECKED: u8 = 0;
+const COMPATIBLE: u8 = 1;
+const INCOMPATIBLE: u8 = 2;
+
+static STATE: AtomicU8 = AtomicU8::new(UNCHECKED);
+
+const PROBE_VALUE: usize = 0x5A5A;
+
+unsafe extern "system" fn probe_window_procedure(first_parameter: HWND, _: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+    LRESULT(first_parameter.0 as _)
+}
+
+/// Performs a round trip through all ATL thunk functions. Returns [`None`] if no ATL thunk can be allocated.
+fn probe() -> Option<bool> {
+    let thunk = unsafe { crate::AtlThunk_AllocateData() };
+
+    if thunk.is_null() {
+        return None;
+    }
+
+    let compatible = unsafe {
+        crate::AtlThunk_InitData(thunk, probe_window_procedure as *mut c_void, PROBE_VALUE);
+
+        let compatible = crate::AtlThunk_DataToCode(thunk).is_some_and(|window_procedure| {
+            window_procedure(HWND::default(), 0, WPARAM(0), LPARAM(0)).0 == PROBE_VALUE as isize
+        });
+
+        crate::AtlThunk_FreeData(thunk);
+
+        compatible
+    };
+
+    Some(compatible)
+}
+
+fn incompatible_error() -> crate::sys::core::Error {
+    crate::sys::core::Error::new(
+        E_UNEXPECTED,
+        "`atlthunk.dll` does not work as expected, it may not match the Windows SDK version used by the `windows` \
+         crate",
+    )
+}
+
+/// Checks that ATL thunks work correctly, the check is performed once and the result is cached.
+pub(crate) fn check() -> crate::sys::core::Result<()> {
+    match STATE.load(Ordering::Acquire) {
+        COMPATIBLE => Ok(()),
+        INCOMPATIBLE => Err(incompatible_error()),
+        _ => match probe() {
+            // Allocation failures are reported by the caller.
+            None => Ok(()),
+            Some(true) => {
+                STATE.store(COMPATIBLE, Ordering::Release);
+
+                Ok(())
+            }
+            Some(false) => {
+                STATE.store(INCOMPATIBLE, Ordering::Release);
+
+                Err(incompatible_error())
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_compatibility_check() {
+        assert!(super::check().is_ok());
+        assert!(super::check().is_ok());
+    }
+}
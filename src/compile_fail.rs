@@ -0,0 +1,159 @@
+//! Checks that misuses rejected by the type system keep failing to compile, as rustdoc `compile_fail` tests.
+
+/// [`UninitAtlThunk`](crate::UninitAtlThunk) objects can not be used as window procedures before initialized:
+///
+/// ```compile_fail,E0599
+/// let thunk = atl_thunk::UninitAtlThunk::try_new().unwrap();
+///
+/// let _ = thunk.as_window_procedure();
+/// ```
+pub struct UninitThunkAsWindowProcedure;
+
+/// [`ScopedThunk`](crate::ScopedThunk) objects can not outlive the borrowed handler:
+///
+/// ```compile_fail,E0515
+/// use atl_thunk::windows::{LPARAM, LRESULT, WPARAM};
+/// use atl_thunk::{AtlThunk, ScopedThunk};
+///
+/// fn make() -> ScopedThunk<'static> {
+///     let handler = |_: u32, _: WPARAM, _: LPARAM| LRESULT(0);
+///
+///     AtlThunk::scoped_handler(&handler).unwrap()
+/// }
+/// ```
+pub struct ScopedThunkOutlivesHandler;
+
+/// [`BoundProc`](crate::BoundProc) objects can not outlive the thunk they are borrowed from:
+///
+/// ```compile_fail,E0597
+/// use atl_thunk::windows::{LPARAM, LRESULT, WPARAM};
+/// use atl_thunk::{AtlThunk, BoundProc};
+///
+/// fn store(_: BoundProc<'static>) {}
+///
+/// let handler = |_: u32, _: WPARAM, _: LPARAM| LRESULT(0);
+/// let thunk = AtlThunk::scoped_handler(&handler).unwrap();
+///
+/// store(thunk.bound_procedure());
+/// ```
+pub struct BoundProcOutlivesThunk;
+
+/// [`ScopedThunk`](crate::ScopedThunk) objects created from a [`ClosureHolder`](crate::ClosureHolder) can not outlive
+/// the holder:
+///
+/// ```compile_fail,E0515
+/// use atl_thunk::windows::{HWND, LPARAM, LRESULT, WPARAM};
+/// use atl_thunk::{AtlThunk, ClosureHolder, ScopedThunk};
+///
+/// fn make() -> ScopedThunk<'static> {
+///     let mut closure = |_: HWND, _: u32, _: WPARAM, _: LPARAM| LRESULT(0);
+///     let holder = ClosureHolder::new(&mut closure);
+///
+///     AtlThunk::borrowed_closure(&holder).unwrap()
+/// }
+/// ```
+pub struct BorrowedClosureOutlivesHolder;
+
+/// [`ContextRefMut`](crate::ContextRefMut) guards keep the context object exclusively borrowed:
+///
+/// ```compile_fail,E0502
+/// use atl_thunk::windows::{LPARAM, LRESULT, WPARAM};
+/// use atl_thunk::ContextThunk;
+///
+/// let mut thunk = ContextThunk::for_handler(|_: u32, _: WPARAM, _: LPARAM| LRESULT(0)).unwrap();
+/// let guard = thunk.get_mut();
+///
+/// thunk.context();
+///
+/// drop(guard);
+/// ```
+#[cfg(feature = "alloc")]
+pub struct ContextRefMutAliased;
+
+/// [`SharedContextThunk`](crate::SharedContextThunk) objects can not be sent to other threads:
+///
+/// ```compile_fail,E0277
+/// use atl_thunk::windows::{LPARAM, LRESULT, WPARAM};
+/// use atl_thunk::ContextThunk;
+///
+/// let thunk = ContextThunk::for_handler(|_: u32, _: WPARAM, _: LPARAM| LRESULT(0))
+///     .unwrap()
+///     .into_shared();
+///
+/// std::thread::spawn(move || drop(thunk));
+/// ```
+#[cfg(feature = "std")]
+pub struct SharedContextThunkSent;
+
+/// [`ContextThunk`](crate::ContextThunk) objects with [`Rebindable`](crate::Rebindable) context objects can not be
+/// sent to other threads if the handler can not:
+///
+/// ```compile_fail,E0277
+/// use atl_thunk::windows::{LPARAM, LRESULT, WPARAM};
+/// use atl_thunk::ContextThunk;
+/// use std::rc::Rc;
+///
+/// let counter = Rc::new(());
+///
+/// let thunk = ContextThunk::for_rebindable_handler(move |_: u32, _: WPARAM, _: LPARAM| {
+///     let _ = &counter;
+///
+///     LRESULT(0)
+/// })
+/// .unwrap();
+///
+/// std::thread::spawn(move || drop(thunk));
+/// ```
+#[cfg(feature = "std")]
+pub struct RebindableThunkSent;
+
+/// Swapping the window procedure and the first parameter of [`AtlThunk::try_new_with`](crate::AtlThunk::try_new_with)
+/// is rejected for every type accepted as [`FirstParameter`](crate::FirstParameter), because window procedures are not
+/// first parameters:
+///
+/// ```compile_fail,E0308
+/// use atl_thunk::windows::{HWND, LPARAM, LRESULT, WPARAM};
+/// use atl_thunk::AtlThunk;
+///
+/// unsafe extern "system" fn callback(_: HWND, _: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+///     LRESULT(0)
+/// }
+///
+/// let _ = AtlThunk::try_new_with(HWND(2 as _), callback);
+/// ```
+///
+/// ```compile_fail,E0308
+/// use atl_thunk::windows::{HWND, LPARAM, LRESULT, WPARAM};
+/// use atl_thunk::AtlThunk;
+///
+/// unsafe extern "system" fn callback(_: HWND, _: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+///     LRESULT(0)
+/// }
+///
+/// let _ = AtlThunk::try_new_with(2_usize, callback);
+/// ```
+///
+/// ```compile_fail,E0308
+/// use atl_thunk::windows::{HWND, LPARAM, LRESULT, WPARAM};
+/// use atl_thunk::AtlThunk;
+///
+/// unsafe extern "system" fn callback(_: HWND, _: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+///     LRESULT(0)
+/// }
+///
+/// let _ = AtlThunk::try_new_with(core::ptr::null::<u8>(), callback);
+/// ```
+///
+/// The same holds for [`AtlThunk::set_data`](crate::AtlThunk::set_data):
+///
+/// ```compile_fail,E0308
+/// use atl_thunk::windows::{HWND, LPARAM, LRESULT, WPARAM};
+/// use atl_thunk::AtlThunk;
+///
+/// unsafe extern "system" fn callback(_: HWND, _: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+///     LRESULT(0)
+/// }
+///
+/// AtlThunk::try_new().unwrap().set_data(HWND(2 as _), callback);
+/// ```
+pub struct SwappedProcedureAndFirstParameter;
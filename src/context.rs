@@ -0,0 +1,1763 @@
+//! ATL thunks that own their associated context objects.
+
+use crate::handler::{OptionalWindowHandler, WindowHandler};
+use crate::sys::Win32::Foundation::{E_OUTOFMEMORY, HWND, LPARAM, LRESULT, WPARAM};
+use crate::sys::Win32::System::Memory::AtlThunkData_t;
+use crate::sys::Win32::UI::WindowsAndMessaging::{DefWindowProcA, WM_NCDESTROY};
+#[cfg(feature = "std")]
+use crate::unwind::PanicHook;
+#[cfg(feature = "std")]
+use crate::PanicPolicy;
+use crate::{AtlThunk, FirstParameter, UninitAtlThunk, WindowProcedure};
+use alloc::alloc::Layout;
+use alloc::boxed::Box;
+#[cfg(debug_assertions)]
+use core::any;
+use core::ffi::c_void;
+use core::fmt::{self, Display, Formatter};
+use core::marker::PhantomData;
+use core::mem::{self, ManuallyDrop};
+use core::ops::{Deref, DerefMut};
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+/// Character set of a window, which decides the default window procedure used by [`ContextThunk`] objects.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CharSet {
+    /// The window is created with a Unicode window class, default messages are processed by
+    /// [`DefWindowProcW`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-defwindowprocw).
+    #[default]
+    Wide,
+    /// The window is created with an ANSI window class, default messages are processed by
+    /// [`DefWindowProcA`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-defwindowproca).
+    Ansi,
+}
+
+/// A context object of any type, which is implemented for all types. [`ContextThunk::erase`] converts to a
+/// [`ContextThunk`] object whose context object is an [`AnyContext`] trait object, so thunks of different context
+/// types can be stored together, and [`ContextThunk::downcast`] converts back.
+pub trait AnyContext {}
+
+impl<T: ?Sized> AnyContext for T {}
+
+/// States stored in front of every context object, which are available to window procedures through
+/// [`context_header`].
+pub(crate) struct ContextHeader {
+    raw_thunk_ptr: NonNull<AtlThunkData_t>,
+    window: AtomicPtr<c_void>,
+    ansi: AtomicBool,
+    dispatch_depth: AtomicUsize,
+    /// Number of dispatches started so far, checked by [`ContextThunk::set_data_checked`].
+    generation: AtomicUsize,
+    pending_procedure: AtomicPtr<c_void>,
+    pending_first_parameter: AtomicUsize,
+    /// The associated data last set on the ATL thunk, which is restored by [`ContextThunk::reallocate`].
+    active_procedure: AtomicPtr<c_void>,
+    active_first_parameter: AtomicUsize,
+    poisoned: AtomicBool,
+    /// The window procedure the thunk is created with, which is called by [`ncdestroy_window_procedure`].
+    procedure: WindowProcedure,
+    context_dropped: AtomicBool,
+    drop_requested: AtomicBool,
+    /// Whether the bound window has received `WM_NCDESTROY`, only tracked for thunks created by
+    /// [`ContextThunk::for_window_handler`].
+    #[cfg(windows)]
+    window_destroyed: AtomicBool,
+    /// Whether the context object is shared by [`ContextThunk::into_shared`], in which case
+    /// [`ncdestroy_window_procedure`] keeps it alive, because other threads may still be reading it.
+    context_shared: AtomicBool,
+    /// Whether a [`ContextRefMut`] object is alive, in which case message dispatches must not access the context
+    /// object.
+    mutably_borrowed: AtomicBool,
+    /// Name of the context object type, checked by [`context_header`] in debug builds. `TypeId` is not used because
+    /// context objects are not required to be `'static`.
+    #[cfg(debug_assertions)]
+    type_name: &'static str,
+    #[cfg(feature = "std")]
+    pub(crate) panic_policy: PanicPolicy,
+    #[cfg(feature = "std")]
+    pub(crate) panic_hook: Option<PanicHook>,
+}
+
+impl ContextHeader {
+    fn new<T>(raw_thunk_ptr: NonNull<AtlThunkData_t>, procedure: WindowProcedure) -> Self {
+        Self {
+            raw_thunk_ptr,
+            window: AtomicPtr::new(ptr::null_mut()),
+            ansi: AtomicBool::new(false),
+            dispatch_depth: AtomicUsize::new(0),
+            generation: AtomicUsize::new(0),
+            pending_procedure: AtomicPtr::new(ptr::null_mut()),
+            pending_first_parameter: AtomicUsize::new(0),
+            active_procedure: AtomicPtr::new(procedure as *mut c_void),
+            active_first_parameter: AtomicUsize::new(0),
+            poisoned: AtomicBool::new(false),
+            procedure,
+            context_dropped: AtomicBool::new(false),
+            drop_requested: AtomicBool::new(false),
+            #[cfg(windows)]
+            window_destroyed: AtomicBool::new(false),
+            context_shared: AtomicBool::new(false),
+            mutably_borrowed: AtomicBool::new(false),
+            #[cfg(debug_assertions)]
+            type_name: any::type_name::<T>(),
+            #[cfg(feature = "std")]
+            panic_policy: PanicPolicy::default(),
+            #[cfg(feature = "std")]
+            panic_hook: None,
+        }
+    }
+
+    pub(crate) fn window(&self) -> HWND {
+        HWND(self.window.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn set_window(&self, window: HWND) {
+        self.window.store(window.0, Ordering::Relaxed);
+    }
+
+    #[cfg(windows)]
+    pub(crate) fn is_window_destroyed(&self) -> bool {
+        self.window_destroyed.load(Ordering::Relaxed)
+    }
+
+    #[cfg(windows)]
+    pub(crate) fn set_window_destroyed(&self) {
+        self.window_destroyed.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_context_shared(&self) {
+        self.context_shared.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns the window procedure of the ATL thunk.
+    #[cfg(windows)]
+    pub(crate) fn thunk_window_procedure(&self) -> WindowProcedure {
+        unsafe { crate::raw::data_to_code(self.raw_thunk_ptr) }
+    }
+
+    pub(crate) fn char_set(&self) -> CharSet {
+        if self.ansi.load(Ordering::Relaxed) {
+            CharSet::Ansi
+        } else {
+            CharSet::Wide
+        }
+    }
+
+    pub(crate) fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Relaxed)
+    }
+
+    #[cfg_attr(not(feature = "std"), expect(dead_code, reason = "only used by `std` feature"))]
+    pub(crate) fn poison(&self) {
+        self.poisoned.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns whether a message dispatch is in progress, in which case the context object may be borrowed.
+    pub(crate) fn is_dispatching(&self) -> bool {
+        self.dispatch_depth.load(Ordering::Relaxed) != 0
+    }
+
+    /// Returns whether no other dispatch is in progress outside of the current one, only meaningful inside
+    /// [`ContextHeader::dispatch`].
+    pub(crate) fn is_outermost_dispatch(&self) -> bool {
+        self.dispatch_depth.load(Ordering::Relaxed) == 1
+    }
+
+    /// Calls `f` as a message dispatch. Associated data updates requested by [`ContextThunk::set_data_deferred`] during
+    /// the dispatch are applied after the outermost dispatch returns.
+    ///
+    /// Panics if the context object is mutably borrowed by [`ContextThunk::get_mut`], which aborts the process because
+    /// window procedures can not unwind.
+    pub(crate) fn dispatch<R>(&self, f: impl FnOnce() -> R) -> R {
+        // Sequentially consistent operations pair with the ones in `ContextThunk::get_mut`, so a dispatch and a mutable
+        // borrow racing on different threads can not both succeed.
+        self.dispatch_depth.fetch_add(1, Ordering::SeqCst);
+        self.generation.fetch_add(1, Ordering::SeqCst);
+
+        assert!(
+            !self.mutably_borrowed.load(Ordering::SeqCst),
+            "a message is dispatched while the context object is mutably borrowed by `ContextThunk::get_mut`",
+        );
+
+        let result = f();
+
+        if self.dispatch_depth.fetch_sub(1, Ordering::Relaxed) == 1 {
+            self.apply_pending_data();
+        }
+
+        result
+    }
+
+    fn apply_pending_data(&self) {
+        let procedure = self.pending_procedure.swap(ptr::null_mut(), Ordering::Acquire);
+
+        if !procedure.is_null() {
+            let procedure = unsafe { mem::transmute::<*mut c_void, WindowProcedure>(procedure) };
+            let first_parameter = FirstParameter(self.pending_first_parameter.load(Ordering::Relaxed));
+
+            unsafe { self.init_data(procedure, first_parameter) };
+        }
+    }
+
+    /// Sets the associated data of the ATL thunk, and records it.
+    ///
+    /// # Safety
+    ///
+    /// The context object must be valid for `procedure` with `first_parameter`.
+    unsafe fn init_data(&self, procedure: WindowProcedure, first_parameter: FirstParameter) {
+        unsafe { crate::init_data(self.raw_thunk_ptr, procedure, first_parameter) };
+
+        self.record_data(procedure, first_parameter);
+    }
+
+    /// Records the associated data set on the ATL thunk by other means.
+    fn record_data(&self, procedure: WindowProcedure, first_parameter: impl Into<FirstParameter>) {
+        self.active_first_parameter
+            .store(first_parameter.into().0, Ordering::Relaxed);
+        self.active_procedure.store(procedure as *mut c_void, Ordering::Relaxed);
+    }
+
+    fn active_data(&self) -> (WindowProcedure, FirstParameter) {
+        let procedure = self.active_procedure.load(Ordering::Relaxed);
+
+        (
+            unsafe { mem::transmute::<*mut c_void, WindowProcedure>(procedure) },
+            FirstParameter(self.active_first_parameter.load(Ordering::Relaxed)),
+        )
+    }
+
+    /// Passes a message to the default window procedure of the bound window.
+    pub(crate) fn default_process(&self, message: u32, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+        match self.char_set() {
+            CharSet::Wide => unsafe { crate::default_window_procedure()(self.window(), message, w_param, l_param) },
+            CharSet::Ansi => unsafe { DefWindowProcA(self.window(), message, w_param, l_param) },
+        }
+    }
+}
+
+#[repr(C)]
+struct Context<T: ?Sized> {
+    header: ContextHeader,
+    value: ManuallyDrop<T>,
+}
+
+/// Returns the header of the context object pointed by `context`. In debug builds, panics if the context object is
+/// not of type `T`, which catches window procedures that reconstruct the context pointer with a wrong type.
+///
+/// # Safety
+///
+/// `context` must point to a context object owned by a live [`ContextThunk`] object.
+pub(crate) unsafe fn context_header<'a, T>(context: *const T) -> &'a ContextHeader {
+    let header = unsafe {
+        &*context
+            .byte_sub(mem::offset_of!(Context<T>, value))
+            .cast::<ContextHeader>()
+    };
+
+    #[cfg(debug_assertions)]
+    assert_eq!(
+        header.type_name,
+        any::type_name::<T>(),
+        "the context object is reconstructed with a wrong type",
+    );
+
+    header
+}
+
+/// Error returned by [`ContextThunk::set_data_checked`] if the update races with a message dispatch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Reentrant;
+
+impl Display for Reentrant {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("the associated data of the ATL thunk is updated during a message dispatch")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Reentrant {}
+
+/// A mutable borrow of the context object of a [`ContextThunk`] object, see [`ContextThunk::get_mut`].
+pub struct ContextRefMut<'a, T: ?Sized> {
+    header: &'a ContextHeader,
+    value: &'a mut T,
+}
+
+impl<T: ?Sized> Deref for ContextRefMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+impl<T: ?Sized> DerefMut for ContextRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.value
+    }
+}
+
+impl<T: ?Sized> Drop for ContextRefMut<'_, T> {
+    fn drop(&mut self) {
+        self.header.mutably_borrowed.store(false, Ordering::SeqCst);
+    }
+}
+
+/// An [`AtlThunk`] that owns a heap allocated context object, and passes a pointer to it as the first parameter of the
+/// window procedure. The context object is dropped with the [`ContextThunk`] object.
+///
+/// Some window procedures pass messages they do not handle to
+/// [`DefWindowProcW`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-defwindowprocw), which
+/// needs the real window handle that is replaced by the context pointer. Use [`ContextThunk::bind_window`] to specify
+/// the window handle before those messages are received. For windows created with ANSI window classes, use
+/// [`ContextThunk::set_char_set`] to select
+/// [`DefWindowProcA`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-defwindowproca) instead.
+///
+/// Like `Box<T>`, [`ContextThunk`] is covariant in `T`, so a `ContextThunk<&'static U>` object can be used as a
+/// `ContextThunk<&'a U>` object.
+pub struct ContextThunk<T: ?Sized> {
+    thunk: AtlThunk,
+    context: NonNull<Context<T>>,
+    has_value: bool,
+    /// The context object is owned like in a `Box<T>`, so [`ContextThunk`] is covariant in `T`, and the drop checker
+    /// treats dropping a [`ContextThunk`] object as dropping a `T` value.
+    _phantom: PhantomData<T>,
+}
+
+impl<T: ?Sized> ContextThunk<T> {
+    /// Creates a new [`ContextThunk`] object that owns `context`.
+    ///
+    /// # Safety
+    ///
+    /// `window_procedure` will be called with a pointer to the context object as its first parameter, it must access
+    /// the context object only through shared references.
+    #[track_caller]
+    pub(crate) unsafe fn try_new_with_procedure(
+        context: T,
+        window_procedure: WindowProcedure,
+    ) -> crate::sys::core::Result<Self>
+    where
+        T: Sized,
+    {
+        Ok(unsafe { Self::new_in(UninitAtlThunk::try_new()?, context, window_procedure) })
+    }
+
+    /// Same as [`ContextThunk::try_new_with_procedure`], but uses an existing ATL thunk.
+    ///
+    /// # Safety
+    ///
+    /// See [`ContextThunk::try_new_with_procedure`].
+    pub(crate) unsafe fn new_in(thunk: UninitAtlThunk, context: T, window_procedure: WindowProcedure) -> Self
+    where
+        T: Sized,
+    {
+        let context = NonNull::from(Box::leak(Box::new(Context {
+            header: ContextHeader::new::<T>(thunk.as_raw(), window_procedure),
+            value: ManuallyDrop::new(context),
+        })));
+
+        let first_parameter = unsafe { ptr::addr_of_mut!((*context.as_ptr()).value) }.cast::<T>();
+
+        unsafe { &context.as_ref().header }.record_data(window_procedure, first_parameter);
+
+        Self {
+            thunk: thunk.init(window_procedure, first_parameter),
+            context,
+            has_value: true,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Drops the context object, and returns the ATL thunk for reuse.
+    pub(crate) fn into_thunk(self) -> AtlThunk {
+        let mut this = ManuallyDrop::new(self);
+
+        unsafe { this.free_context() };
+
+        unsafe { ptr::read(&this.thunk) }
+    }
+
+    /// # Safety
+    ///
+    /// Must be called only once, and the context object must not be accessed afterwards.
+    unsafe fn free_context(&mut self) {
+        #[cfg(all(feature = "debug-checks", debug_assertions))]
+        crate::checks::assert_not_installed(
+            self.window(),
+            self.char_set() == CharSet::Ansi,
+            self.as_window_procedure(),
+        );
+
+        let has_live_value = self.has_live_value();
+        let mut context = unsafe { Box::from_raw(self.context.as_ptr()) };
+
+        if has_live_value {
+            unsafe { ManuallyDrop::drop(&mut context.value) };
+        }
+    }
+
+    pub(crate) fn header(&self) -> &ContextHeader {
+        unsafe { &self.context.as_ref().header }
+    }
+
+    /// Only used for initializing the header, before the window procedure is exposed.
+    #[cfg(feature = "std")]
+    pub(crate) fn header_mut(&mut self) -> &mut ContextHeader {
+        unsafe { &mut self.context.as_mut().header }
+    }
+
+    /// Returns a reference to the context object.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the context object has been taken by [`ContextThunk::take_context`].
+    pub fn context(&self) -> &T {
+        self.try_context()
+            .expect("the context object has been taken by `ContextThunk::take_context`")
+    }
+
+    /// Returns a reference to the context object, or [`None`] if it has been taken by [`ContextThunk::take_context`],
+    /// or dropped because of [`ContextThunk::drop_context_on_ncdestroy`].
+    pub fn try_context(&self) -> Option<&T> {
+        self.has_live_value().then(|| unsafe { &*self.context.as_ref().value })
+    }
+
+    /// Returns a raw pointer to the context object, which is the pointer passed to the window procedure, for passing
+    /// through Win32 APIs that take a single `LPARAM` value, like
+    /// [`EnumChildWindows`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-enumchildwindows).
+    ///
+    /// The pointer is only valid while the [`ContextThunk`] object is alive, and does not point to a live value after
+    /// the context object has been taken or dropped, see [`ContextThunk::try_context`]. Window procedures may access
+    /// the context object through shared references at any time, so the pointer must not be used for creating mutable
+    /// references, use [`ContextThunk::get_mut`] instead.
+    pub fn context_ptr(&self) -> *mut T
+    where
+        T: Sized,
+    {
+        unsafe { ptr::addr_of_mut!((*self.context.as_ptr()).value) }.cast()
+    }
+
+    fn has_live_value(&self) -> bool {
+        self.has_value && !self.header().context_dropped.load(Ordering::Acquire)
+    }
+
+    /// Makes the thunk drop the context object after the window receives
+    /// [`WM_NCDESTROY`](https://learn.microsoft.com/en-us/windows/win32/winmsg/wm-ncdestroy), which is the last message
+    /// a window receives, so the context object is reclaimed even if the [`ContextThunk`] object is leaked. If
+    /// `WM_NCDESTROY` is received while another message is being dispatched, like when a handler calls
+    /// [`DestroyWindow`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-destroywindow), the
+    /// context object is dropped after the outermost dispatch returns. Messages received after that are passed to the
+    /// default window procedure.
+    ///
+    /// This replaces the associated window procedure with a wrapper of the one the thunk is created with, so it should
+    /// not be combined with [`ContextThunk::set_data_deferred`]. It has no effect after [`ContextThunk::into_shared`].
+    ///
+    /// # Safety
+    ///
+    /// References returned by [`ContextThunk::context`] must not be used after the window is destroyed.
+    #[must_use]
+    pub unsafe fn drop_context_on_ncdestroy(mut self, enabled: bool) -> Self
+    where
+        T: Sized,
+    {
+        let first_parameter = unsafe { ptr::addr_of_mut!((*self.context.as_ptr()).value) }.cast::<T>();
+
+        let procedure = if enabled {
+            ncdestroy_window_procedure::<T>
+        } else {
+            self.header().procedure
+        };
+
+        self.thunk.set_data(procedure, first_parameter);
+        self.header().record_data(procedure, first_parameter);
+
+        self
+    }
+
+    /// Moves the context object out of the [`ContextThunk`] object, and returns it. Returns [`None`] if it has already
+    /// been taken. The ATL thunk is reset with [`AtlThunk::clear_data`] and the bound window first, so that following
+    /// messages are handled by the default procedure without accessing the context object. Pending updates from
+    /// [`ContextThunk::set_data_deferred`] are discarded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a message dispatch is in progress, which may still be using the context object.
+    pub fn take_context(&mut self) -> Option<T>
+    where
+        T: Sized,
+    {
+        if !self.has_live_value() {
+            return None;
+        }
+
+        assert!(
+            !self.header().is_dispatching(),
+            "the context object can not be taken while dispatching a message",
+        );
+
+        self.has_value = false;
+
+        let window = self.window();
+        let context = unsafe { self.context.as_mut() };
+
+        context
+            .header
+            .pending_procedure
+            .store(ptr::null_mut(), Ordering::Relaxed);
+
+        let procedure = crate::default_procedure();
+
+        self.thunk.clear_data_to(procedure, window);
+        context.header.record_data(procedure, window);
+
+        Some(unsafe { ManuallyDrop::take(&mut context.value) })
+    }
+
+    /// Frees the ATL thunk, and returns a pointer to the context object without dropping it. The context object stays
+    /// at the same address, so pointers to it that are stored elsewhere remain valid. The caller becomes responsible
+    /// for the context object, which can be dropped with [`ptr::drop_in_place`], but the memory it occupies is never
+    /// freed. The caller must make sure the window procedure is no longer used by any window.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the context object has been taken or dropped, or if a message dispatch is in progress, which may still
+    /// be using the header of the context object.
+    pub fn leak_context(self) -> *mut T
+    where
+        T: Sized,
+    {
+        assert!(self.has_live_value(), "the context object has been taken or dropped",);
+
+        assert!(
+            !self.header().is_dispatching(),
+            "the context object can not be leaked while dispatching a message",
+        );
+
+        #[cfg(all(feature = "debug-checks", debug_assertions))]
+        crate::checks::assert_not_installed(
+            self.window(),
+            self.char_set() == CharSet::Ansi,
+            self.as_window_procedure(),
+        );
+
+        let this = ManuallyDrop::new(self);
+        let context = this.context.as_ptr();
+
+        drop(unsafe { ptr::read(&this.thunk) });
+
+        unsafe {
+            ptr::drop_in_place(ptr::addr_of_mut!((*context).header));
+
+            ptr::addr_of_mut!((*context).value).cast::<T>()
+        }
+    }
+
+    /// Converts back to a [`ContextThunk`] object with the concrete context type `T`, typically after
+    /// [`ContextThunk::erase`] or [`ContextThunk::into_dyn`]. Like [`ContextThunk::reinterpret_context`], nothing else
+    /// is changed.
+    ///
+    /// # Safety
+    ///
+    /// `U` must be the type the [`ContextThunk`] object is created with. In debug builds, this is checked against the
+    /// type name stored in the context header, and a mismatch panics.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, panics if `U` is not the type the [`ContextThunk`] object is created with.
+    pub unsafe fn downcast<U>(self) -> ContextThunk<U> {
+        #[cfg(debug_assertions)]
+        assert_eq!(
+            self.header().type_name,
+            any::type_name::<U>(),
+            "the context object is downcast to a wrong type",
+        );
+
+        let this = ManuallyDrop::new(self);
+
+        ContextThunk {
+            thunk: unsafe { ptr::read(&this.thunk) },
+            context: this.context.cast(),
+            has_value: this.has_value,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Changes the type of the context object to `U` in place, without touching the context object or the associated
+    /// data. The installed window procedure is not changed, so it keeps accessing the context object as `T`.
+    ///
+    /// `T` and `U` must have the same size and alignment, which is checked at compile time.
+    ///
+    /// # Safety
+    ///
+    /// The context object must be a valid `U` value, both now and whenever it is accessed as `T` by the installed
+    /// window procedure.
+    pub unsafe fn reinterpret_context<U>(self) -> ContextThunk<U>
+    where
+        T: Sized,
+    {
+        const {
+            assert!(
+                mem::size_of::<T>() == mem::size_of::<U>(),
+                "`T` and `U` have different sizes"
+            );
+            assert!(
+                mem::align_of::<T>() == mem::align_of::<U>(),
+                "`T` and `U` have different alignments"
+            );
+        }
+
+        let this = ManuallyDrop::new(self);
+
+        ContextThunk {
+            thunk: unsafe { ptr::read(&this.thunk) },
+            context: this.context.cast(),
+            has_value: this.has_value,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Converts to a [`ContextThunk`] object whose context object is an [`AnyContext`] trait object, so thunks of
+    /// different context types have the same type. Use [`ContextThunk::downcast`] to restore the concrete type.
+    ///
+    /// Like [`ContextThunk::into_dyn`], the ATL thunk and the window procedure are kept, and dropping the returned
+    /// object drops the context object as `T`.
+    pub fn erase<'a>(self) -> ContextThunk<dyn AnyContext + 'a>
+    where
+        T: Sized + 'a,
+    {
+        let this = ManuallyDrop::new(self);
+
+        ContextThunk {
+            thunk: unsafe { ptr::read(&this.thunk) },
+            context: this.context,
+            has_value: this.has_value,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Replaces the context object with the one computed by `f` from the current one, and dispatches messages to it
+    /// like [`ContextThunk::for_handler`]. The ATL thunk is kept, so the window procedure returned by
+    /// [`ContextThunk::as_window_procedure`] stays the same, and so do the bound window and the character set. Other
+    /// states, like the panic policy and the poison flag, are reset, and pending updates from
+    /// [`ContextThunk::set_data_deferred`] are discarded. While `f` is running, messages are handled by the default
+    /// procedure, like after [`ContextThunk::take_context`].
+    ///
+    /// If allocating the new context object fails, an error with code `E_OUTOFMEMORY` is returned, and the new context
+    /// object is dropped along with the [`ContextThunk`] object.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the context object has been taken or dropped, or if a message dispatch is in progress, which may still
+    /// be using the current context object.
+    pub fn map_context<U>(mut self, f: impl FnOnce(T) -> U) -> crate::sys::core::Result<ContextThunk<U>>
+    where
+        T: Sized,
+        U: WindowHandler,
+    {
+        assert!(
+            !self.header().is_dispatching(),
+            "the context object can not be replaced while dispatching a message",
+        );
+
+        let value = self
+            .take_context()
+            .expect("the context object has been taken by `ContextThunk::take_context`");
+
+        let value = f(value);
+        let context = unsafe { alloc::alloc::alloc(Layout::new::<Context<U>>()) }.cast::<Context<U>>();
+        let context = NonNull::new(context).ok_or(E_OUTOFMEMORY)?;
+        let procedure = context_handler_window_procedure::<U> as WindowProcedure;
+        let this = ManuallyDrop::new(self);
+        let mut thunk = unsafe { ptr::read(&this.thunk) };
+        let old_context = unsafe { Box::from_raw(this.context.as_ptr()) };
+
+        unsafe {
+            context.as_ptr().write(Context {
+                header: ContextHeader::new::<U>(thunk.raw_thunk_ptr, procedure),
+                value: ManuallyDrop::new(value),
+            });
+        }
+
+        let header = unsafe { &context.as_ref().header };
+
+        header.window.store(old_context.header.window().0, Ordering::Relaxed);
+        header
+            .ansi
+            .store(old_context.header.ansi.load(Ordering::Relaxed), Ordering::Relaxed);
+
+        // The old value has been taken, so only the old header is dropped.
+        drop(old_context);
+
+        let first_parameter = unsafe { ptr::addr_of_mut!((*context.as_ptr()).value) }.cast::<U>();
+
+        thunk.set_data(procedure, first_parameter);
+        header.record_data(procedure, first_parameter);
+
+        Ok(ContextThunk {
+            thunk,
+            context,
+            has_value: true,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Returns a reference to the context object, like [`ContextThunk::context`], but also checks that the context
+    /// object is not mutably borrowed, which can only happen here if a [`ContextRefMut`] object has been leaked.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the context object has been taken, or is mutably borrowed.
+    pub fn get_ref(&self) -> &T {
+        assert!(
+            !self.header().mutably_borrowed.load(Ordering::SeqCst),
+            "the context object is mutably borrowed by `ContextThunk::get_mut`",
+        );
+
+        self.context()
+    }
+
+    /// Mutably borrows the context object. Like [`RefCell`](core::cell::RefCell), the borrow is tracked at runtime:
+    /// message dispatches are shared borrows, so this panics if a message is being dispatched, and a message dispatched
+    /// while the returned [`ContextRefMut`] object is alive, like one sent by
+    /// [`SendMessageW`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-sendmessagew), panics
+    /// in the window procedure, which aborts the process instead of aliasing the context object.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the context object has been taken or dropped, or if a message is being dispatched.
+    pub fn get_mut(&mut self) -> ContextRefMut<'_, T> {
+        assert!(
+            self.has_live_value(),
+            "the context object has been taken by `ContextThunk::take_context`",
+        );
+
+        let context = self.context.as_ptr();
+        let header = unsafe { &*ptr::addr_of!((*context).header) };
+
+        header.mutably_borrowed.store(true, Ordering::SeqCst);
+
+        if header.dispatch_depth.load(Ordering::SeqCst) != 0 {
+            header.mutably_borrowed.store(false, Ordering::SeqCst);
+
+            panic!("the context object is borrowed by a message dispatch");
+        }
+
+        // Only the value is borrowed mutably, because window procedures on other threads may still read the header.
+        ContextRefMut {
+            header,
+            value: unsafe { &mut *ptr::addr_of_mut!((*context).value) },
+        }
+    }
+
+    /// Replaces the context object with `context`, and returns the previous one. Unlike [`ContextThunk::map_context`],
+    /// the new value is moved into the existing allocation, so the ATL thunk keeps its first parameter.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the context object has been taken or dropped, or if a message is being dispatched.
+    pub fn replace_context(&mut self, context: T) -> T
+    where
+        T: Sized,
+    {
+        mem::replace(&mut *self.get_mut(), context)
+    }
+
+    /// Returns the window handle specified by [`ContextThunk::bind_window`], or a null handle if no window is bound.
+    pub fn window(&self) -> HWND {
+        self.header().window()
+    }
+
+    /// Specifies the window handle used for passing messages to default window procedures.
+    ///
+    /// With the `debug-checks` feature, debug builds also check on drop that the ATL thunk is no longer the window
+    /// procedure of the bound window, and panic if it still is.
+    pub fn bind_window(&self, window: HWND) {
+        self.header().set_window(window);
+    }
+
+    /// Returns the character set specified by [`ContextThunk::set_char_set`], which is [`CharSet::Wide`] by default.
+    pub fn char_set(&self) -> CharSet {
+        self.header().char_set()
+    }
+
+    /// Specifies the character set of the bound window, which decides the default window procedure to use.
+    pub fn set_char_set(&self, char_set: CharSet) {
+        self.header().ansi.store(char_set == CharSet::Ansi, Ordering::Relaxed);
+    }
+
+    /// Returns whether a handler has panicked while dispatching messages, after which messages are passed to the
+    /// default window procedure without calling the handler. Only thunks created with the `PanicPolicy::Poison` panic
+    /// policy can be poisoned.
+    pub fn is_poisoned(&self) -> bool {
+        self.header().is_poisoned()
+    }
+
+    /// Clears the poisoned state, so that following messages are dispatched to the handler again.
+    pub fn clear_poison(&self) {
+        self.header().poisoned.store(false, Ordering::Relaxed);
+    }
+
+    /// Updates the associated window procedure and data like [`AtlThunk::set_data`], but if a message is being
+    /// dispatched, the update is delayed until the outermost dispatch returns, so that a handler can change the
+    /// behavior of the thunk for following messages without modifying the code it is running. If this is called
+    /// multiple times during a dispatch, only the last update is applied.
+    ///
+    /// Note that after the update, `window_procedure` receives `first_parameter` instead of the context object, and
+    /// [`ContextThunk::context`] still returns the original context object.
+    ///
+    /// # Safety
+    ///
+    /// Must be called on the thread that dispatches messages, usually the thread that owns the window, because the
+    /// update may be applied immediately. It must not race with messages dispatched on other threads, or with other
+    /// updates of the associated data of the same ATL thunk.
+    pub unsafe fn set_data_deferred(
+        &self,
+        window_procedure: WindowProcedure,
+        first_parameter: impl Into<FirstParameter>,
+    ) {
+        let header = self.header();
+
+        header
+            .pending_first_parameter
+            .store(first_parameter.into().0, Ordering::Relaxed);
+
+        header
+            .pending_procedure
+            .store(window_procedure as *mut c_void, Ordering::Release);
+
+        if header.dispatch_depth.load(Ordering::Relaxed) == 0 {
+            header.apply_pending_data();
+        }
+    }
+
+    /// Updates the associated window procedure and data like [`AtlThunk::set_data`], but refuses to do so if it would
+    /// race with a message dispatch. Returns [`Reentrant`] without updating if a message is being dispatched, like when
+    /// called from a handler. Also returns [`Reentrant`] if a dispatch starts while updating, like one on the thread of
+    /// a window owned by another thread, which is detected with a counter of started dispatches. In that case, the
+    /// update has been applied, but the window procedure and the first parameter are stored separately, so the racing
+    /// dispatch may have observed a mismatched window procedure and first parameter.
+    ///
+    /// Unlike [`ContextThunk::set_data_deferred`], the update is never delayed. The same note about the first parameter
+    /// applies.
+    pub fn set_data_checked(
+        &mut self,
+        window_procedure: WindowProcedure,
+        first_parameter: impl Into<FirstParameter>,
+    ) -> Result<(), Reentrant> {
+        let header = self.header();
+        let generation = header.generation.load(Ordering::SeqCst);
+
+        if header.is_dispatching() {
+            return Err(Reentrant);
+        }
+
+        unsafe { header.init_data(window_procedure, first_parameter.into()) };
+
+        if header.generation.load(Ordering::SeqCst) == generation {
+            Ok(())
+        } else {
+            Err(Reentrant)
+        }
+    }
+
+    /// Replaces the ATL thunk with a newly allocated one, initialized with the associated data last set on the old one,
+    /// then frees the old ATL thunk. This is for migrating thunks, like when the host wants the ATL thunk data
+    /// allocated again after `atlthunk.dll` is reloaded.
+    ///
+    /// The window procedure returned by [`ContextThunk::as_window_procedure`] changes, and the previous one is
+    /// invalidated, so wherever it has been installed, the caller must install the new one before any message is
+    /// dispatched with the previous one. If the new ATL thunk can not be allocated, the old one is kept and an error is
+    /// returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a message is being dispatched.
+    #[track_caller]
+    pub fn reallocate(&mut self) -> crate::sys::core::Result<()> {
+        let header = self.header();
+
+        assert!(
+            !header.is_dispatching(),
+            "the ATL thunk can not be reallocated while dispatching a message",
+        );
+
+        let (procedure, first_parameter) = header.active_data();
+        let thunk = UninitAtlThunk::try_new()?.init(procedure, first_parameter);
+
+        // No dispatch is in progress, and the previous window procedure may no longer be called.
+        unsafe { (*self.context.as_ptr()).header.raw_thunk_ptr = thunk.raw_thunk_ptr };
+
+        drop(mem::replace(&mut self.thunk, thunk));
+
+        Ok(())
+    }
+
+    /// Returns a window procedure that dispatches messages with the context object. The returned function pointer is
+    /// only valid while the originating [`ContextThunk`] object is alive.
+    #[inline(always)]
+    pub fn as_window_procedure(&self) -> WindowProcedure {
+        self.thunk.as_window_procedure()
+    }
+
+    /// Returns the creation parameter to pass to
+    /// [`CreateWindowExW`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-createwindowexw) for
+    /// windows of classes whose window procedure is [`creation_window_procedure`](crate::creation_window_procedure),
+    /// which installs the thunk on the created window. The returned pointer is only valid while the [`ContextThunk`]
+    /// object is alive.
+    pub fn creation_parameter(&self) -> *const c_void {
+        // The header is the first field of the context allocation.
+        self.context.as_ptr().cast::<ContextHeader>().cast_const().cast()
+    }
+}
+
+/// Window procedure that forwards messages to the handler pointed by the associated data, which is owned by a
+/// [`ContextThunk`] object.
+unsafe extern "system" fn context_handler_window_procedure<H>(
+    handler: HWND,
+    message: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> LRESULT
+where
+    H: WindowHandler,
+{
+    let handler = handler.0.cast::<H>().cast_const();
+
+    unsafe { context_header(handler) }.dispatch(|| unsafe { (*handler).handle(message, w_param, l_param) })
+}
+
+impl<H> ContextThunk<H>
+where
+    H: WindowHandler,
+{
+    /// Creates a new [`ContextThunk`] object that owns `handler`, and dispatches messages to it.
+    #[track_caller]
+    pub fn for_handler(handler: H) -> crate::sys::core::Result<Self> {
+        Ok(Self::for_handler_in(UninitAtlThunk::try_new()?, handler))
+    }
+
+    /// Same as [`ContextThunk::for_handler`], but uses an existing ATL thunk.
+    pub(crate) fn for_handler_in(thunk: UninitAtlThunk, handler: H) -> Self {
+        unsafe { Self::new_in(thunk, handler, context_handler_window_procedure::<H>) }
+    }
+
+    /// Converts to a [`ContextThunk`] object whose context object is a [`WindowHandler`] trait object, so thunks of
+    /// different handler types have the same type, and [`ContextThunk::context`] returns a `&dyn WindowHandler`
+    /// reference.
+    ///
+    /// Only the pointer owned by the [`ContextThunk`] object carries the vtable. The ATL thunk still passes a thin
+    /// pointer to the concrete context object, and the window procedure selected for `H` at creation is kept, so
+    /// messages are dispatched without going through the vtable, and no extra allocation is needed.
+    pub fn into_dyn<'a>(self) -> ContextThunk<dyn WindowHandler + 'a>
+    where
+        H: 'a,
+    {
+        let this = ManuallyDrop::new(self);
+
+        ContextThunk {
+            thunk: unsafe { ptr::read(&this.thunk) },
+            context: this.context,
+            has_value: this.has_value,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+/// Window procedure installed by [`ContextThunk::drop_context_on_ncdestroy`], which forwards messages to the original
+/// window procedure, and drops the context object after `WM_NCDESTROY` is handled.
+unsafe extern "system" fn ncdestroy_window_procedure<T>(
+    value: HWND,
+    message: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> LRESULT {
+    let context = value.0.cast::<T>();
+    let header = unsafe { context_header(context.cast_const()) };
+
+    if header.context_dropped.load(Ordering::Acquire) {
+        return header.default_process(message, w_param, l_param);
+    }
+
+    let result = unsafe { (header.procedure)(value, message, w_param, l_param) };
+
+    // Shared context objects may be read by other threads, so they are only dropped with the last handle.
+    if message == WM_NCDESTROY && !header.context_shared.load(Ordering::Relaxed) {
+        header.drop_requested.store(true, Ordering::Relaxed);
+    }
+
+    // Outer dispatches may still borrow the context object, in which case it is dropped after they return.
+    if !header.is_dispatching() && header.drop_requested.swap(false, Ordering::Relaxed) {
+        header.pending_procedure.store(ptr::null_mut(), Ordering::Relaxed);
+        header.context_dropped.store(true, Ordering::Release);
+
+        unsafe { ptr::drop_in_place(context) };
+    }
+
+    result
+}
+
+/// Same as [`context_handler_window_procedure`], but passes unhandled messages to the default window procedure.
+unsafe extern "system" fn context_optional_handler_window_procedure<H>(
+    handler: HWND,
+    message: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> LRESULT
+where
+    H: OptionalWindowHandler,
+{
+    let handler = handler.0.cast::<H>().cast_const();
+    let header = unsafe { context_header(handler) };
+
+    match header.dispatch(|| unsafe { (*handler).handle(message, w_param, l_param) }) {
+        None => header.default_process(message, w_param, l_param),
+        Some(result) => result,
+    }
+}
+
+impl<H> ContextThunk<H>
+where
+    H: OptionalWindowHandler,
+{
+    /// Creates a new [`ContextThunk`] object that owns `handler`, and dispatches messages to it. Messages for which the
+    /// handler returns [`None`] are passed to
+    /// [`DefWindowProcW`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-defwindowprocw), which
+    /// needs the window bound with [`ContextThunk::bind_window`].
+    #[track_caller]
+    pub fn for_optional_handler(handler: H) -> crate::sys::core::Result<Self> {
+        unsafe { Self::try_new_with_procedure(handler, context_optional_handler_window_procedure::<H>) }
+    }
+}
+
+impl<T: ?Sized> Drop for ContextThunk<T> {
+    fn drop(&mut self) {
+        unsafe { self.free_context() };
+    }
+}
+
+// The context object is accessed by both the owner and the window procedure, which may run on different threads.
+unsafe impl<T: ?Sized> Send for ContextThunk<T> where T: Send + Sync {}
+unsafe impl<T: ?Sized> Sync for ContextThunk<T> where T: Sync {}
+
+#[cfg(test)]
+mod tests {
+    use super::{CharSet, ContextThunk};
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use crate::test_support::DEFAULT_RESULT;
+    use alloc::rc::Rc;
+    use core::cell::Cell;
+    use core::ptr;
+
+    #[test]
+    fn test_context_thunk_for_handler() {
+        struct Handler {
+            count: Cell<u32>,
+        }
+
+        impl crate::WindowHandler for Handler {
+            fn handle(&self, message: u32, w_param: WPARAM, _: LPARAM) -> LRESULT {
+                self.count.set(self.count.get() + 1);
+
+                LRESULT(message as isize * w_param.0 as isize)
+            }
+        }
+
+        let thunk = ContextThunk::for_handler(Handler { count: Cell::new(0) }).unwrap();
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 2, WPARAM(3), LPARAM(0)) }.0,
+            6,
+        );
+
+        assert_eq!(thunk.context().count.get(), 1);
+        assert_eq!(thunk.window(), HWND::default());
+
+        thunk.bind_window(HWND(5 as _));
+
+        assert_eq!(thunk.window(), HWND(5 as _));
+        assert_eq!(thunk.char_set(), CharSet::Wide);
+
+        thunk.set_char_set(CharSet::Ansi);
+
+        assert_eq!(thunk.char_set(), CharSet::Ansi);
+    }
+
+    #[test]
+    fn test_context_thunk_context_ptr() {
+        let thunk = unsafe { ContextThunk::try_new_with_procedure(2_isize, crate::default_procedure()) }.unwrap();
+        let l_param = LPARAM(thunk.context_ptr() as _);
+
+        assert!(ptr::eq(thunk.context_ptr(), thunk.context()));
+
+        // Like a callback that receives the pointer as its `LPARAM` argument.
+        assert_eq!(unsafe { *(l_param.0 as *const isize) }, 2);
+    }
+
+    #[test]
+    fn test_context_thunk_variance() {
+        // Only compiles if `ContextThunk` is covariant in `T`.
+        fn shorten<'a>(thunk: ContextThunk<&'static isize>) -> ContextThunk<&'a isize> {
+            thunk
+        }
+
+        let value = 3;
+        let thunk = unsafe { ContextThunk::try_new_with_procedure(&2, crate::default_procedure()) }.unwrap();
+        let mut thunk = shorten(thunk);
+
+        assert_eq!(thunk.replace_context(&value), &2);
+        assert_eq!(**thunk.context(), 3);
+    }
+
+    #[test]
+    fn test_context_thunk_into_dyn() {
+        struct Handler {
+            value: isize,
+            _counter: Rc<()>,
+        }
+
+        impl crate::WindowHandler for Handler {
+            fn handle(&self, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+                LRESULT(message as isize * self.value)
+            }
+        }
+
+        let counter = Rc::new(());
+
+        let thunks: [ContextThunk<dyn crate::WindowHandler>; 2] = [
+            ContextThunk::for_handler(Handler {
+                value: 3,
+                _counter: Rc::clone(&counter),
+            })
+            .unwrap()
+            .into_dyn(),
+            ContextThunk::for_handler(|message: u32, _: WPARAM, _: LPARAM| LRESULT(-(message as isize)))
+                .unwrap()
+                .into_dyn(),
+        ];
+
+        let call = |thunk: &ContextThunk<dyn crate::WindowHandler>| {
+            unsafe { thunk.as_window_procedure()(HWND::default(), 2, WPARAM(0), LPARAM(0)) }.0
+        };
+
+        assert_eq!(call(&thunks[0]), 6);
+        assert_eq!(call(&thunks[1]), -2);
+
+        // The context object is accessed through the vtable.
+        assert_eq!(thunks[0].context().handle(4, WPARAM(0), LPARAM(0)).0, 12);
+
+        thunks[1].bind_window(HWND(5 as _));
+
+        assert_eq!(thunks[1].window(), HWND(5 as _));
+        assert_eq!(Rc::strong_count(&counter), 2);
+
+        drop(thunks);
+
+        // The concrete context objects are dropped.
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn test_context_thunk_erase_downcast() {
+        let counter = Rc::new(());
+
+        let thunks = [
+            ContextThunk::for_handler(|message: u32, _: WPARAM, _: LPARAM| LRESULT(message as isize))
+                .unwrap()
+                .erase(),
+            unsafe { ContextThunk::try_new_with_procedure(Rc::clone(&counter), crate::default_procedure()) }
+                .unwrap()
+                .erase(),
+        ];
+
+        let [handler, counter_thunk] = thunks;
+
+        // The ATL thunk and the window procedure are kept.
+        assert_eq!(
+            unsafe { handler.as_window_procedure()(HWND::default(), 2, WPARAM(0), LPARAM(0)) }.0,
+            2,
+        );
+
+        let counter_thunk = unsafe { counter_thunk.downcast::<Rc<()>>() };
+
+        assert!(Rc::ptr_eq(counter_thunk.context(), &counter));
+
+        drop(counter_thunk);
+
+        // The context object is dropped as its concrete type.
+        assert_eq!(Rc::strong_count(&counter), 1);
+
+        drop(handler);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "downcast to a wrong type")]
+    fn test_context_thunk_downcast_wrong_type() {
+        let thunk = unsafe { ContextThunk::try_new_with_procedure(2_u32, crate::default_procedure()) }
+            .unwrap()
+            .erase();
+
+        drop(unsafe { thunk.downcast::<i32>() });
+    }
+
+    #[test]
+    fn test_context_thunk_set_data_deferred() {
+        unsafe extern "system" fn callback(first_parameter: HWND, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+            LRESULT(first_parameter.0 as isize + message as isize)
+        }
+
+        struct Handler {
+            thunk: Cell<*const ContextThunk<Handler>>,
+        }
+
+        impl crate::WindowHandler for Handler {
+            fn handle(&self, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+                if message == 1 {
+                    let thunk = unsafe { &*self.thunk.get() };
+
+                    unsafe { thunk.set_data_deferred(callback, HWND(7 as _)) };
+
+                    // The update is not applied until the outermost dispatch returns.
+                    assert_eq!(
+                        unsafe { thunk.as_window_procedure()(HWND::default(), 2, WPARAM(0), LPARAM(0)) }.0,
+                        -2,
+                    );
+                }
+
+                LRESULT(-(message as isize))
+            }
+        }
+
+        let thunk = ContextThunk::for_handler(Handler {
+            thunk: Cell::new(ptr::null()),
+        })
+        .unwrap();
+
+        thunk.context().thunk.set(&thunk);
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 1, WPARAM(0), LPARAM(0)) }.0,
+            -1,
+        );
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 3, WPARAM(0), LPARAM(0)) }.0,
+            10,
+        );
+
+        // Outside of dispatches, the update is applied immediately.
+        unsafe { thunk.set_data_deferred(callback, HWND(11 as _)) };
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 3, WPARAM(0), LPARAM(0)) }.0,
+            14,
+        );
+    }
+
+    #[test]
+    fn test_context_thunk_reallocate() {
+        unsafe extern "system" fn callback(first_parameter: HWND, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+            LRESULT(first_parameter.0 as isize + message as isize)
+        }
+
+        let mut thunk =
+            ContextThunk::for_handler(|message: u32, _: WPARAM, _: LPARAM| LRESULT(-(message as isize))).unwrap();
+
+        let old_window_procedure = thunk.as_window_procedure();
+
+        thunk.reallocate().unwrap();
+
+        // The old ATL thunk is freed after the new one is allocated, so the window procedure changes.
+        assert_ne!(thunk.as_window_procedure() as usize, old_window_procedure as usize);
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 2, WPARAM(0), LPARAM(0)) }.0,
+            -2,
+        );
+
+        // Updated data is restored too.
+        unsafe { thunk.set_data_deferred(callback, HWND(3 as _)) };
+        thunk.reallocate().unwrap();
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 2, WPARAM(0), LPARAM(0)) }.0,
+            5,
+        );
+
+        thunk.bind_window(HWND(7 as _));
+        assert!(thunk.take_context().is_some());
+        thunk.reallocate().unwrap();
+
+        assert_eq!(thunk.window(), HWND(7 as _));
+    }
+
+    #[test]
+    fn test_context_thunk_set_data_checked() {
+        use super::Reentrant;
+
+        unsafe extern "system" fn callback(first_parameter: HWND, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+            LRESULT(first_parameter.0 as isize + message as isize)
+        }
+
+        struct Handler {
+            thunk: Cell<*mut ContextThunk<Handler>>,
+            result: Cell<Option<Result<(), Reentrant>>>,
+        }
+
+        impl crate::WindowHandler for Handler {
+            fn handle(&self, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+                let thunk = unsafe { &mut *self.thunk.get() };
+
+                // Reconfiguring from a handler is reentrant.
+                self.result.set(Some(thunk.set_data_checked(callback, HWND(7 as _))));
+
+                LRESULT(-(message as isize))
+            }
+        }
+
+        let mut thunk = ContextThunk::for_handler(Handler {
+            thunk: Cell::new(ptr::null_mut()),
+            result: Cell::new(None),
+        })
+        .unwrap();
+
+        let thunk_ptr = ptr::addr_of_mut!(thunk);
+
+        thunk.context().thunk.set(thunk_ptr);
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 1, WPARAM(0), LPARAM(0)) }.0,
+            -1,
+        );
+
+        assert_eq!(thunk.context().result.get(), Some(Err(Reentrant)));
+
+        // The rejected update is not applied.
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 3, WPARAM(0), LPARAM(0)) }.0,
+            -3,
+        );
+
+        assert_eq!(thunk.set_data_checked(callback, HWND(11 as _)), Ok(()));
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 3, WPARAM(0), LPARAM(0)) }.0,
+            14,
+        );
+    }
+
+    /// The drop sequence number, the window procedure and the first parameter recorded by a [`DropRecorder`] object.
+    #[cfg(feature = "mock-backend")]
+    type DropRecord = Rc<Cell<Option<(u32, usize, crate::FirstParameter)>>>;
+
+    /// Context object that records, when dropped, the data stored in the emulated ATL thunk it is dispatched by.
+    #[cfg(feature = "mock-backend")]
+    struct DropRecorder {
+        raw_thunk_ptr: Cell<Option<ptr::NonNull<crate::sys::Win32::System::Memory::AtlThunkData_t>>>,
+        sequence: Rc<Cell<u32>>,
+        recorded: DropRecord,
+    }
+
+    #[cfg(feature = "mock-backend")]
+    impl crate::WindowHandler for DropRecorder {
+        fn handle(&self, _: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+            LRESULT(0)
+        }
+    }
+
+    #[cfg(feature = "mock-backend")]
+    impl Drop for DropRecorder {
+        fn drop(&mut self) {
+            let (procedure, first_parameter) =
+                unsafe { crate::mock::stored_data(self.raw_thunk_ptr.get().unwrap().as_ptr()) };
+            let sequence = self.sequence.get() + 1;
+
+            self.sequence.set(sequence);
+            self.recorded.set(Some((sequence, procedure as usize, first_parameter)));
+        }
+    }
+
+    #[cfg(feature = "mock-backend")]
+    fn new_drop_recorder(sequence: &Rc<Cell<u32>>) -> (ContextThunk<DropRecorder>, DropRecord) {
+        let recorded = Rc::new(Cell::new(None));
+
+        let thunk = ContextThunk::for_handler(DropRecorder {
+            raw_thunk_ptr: Cell::new(None),
+            sequence: Rc::clone(sequence),
+            recorded: Rc::clone(&recorded),
+        })
+        .unwrap();
+
+        thunk.context().raw_thunk_ptr.set(Some(thunk.header().raw_thunk_ptr));
+
+        (thunk, recorded)
+    }
+
+    #[cfg(feature = "mock-backend")]
+    #[test]
+    fn test_context_thunk_drop_order() {
+        let sequence = Rc::new(Cell::new(0));
+        let (first, first_recorded) = new_drop_recorder(&sequence);
+        let (second, second_recorded) = new_drop_recorder(&sequence);
+        let first_address = crate::FirstParameter::from(ptr::from_ref(first.context()));
+        let first_procedure =
+            super::context_handler_window_procedure::<DropRecorder> as crate::WindowProcedure as usize;
+
+        drop(second);
+        drop(first);
+
+        // The context object is dropped while the ATL thunk still holds its original data, so the ATL thunk is freed
+        // only after the context object.
+        assert_eq!(second_recorded.get().map(|(sequence, ..)| sequence), Some(1));
+        assert_eq!(first_recorded.get(), Some((2, first_procedure, first_address)));
+    }
+
+    #[cfg(feature = "mock-backend")]
+    #[test]
+    fn test_context_thunk_take_context_drop_order() {
+        let sequence = Rc::new(Cell::new(0));
+        let (mut thunk, recorded) = new_drop_recorder(&sequence);
+
+        thunk.bind_window(HWND(5 as _));
+
+        let context = thunk.take_context().unwrap();
+
+        // The ATL thunk is reset to the default window procedure with the bound window, before the context object is
+        // dropped, like in `WindowObject`.
+        drop(context);
+
+        assert_eq!(
+            recorded.get(),
+            Some((1, crate::default_procedure() as usize, HWND(5 as _).into())),
+        );
+
+        drop(thunk);
+
+        // The ATL thunk does not drop the taken context object again.
+        assert_eq!(sequence.get(), 1);
+    }
+
+    #[cfg(all(feature = "debug-checks", debug_assertions, target_pointer_width = "64"))]
+    #[test]
+    #[should_panic(expected = "still the window procedure")]
+    fn test_context_thunk_drop_while_installed() {
+        use crate::sys::core::w;
+        use crate::sys::Win32::UI::WindowsAndMessaging::{
+            CreateWindowExW, SetWindowLongPtrW, GWLP_WNDPROC, HWND_MESSAGE, WINDOW_EX_STYLE, WINDOW_STYLE,
+        };
+
+        let thunk = ContextThunk::for_handler(|_: u32, _: WPARAM, _: LPARAM| LRESULT(0)).unwrap();
+
+        let window = unsafe {
+            CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                w!("STATIC"),
+                None,
+                WINDOW_STYLE::default(),
+                0,
+                0,
+                0,
+                0,
+                HWND_MESSAGE,
+                None,
+                None,
+                None,
+            )
+        }
+        .unwrap();
+
+        unsafe { SetWindowLongPtrW(window, GWLP_WNDPROC, thunk.as_window_procedure() as usize as _) };
+
+        thunk.bind_window(window);
+
+        drop(thunk);
+    }
+
+    #[test]
+    fn test_context_thunk_for_optional_handler() {
+        let thunk = ContextThunk::for_optional_handler(|message: u32, w_param: WPARAM, _: LPARAM| {
+            (message == 2).then_some(LRESULT(w_param.0 as _))
+        })
+        .unwrap();
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 2, WPARAM(3), LPARAM(0)) }.0,
+            3,
+        );
+
+        // Messages that are not handled are passed to the default window procedure.
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 4, WPARAM(3), LPARAM(0)) }.0,
+            DEFAULT_RESULT,
+        );
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "wrong type")]
+    fn test_context_header_wrong_type() {
+        let thunk = ContextThunk::for_handler(|_: u32, _: WPARAM, _: LPARAM| LRESULT(0)).unwrap();
+
+        // `u8` has the same alignment as the closure, so the header is still found at the right offset.
+        unsafe { super::context_header(ptr::from_ref(thunk.context()).cast::<u8>()) };
+    }
+
+    #[test]
+    fn test_context_thunk_drop_context_on_ncdestroy() {
+        use crate::sys::Win32::UI::WindowsAndMessaging::{WM_CLOSE, WM_NCDESTROY};
+        use crate::WindowProcedure;
+
+        let rc = Rc::new(());
+        let captured = rc.clone();
+        let window_procedure = Cell::new(None::<WindowProcedure>);
+        let window_procedure_ref = &window_procedure;
+
+        let thunk = ContextThunk::for_handler(move |message: u32, _: WPARAM, _: LPARAM| {
+            if message == WM_CLOSE {
+                // Destroying the window from a handler delivers `WM_NCDESTROY` during the dispatch.
+                unsafe { window_procedure_ref.get().unwrap()(HWND::default(), WM_NCDESTROY, WPARAM(0), LPARAM(0)) };
+
+                // The handler is still alive.
+                LRESULT(Rc::strong_count(&captured) as _)
+            } else {
+                LRESULT(message as _)
+            }
+        })
+        .unwrap();
+
+        let thunk = unsafe { thunk.drop_context_on_ncdestroy(true) };
+
+        window_procedure.set(Some(thunk.as_window_procedure()));
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), WM_CLOSE, WPARAM(0), LPARAM(0)) }.0,
+            2,
+        );
+
+        // The context object is dropped after the outermost dispatch returns.
+        assert_eq!(Rc::strong_count(&rc), 1);
+        assert!(thunk.try_context().is_none());
+
+        // Following messages are passed to the default window procedure.
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 3, WPARAM(0), LPARAM(0)) }.0,
+            DEFAULT_RESULT,
+        );
+    }
+
+    #[test]
+    fn test_context_thunk_take_context() {
+        let counter = Rc::new(());
+        let cloned = Rc::clone(&counter);
+
+        let mut thunk = ContextThunk::for_handler(move |message: u32, _: WPARAM, _: LPARAM| {
+            _ = &cloned;
+
+            LRESULT(message as _)
+        })
+        .unwrap();
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 2, WPARAM(0), LPARAM(0)) }.0,
+            2,
+        );
+
+        let context = thunk.take_context().unwrap();
+
+        assert!(thunk.take_context().is_none());
+        assert!(thunk.try_context().is_none());
+
+        // Messages are passed to the default window procedure after the context object is taken.
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 2, WPARAM(0), LPARAM(0)) }.0,
+            DEFAULT_RESULT,
+        );
+
+        drop(thunk);
+
+        assert_eq!(Rc::strong_count(&counter), 2);
+
+        drop(context);
+
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn test_context_thunk_leak_context() {
+        let counter = Rc::new(());
+        let cloned = Rc::clone(&counter);
+
+        let thunk = ContextThunk::for_handler(move |message: u32, _: WPARAM, _: LPARAM| {
+            _ = &cloned;
+
+            LRESULT(message as _)
+        })
+        .unwrap();
+
+        let address = ptr::from_ref(thunk.context());
+        let context = thunk.leak_context();
+
+        // The context object is not moved or dropped.
+        assert_eq!(context.cast_const(), address);
+        assert_eq!(Rc::strong_count(&counter), 2);
+
+        unsafe { ptr::drop_in_place(context) };
+
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "can not be taken while dispatching")]
+    fn test_context_thunk_take_context_while_dispatching() {
+        struct Handler {
+            thunk: Cell<*mut ContextThunk<Handler>>,
+        }
+
+        impl crate::WindowHandler for Handler {
+            fn handle(&self, _: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+                // Like a handler that reaches its own thunk, which would leave `self` dangling.
+                _ = unsafe { &mut *self.thunk.get() }.take_context();
+
+                LRESULT(0)
+            }
+        }
+
+        let mut thunk = ContextThunk::for_handler(Handler {
+            thunk: Cell::new(ptr::null_mut()),
+        })
+        .unwrap();
+
+        let thunk_ptr = ptr::addr_of_mut!(thunk);
+
+        thunk.context().thunk.set(thunk_ptr);
+
+        // Window procedures abort on panics, so the dispatch is simulated without one.
+        let header = unsafe { &*ptr::from_ref(thunk.header()) };
+
+        header.dispatch(|| {
+            crate::WindowHandler::handle(unsafe { &*ptr::from_ref(thunk.context()) }, 0, WPARAM(0), LPARAM(0))
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "can not be leaked while dispatching")]
+    fn test_context_thunk_leak_context_while_dispatching() {
+        let thunk = ContextThunk::for_handler(|_: u32, _: WPARAM, _: LPARAM| LRESULT(0)).unwrap();
+
+        // Window procedures abort on panics, so the dispatch is simulated without one.
+        let header = unsafe { &*ptr::from_ref(thunk.header()) };
+
+        header.dispatch(|| thunk.leak_context());
+    }
+
+    #[test]
+    #[should_panic(expected = "taken or dropped")]
+    fn test_context_thunk_leak_taken_context() {
+        let mut thunk = ContextThunk::for_handler(|_: u32, _: WPARAM, _: LPARAM| LRESULT(0)).unwrap();
+
+        thunk.take_context();
+        thunk.leak_context();
+    }
+
+    #[test]
+    fn test_context_thunk_get_mut() {
+        use crate::WindowHandler;
+
+        struct Value(isize);
+
+        impl WindowHandler for Value {
+            fn handle(&self, _: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+                LRESULT(self.0)
+            }
+        }
+
+        let mut thunk = ContextThunk::for_handler(Value(2)).unwrap();
+        let window_procedure = thunk.as_window_procedure();
+
+        assert_eq!(thunk.get_ref().0, 2);
+
+        thunk.get_mut().0 = 3;
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), 0, WPARAM(0), LPARAM(0)) }.0,
+            3,
+        );
+    }
+
+    #[test]
+    fn test_context_thunk_replace_context() {
+        use crate::WindowHandler;
+
+        struct Value(isize);
+
+        impl WindowHandler for Value {
+            fn handle(&self, _: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+                LRESULT(self.0)
+            }
+        }
+
+        let mut thunk = ContextThunk::for_handler(Value(2)).unwrap();
+        let window_procedure = thunk.as_window_procedure();
+        let address = ptr::from_ref(thunk.context());
+
+        assert_eq!(thunk.replace_context(Value(3)).0, 2);
+
+        // The context object is replaced in place.
+        assert_eq!(ptr::from_ref(thunk.context()), address);
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), 0, WPARAM(0), LPARAM(0)) }.0,
+            3,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "mutably borrowed")]
+    fn test_context_thunk_get_ref_while_mutably_borrowed() {
+        let mut thunk = ContextThunk::for_handler(|_: u32, _: WPARAM, _: LPARAM| LRESULT(0)).unwrap();
+
+        core::mem::forget(thunk.get_mut());
+
+        thunk.get_ref();
+    }
+
+    #[test]
+    fn test_context_thunk_map_context() {
+        let counter = Rc::new(());
+        let cloned = Rc::clone(&counter);
+
+        let thunk = ContextThunk::for_handler(move |message: u32, _: WPARAM, _: LPARAM| {
+            _ = &cloned;
+
+            LRESULT(message as _)
+        })
+        .unwrap();
+
+        thunk.bind_window(HWND(3 as _));
+        thunk.set_char_set(CharSet::Ansi);
+
+        let window_procedure = thunk.as_window_procedure();
+
+        let thunk = thunk
+            .map_context(|handler| {
+                move |message: u32, w_param: WPARAM, l_param: LPARAM| LRESULT(handler(message, w_param, l_param).0 * 2)
+            })
+            .unwrap();
+
+        assert_eq!(thunk.as_window_procedure() as usize, window_procedure as usize);
+        assert_eq!(thunk.window(), HWND(3 as _));
+        assert_eq!(thunk.char_set(), CharSet::Ansi);
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), 5, WPARAM(0), LPARAM(0)) }.0,
+            10,
+        );
+
+        assert_eq!(Rc::strong_count(&counter), 2);
+
+        drop(thunk);
+
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+}
@@ -0,0 +1,379 @@
+//! Installing context thunks on windows as they are created, so context objects learn their window handles.
+
+use crate::context::{ContextHeader, ContextThunk};
+use crate::sys::core::PCWSTR;
+use crate::sys::Win32::Foundation::{HINSTANCE, HWND, LPARAM, LRESULT, WPARAM};
+use crate::sys::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DestroyWindow, CREATESTRUCTW, CW_USEDEFAULT, HMENU, WINDOW_EX_STYLE, WINDOW_STYLE, WM_NCCREATE,
+    WM_NCDESTROY,
+};
+use crate::{subclass, UninitAtlThunk, WindowHandler};
+
+/// Context objects that need to know their window handles, see [`ContextThunk::for_window_handler`].
+pub trait SetWindowHandle {
+    /// Called with the bound window before the handler receives
+    /// [`WM_NCCREATE`](https://learn.microsoft.com/en-us/windows/win32/winmsg/wm-nccreate).
+    fn set_window_handle(&self, window: HWND);
+}
+
+/// A window procedure for window classes whose windows are created with [`ContextThunk::creation_parameter`] as the
+/// creation parameter. On [`WM_NCCREATE`](https://learn.microsoft.com/en-us/windows/win32/winmsg/wm-nccreate), it
+/// binds the created window to the thunk, see [`ContextThunk::bind_window`], replaces itself with the window procedure
+/// of the thunk, then forwards the message to it. Messages received before that, or by windows created without a
+/// creation parameter, are passed to
+/// [`DefWindowProcW`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-defwindowprocw).
+///
+/// # Safety
+///
+/// The creation parameter of every window of the class must be either null, or a pointer returned by
+/// [`ContextThunk::creation_parameter`] whose [`ContextThunk`] object outlives the window.
+pub unsafe extern "system" fn creation_window_procedure(
+    window: HWND,
+    message: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> LRESULT {
+    if message == WM_NCCREATE {
+        // `CREATESTRUCTA` has the same layout for the creation parameter.
+        let header = unsafe { (*(l_param.0 as *const CREATESTRUCTW)).lpCreateParams }.cast::<ContextHeader>();
+
+        if let Some(header) = unsafe { header.as_ref() } {
+            let window_procedure = header.thunk_window_procedure();
+
+            header.set_window(window);
+
+            unsafe { subclass::set_window_procedure(window, header.char_set(), window_procedure) };
+
+            return unsafe { window_procedure(window, message, w_param, l_param) };
+        }
+    }
+
+    unsafe { crate::default_window_procedure()(window, message, w_param, l_param) }
+}
+
+unsafe extern "system" fn window_handle_window_procedure<H>(
+    handler: HWND,
+    message: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> LRESULT
+where
+    H: WindowHandler + SetWindowHandle,
+{
+    let handler = handler.0.cast::<H>().cast_const();
+    let header = unsafe { crate::context::context_header(handler) };
+
+    header.dispatch(|| {
+        let handler = unsafe { &*handler };
+
+        if message == WM_NCCREATE {
+            let window = header.window();
+
+            if !window.is_invalid() {
+                handler.set_window_handle(window);
+            }
+        }
+
+        let result = handler.handle(message, w_param, l_param);
+
+        if message == WM_NCDESTROY {
+            header.set_window_destroyed();
+        }
+
+        result
+    })
+}
+
+impl<H> ContextThunk<H>
+where
+    H: WindowHandler + SetWindowHandle,
+{
+    /// Same as [`ContextThunk::for_handler`], but before the handler receives
+    /// [`WM_NCCREATE`](https://learn.microsoft.com/en-us/windows/win32/winmsg/wm-nccreate), passes the bound window to
+    /// [`SetWindowHandle::set_window_handle`]. Windows of classes using [`creation_window_procedure`] are bound
+    /// automatically with [`ContextThunk::creation_parameter`], otherwise the window needs to be bound with
+    /// [`ContextThunk::bind_window`] before it is created.
+    #[track_caller]
+    pub fn for_window_handler(handler: H) -> crate::sys::core::Result<Self> {
+        Ok(unsafe { Self::new_in(UninitAtlThunk::try_new()?, handler, window_handle_window_procedure::<H>) })
+    }
+}
+
+/// A window created by [`WindowHandle::create`], which owns both the window and the [`ContextThunk`] object that
+/// handles its messages.
+///
+/// On drop, the window is destroyed first, so the handler still receives the messages sent during destruction, then
+/// the context object is dropped, and finally the ATL thunk is freed.
+pub struct WindowHandle<H> {
+    window: HWND,
+    thunk: ContextThunk<H>,
+}
+
+impl<H> WindowHandle<H>
+where
+    H: WindowHandler + SetWindowHandle,
+{
+    /// Creates a [`ContextThunk`] object for `handler` with [`ContextThunk::for_window_handler`], then creates a window
+    /// of `class_name` with [`ContextThunk::creation_parameter`] as the creation parameter, so the window is bound to
+    /// the thunk on [`WM_NCCREATE`](https://learn.microsoft.com/en-us/windows/win32/winmsg/wm-nccreate). The window is
+    /// created at the default position with the default size. For more information, see document for
+    /// [`CreateWindowExW`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-createwindowexw).
+    ///
+    /// # Safety
+    ///
+    /// `class_name` must name a window class registered by `instance` whose window procedure is
+    /// [`creation_window_procedure`], and `title` must be either null or a valid null terminated string.
+    #[track_caller]
+    pub unsafe fn create(
+        class_name: PCWSTR,
+        instance: HINSTANCE,
+        title: PCWSTR,
+        style: WINDOW_STYLE,
+        parent: HWND,
+        handler: H,
+    ) -> crate::sys::core::Result<Self> {
+        let thunk = ContextThunk::for_window_handler(handler)?;
+
+        let window = unsafe {
+            CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                class_name,
+                title,
+                style,
+                CW_USEDEFAULT,
+                CW_USEDEFAULT,
+                CW_USEDEFAULT,
+                CW_USEDEFAULT,
+                parent,
+                HMENU::default(),
+                instance,
+                Some(thunk.creation_parameter()),
+            )
+        }?;
+
+        Ok(Self { window, thunk })
+    }
+}
+
+impl<H> WindowHandle<H> {
+    /// Returns the created window.
+    pub fn window(&self) -> HWND {
+        self.window
+    }
+
+    /// Returns a reference to the context object, see [`ContextThunk::context`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the context object has been dropped by [`ContextThunk::drop_context_on_ncdestroy`].
+    pub fn context(&self) -> &H {
+        self.thunk.context()
+    }
+
+    /// Returns the [`ContextThunk`] object that handles messages of the window.
+    pub fn thunk(&self) -> &ContextThunk<H> {
+        &self.thunk
+    }
+}
+
+impl<H> Drop for WindowHandle<H> {
+    fn drop(&mut self) {
+        // The window may have been destroyed already, like by the default handling of `WM_CLOSE`, in which case its
+        // handle may have been reused by an unrelated window. The window procedure can not tell, because the window may
+        // be subclassed on top of the ATL thunk, so destruction is tracked by the thunk instead.
+        if !self.thunk.header().is_window_destroyed() {
+            _ = unsafe { DestroyWindow(self.window) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SetWindowHandle;
+    use crate::sys::core::{w, PCWSTR};
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use crate::sys::Win32::System::LibraryLoader::GetModuleHandleW;
+    use crate::sys::Win32::UI::WindowsAndMessaging::{
+        CreateWindowExW, DefWindowProcW, DestroyWindow, RegisterClassW, UnregisterClassW, HWND_MESSAGE,
+        WINDOW_EX_STYLE, WINDOW_STYLE, WNDCLASSW,
+    };
+    use crate::{CharSet, ContextThunk, WindowHandler};
+    use core::cell::Cell;
+
+    #[derive(Default)]
+    struct Window {
+        window: Cell<HWND>,
+    }
+
+    impl SetWindowHandle for Window {
+        fn set_window_handle(&self, window: HWND) {
+            self.window.set(window);
+        }
+    }
+
+    impl WindowHandler for Window {
+        fn handle(&self, message: u32, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+            unsafe { DefWindowProcW(self.window.get(), message, w_param, l_param) }
+        }
+    }
+
+    #[test]
+    fn test_creation_window_procedure() {
+        let instance = unsafe { GetModuleHandleW(None) }.unwrap();
+        let class_name = w!("atl-thunk-test-creation-window-procedure");
+
+        let class = WNDCLASSW {
+            lpfnWndProc: Some(super::creation_window_procedure),
+            hInstance: instance.into(),
+            lpszClassName: class_name,
+            ..WNDCLASSW::default()
+        };
+
+        assert_ne!(unsafe { RegisterClassW(&class) }, 0);
+
+        let thunk = ContextThunk::for_window_handler(Window::default()).unwrap();
+
+        let window = unsafe {
+            CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                class_name,
+                None,
+                WINDOW_STYLE::default(),
+                0,
+                0,
+                0,
+                0,
+                HWND_MESSAGE,
+                None,
+                instance,
+                Some(thunk.creation_parameter()),
+            )
+        }
+        .unwrap();
+
+        assert_eq!(thunk.context().window.get(), window);
+        assert_eq!(thunk.window(), window);
+
+        assert_eq!(
+            crate::subclass::current_window_procedure(window, CharSet::Wide),
+            thunk.as_window_procedure() as usize,
+        );
+
+        unsafe { DestroyWindow(window) }.unwrap();
+        unsafe { UnregisterClassW(class_name, instance) }.unwrap();
+    }
+
+    #[test]
+    fn test_window_handle_create() {
+        use super::WindowHandle;
+        use crate::sys::Win32::UI::WindowsAndMessaging::{CallWindowProcW, IsWindow, WM_NCDESTROY};
+        use crate::WindowProcedure;
+        use alloc::rc::Rc;
+        use core::mem;
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        static THUNK_PROCEDURE: AtomicUsize = AtomicUsize::new(0);
+
+        unsafe extern "system" fn subclass_procedure(
+            window: HWND,
+            message: u32,
+            w_param: WPARAM,
+            l_param: LPARAM,
+        ) -> LRESULT {
+            let procedure =
+                unsafe { mem::transmute::<usize, WindowProcedure>(THUNK_PROCEDURE.load(Ordering::Relaxed)) };
+
+            unsafe { CallWindowProcW(Some(procedure), window, message, w_param, l_param) }
+        }
+
+        struct CountingWindow {
+            window: Window,
+            destroyed: Rc<Cell<bool>>,
+        }
+
+        impl SetWindowHandle for CountingWindow {
+            fn set_window_handle(&self, window: HWND) {
+                self.window.set_window_handle(window);
+            }
+        }
+
+        impl WindowHandler for CountingWindow {
+            fn handle(&self, message: u32, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+                if message == WM_NCDESTROY {
+                    self.destroyed.set(true);
+                }
+
+                self.window.handle(message, w_param, l_param)
+            }
+        }
+
+        let instance = unsafe { GetModuleHandleW(None) }.unwrap().into();
+        let class_name = w!("atl-thunk-test-window-handle-create");
+
+        let class = WNDCLASSW {
+            lpfnWndProc: Some(super::creation_window_procedure),
+            hInstance: instance,
+            lpszClassName: class_name,
+            ..WNDCLASSW::default()
+        };
+
+        assert_ne!(unsafe { RegisterClassW(&class) }, 0);
+
+        let destroyed = Rc::new(Cell::new(false));
+
+        let handle = unsafe {
+            WindowHandle::create(
+                class_name,
+                instance,
+                w!("title"),
+                WINDOW_STYLE::default(),
+                HWND_MESSAGE,
+                CountingWindow {
+                    window: Window::default(),
+                    destroyed: Rc::clone(&destroyed),
+                },
+            )
+        }
+        .unwrap();
+
+        let window = handle.window();
+
+        assert_eq!(handle.context().window.window.get(), window);
+        assert_eq!(handle.thunk().window(), window);
+
+        // Subclass the window on top of the ATL thunk, which must not keep the window alive after the drop.
+        THUNK_PROCEDURE.store(handle.thunk().as_window_procedure() as usize, Ordering::Relaxed);
+
+        unsafe { crate::subclass::set_window_procedure(window, CharSet::Wide, subclass_procedure) };
+
+        drop(handle);
+
+        // The window is destroyed before the context object is dropped, so the handler receives `WM_NCDESTROY`.
+        assert!(destroyed.get());
+        assert!(!unsafe { IsWindow(window) }.as_bool());
+
+        // Windows destroyed before the drop are not destroyed again.
+        let handle = unsafe {
+            WindowHandle::create(
+                class_name,
+                instance,
+                PCWSTR::null(),
+                WINDOW_STYLE::default(),
+                HWND_MESSAGE,
+                CountingWindow {
+                    window: Window::default(),
+                    destroyed: Rc::new(Cell::new(false)),
+                },
+            )
+        }
+        .unwrap();
+
+        unsafe { DestroyWindow(handle.window()) }.unwrap();
+
+        assert!(handle.thunk().header().is_window_destroyed());
+
+        drop(handle);
+
+        unsafe { UnregisterClassW(class_name, instance) }.unwrap();
+    }
+}
@@ -0,0 +1,113 @@
+//! Default window procedures used wherever the crate needs a harmless window procedure.
+
+use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use crate::sys::Win32::UI::WindowsAndMessaging::DefWindowProcW;
+use crate::{AtlThunk, WindowProcedure};
+use core::ffi::c_void;
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+/// The procedure set by [`set_default_procedure`], null means [`default_window_procedure`].
+static DEFAULT_PROCEDURE: AtomicPtr<c_void> = AtomicPtr::new(ptr::null_mut());
+
+unsafe extern "system" fn def_window_procedure(
+    window: HWND,
+    message: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> LRESULT {
+    unsafe { DefWindowProcW(window, message, w_param, l_param) }
+}
+
+/// Returns a window procedure that calls
+/// [`DefWindowProcW`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-defwindowprocw). Unlike
+/// [`default_procedure`], it is not affected by [`set_default_procedure`], and can be evaluated in constant contexts.
+pub const fn default_window_procedure() -> WindowProcedure {
+    def_window_procedure
+}
+
+/// Returns the window procedure used where the crate needs a harmless default, which is [`default_window_procedure`]
+/// unless changed by [`set_default_procedure`]. It is used by:
+///
+/// - [`AtlThunk::try_new`], as the initial window procedure of new ATL thunks.
+/// - [`AtlThunk::clear_data`].
+/// - [`ContextThunk::take_context`](crate::ContextThunk::take_context), for messages received after the context object
+///   is taken.
+/// - [`AtlThunk::borrowed_closure`], for reentrant messages, which can not be passed to the borrowed closure.
+pub fn default_procedure() -> WindowProcedure {
+    let procedure = DEFAULT_PROCEDURE.load(Ordering::Relaxed);
+
+    if procedure.is_null() {
+        default_window_procedure()
+    } else {
+        unsafe { mem::transmute::<*mut c_void, WindowProcedure>(procedure) }
+    }
+}
+
+/// Changes the window procedure returned by [`default_procedure`], which affects every use listed there from now on,
+/// not only [`AtlThunk::clear_data`]. ATL thunks that already have the previous default installed are not affected.
+pub fn set_default_procedure(window_procedure: WindowProcedure) {
+    DEFAULT_PROCEDURE.store(window_procedure as *mut c_void, Ordering::Relaxed);
+}
+
+impl AtlThunk {
+    /// Replaces the associated data with [`default_procedure`] and `window`, so the ATL thunk still handles messages
+    /// sensibly while it is between handlers. With the default
+    /// [`DefWindowProcW`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-defwindowprocw), the
+    /// messages are processed as if they were sent to `window` directly.
+    pub fn clear_data(&mut self, window: HWND) {
+        self.clear_data_to(default_procedure(), window);
+    }
+
+    /// Same as [`AtlThunk::clear_data`], but installs `window_procedure` instead of [`default_procedure`].
+    pub fn clear_data_to(&mut self, window_procedure: WindowProcedure, window: HWND) {
+        self.set_data(window_procedure, window);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use crate::sys::Win32::UI::WindowsAndMessaging::WM_USER;
+    use crate::test_support::DEFAULT_RESULT;
+    use crate::{AtlThunk, WindowProcedure};
+    use core::sync::atomic::Ordering;
+
+    #[test]
+    fn test_thunk_clear_data() {
+        unsafe extern "system" fn callback(first_parameter: HWND, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+            LRESULT(first_parameter.0 as isize + message as isize)
+        }
+
+        let mut thunk = AtlThunk::try_new_with(callback, HWND(2 as _)).unwrap();
+
+        thunk.clear_data(HWND::default());
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), WM_USER, WPARAM(0), LPARAM(0)) }.0,
+            DEFAULT_RESULT,
+        );
+
+        thunk.clear_data_to(callback, HWND(3 as _));
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 5, WPARAM(0), LPARAM(0)) }.0,
+            8,
+        );
+    }
+
+    #[test]
+    fn test_default_window_procedure() {
+        const DEFAULT_WINDOW_PROCEDURE: WindowProcedure = super::default_window_procedure();
+
+        assert_eq!(
+            unsafe { DEFAULT_WINDOW_PROCEDURE(HWND::default(), WM_USER, WPARAM(0), LPARAM(0)) }.0,
+            DEFAULT_RESULT,
+        );
+
+        // No test changes the default procedure, so it is still `default_window_procedure`. Function addresses are not
+        // compared, because optimized builds may have several copies of the same function.
+        assert!(super::DEFAULT_PROCEDURE.load(Ordering::Relaxed).is_null());
+    }
+}
@@ -0,0 +1,222 @@
+//! Typed handlers for dialog procedures, which follow different return value conventions than window procedures.
+
+use crate::context::{self, CharSet, ContextThunk};
+use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use crate::sys::Win32::UI::WindowsAndMessaging::{
+    DLGPROC, DWLP_MSGRESULT, WINDOW_LONG_PTR_INDEX, WM_CHARTOITEM, WM_COMPAREITEM, WM_CTLCOLORBTN, WM_CTLCOLORDLG,
+    WM_CTLCOLOREDIT, WM_CTLCOLORLISTBOX, WM_CTLCOLORMSGBOX, WM_CTLCOLORSCROLLBAR, WM_CTLCOLORSTATIC, WM_INITDIALOG,
+    WM_QUERYDRAGICON, WM_VKEYTOITEM,
+};
+use crate::Message;
+use core::mem;
+
+/// Result of a dialog message, which is converted to the `INT_PTR` value expected from a
+/// [dialog procedure](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nc-winuser-dlgproc) according to the
+/// message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DialogResult {
+    /// The message is not processed, and is left to the default dialog processing. For `WM_INITDIALOG`, the default
+    /// focus is set, like [`DialogResult::SetFocus`].
+    NotHandled,
+    /// The message is processed.
+    Handled,
+    /// For `WM_INITDIALOG` only: the dialog box sets the keyboard focus to the control in the `WPARAM` parameter,
+    /// which is [`Message::InitDialog::focus`](Message::InitDialog).
+    SetFocus,
+    /// For `WM_INITDIALOG` only: the handler has set the keyboard focus itself, so the dialog box keeps it.
+    KeepFocus,
+    /// The message is processed, and has the specified result. Messages like `WM_CTLCOLORDLG` and `WM_VKEYTOITEM`
+    /// return the result from the dialog procedure directly. For other messages, the result is stored with
+    /// [`DWLP_MSGRESULT`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-setwindowlongptrw),
+    /// which needs the window bound with [`ContextThunk::bind_window`]. Without a bound window, the result is lost.
+    Value(LRESULT),
+}
+
+impl DialogResult {
+    /// Returns whether `message` returns its result from the dialog procedure directly, instead of through
+    /// `DWLP_MSGRESULT`.
+    fn returns_directly(message: u32) -> bool {
+        matches!(
+            message,
+            WM_CHARTOITEM
+                | WM_COMPAREITEM
+                | WM_CTLCOLORBTN
+                | WM_CTLCOLORDLG
+                | WM_CTLCOLOREDIT
+                | WM_CTLCOLORLISTBOX
+                | WM_CTLCOLORMSGBOX
+                | WM_CTLCOLORSCROLLBAR
+                | WM_CTLCOLORSTATIC
+                | WM_INITDIALOG
+                | WM_QUERYDRAGICON
+                | WM_VKEYTOITEM
+        )
+    }
+
+    /// Converts the result to the value returned from the dialog procedure for `message`. `window` receives the
+    /// `DWLP_MSGRESULT` value if needed.
+    fn into_int_ptr(self, message: u32, window: HWND, char_set: CharSet) -> isize {
+        debug_assert!(
+            message == WM_INITDIALOG || !matches!(self, Self::SetFocus | Self::KeepFocus),
+            "`DialogResult::SetFocus` and `DialogResult::KeepFocus` are only for `WM_INITDIALOG`",
+        );
+
+        match self {
+            Self::NotHandled => isize::from(message == WM_INITDIALOG),
+            Self::Handled | Self::SetFocus => 1,
+            Self::KeepFocus => 0,
+            Self::Value(result) => {
+                if Self::returns_directly(message) {
+                    result.0
+                } else {
+                    if !window.is_invalid() {
+                        unsafe { set_message_result(window, char_set, result) };
+                    }
+
+                    1
+                }
+            }
+        }
+    }
+}
+
+unsafe fn set_message_result(window: HWND, char_set: CharSet, result: LRESULT) {
+    let index = WINDOW_LONG_PTR_INDEX(DWLP_MSGRESULT as _);
+
+    #[cfg(target_pointer_width = "64")]
+    unsafe {
+        use crate::sys::Win32::UI::WindowsAndMessaging::{SetWindowLongPtrA, SetWindowLongPtrW};
+
+        match char_set {
+            CharSet::Wide => SetWindowLongPtrW(window, index, result.0),
+            CharSet::Ansi => SetWindowLongPtrA(window, index, result.0),
+        }
+    };
+
+    #[cfg(target_pointer_width = "32")]
+    unsafe {
+        use crate::sys::Win32::UI::WindowsAndMessaging::{SetWindowLongA, SetWindowLongW};
+
+        match char_set {
+            CharSet::Wide => SetWindowLongW(window, index, result.0 as _),
+            CharSet::Ansi => SetWindowLongA(window, index, result.0 as _),
+        }
+    };
+}
+
+/// Handler of decoded dialog messages, see [`ContextThunk::for_dialog_handler`]. See
+/// [`WindowHandler`](crate::WindowHandler) for reentrancy and panic behaviors, which also apply to this trait.
+pub trait DialogHandler {
+    /// Handles a decoded dialog message.
+    fn handle(&self, message: Message) -> DialogResult;
+}
+
+impl<F> DialogHandler for F
+where
+    F: Fn(Message) -> DialogResult,
+{
+    fn handle(&self, message: Message) -> DialogResult {
+        self(message)
+    }
+}
+
+unsafe extern "system" fn dialog_handler_procedure<H>(
+    handler: HWND,
+    message: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> LRESULT
+where
+    H: DialogHandler,
+{
+    let handler = handler.0.cast::<H>().cast_const();
+    let header = unsafe { context::context_header(handler) };
+    let result = header.dispatch(|| unsafe { (*handler).handle(Message::decode(message, w_param, l_param)) });
+
+    LRESULT(result.into_int_ptr(message, header.window(), header.char_set()))
+}
+
+impl<H> ContextThunk<H>
+where
+    H: DialogHandler,
+{
+    /// Creates a new [`ContextThunk`] object that owns `handler`, and dispatches decoded messages to it as a dialog
+    /// procedure, see [`ContextThunk::as_dialog_procedure`]. Unlike window procedures, dialog procedures leave
+    /// unprocessed messages to the dialog box instead of calling a default procedure.
+    #[track_caller]
+    pub fn for_dialog_handler(handler: H) -> crate::sys::core::Result<Self> {
+        unsafe { Self::try_new_with_procedure(handler, dialog_handler_procedure::<H>) }
+    }
+
+    /// Returns the wrapped procedure as a [`DLGPROC`] value, which is always [`Some`]. The returned function pointer
+    /// has the same validity requirements as the one returned by [`ContextThunk::as_window_procedure`].
+    pub fn as_dialog_procedure(&self) -> DLGPROC {
+        // Window procedures and dialog procedures have the same signature, since `LRESULT` is `INT_PTR`.
+        Some(unsafe {
+            mem::transmute::<
+                unsafe extern "system" fn(HWND, u32, WPARAM, LPARAM) -> LRESULT,
+                unsafe extern "system" fn(HWND, u32, WPARAM, LPARAM) -> isize,
+            >(self.as_window_procedure())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DialogResult;
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use crate::sys::Win32::UI::WindowsAndMessaging::{WM_COMMAND, WM_CTLCOLORDLG, WM_INITDIALOG, WM_USER};
+    use crate::{ContextThunk, Message};
+
+    #[test]
+    fn test_context_thunk_for_dialog_handler() {
+        let thunk = ContextThunk::for_dialog_handler(|message| match message {
+            Message::InitDialog { parameter, .. } => {
+                if parameter.0 == 0 {
+                    DialogResult::KeepFocus
+                } else {
+                    DialogResult::SetFocus
+                }
+            }
+            Message::Command { .. } => DialogResult::Handled,
+            Message::Other {
+                message: WM_CTLCOLORDLG,
+                ..
+            } => DialogResult::Value(LRESULT(7)),
+            Message::Other { message: WM_USER, .. } => DialogResult::Value(LRESULT(9)),
+            _ => DialogResult::NotHandled,
+        })
+        .unwrap();
+
+        let dialog_procedure = thunk.as_dialog_procedure().unwrap();
+
+        let cases = [
+            (WM_INITDIALOG, LPARAM(0), 0),
+            (WM_INITDIALOG, LPARAM(1), 1),
+            (WM_COMMAND, LPARAM(0), 1),
+            // Returned directly.
+            (WM_CTLCOLORDLG, LPARAM(0), 7),
+            // Stored with `DWLP_MSGRESULT`, which is skipped without a bound window.
+            (WM_USER, LPARAM(0), 1),
+            (WM_USER + 1, LPARAM(0), 0),
+        ];
+
+        for (message, l_param, expected) in cases {
+            assert_eq!(
+                unsafe { dialog_procedure(HWND::default(), message, WPARAM(0), l_param) },
+                expected,
+            );
+        }
+    }
+
+    #[test]
+    fn test_dialog_result_not_handled_init_dialog() {
+        let thunk = ContextThunk::for_dialog_handler(|_| DialogResult::NotHandled).unwrap();
+
+        // Leaving `WM_INITDIALOG` unprocessed still sets the default focus.
+        assert_eq!(
+            unsafe { thunk.as_dialog_procedure().unwrap()(HWND::default(), WM_INITDIALOG, WPARAM(0), LPARAM(0)) },
+            1,
+        );
+    }
+}
@@ -0,0 +1,333 @@
+//! Window procedures that dispatch messages through a table of message handlers.
+
+use crate::context::{self, ContextThunk};
+use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Handler of a single window message, which receives the context object of the [`ContextThunk`].
+pub type MessageHandler<T> = fn(&T, WPARAM, LPARAM) -> LRESULT;
+
+/// Builder of a message dispatch table.
+///
+/// Like [`WindowHandler`](crate::WindowHandler), message handlers receive the context object through shared
+/// references because window procedures are reentrant, states that need to be updated should use interior mutability.
+pub struct DispatchTable<T> {
+    entries: Vec<(u32, MessageHandler<T>)>,
+}
+
+impl<T> DispatchTable<T> {
+    /// Creates a new empty [`DispatchTable`] object.
+    pub fn new() -> Self {
+        Self { entries: Vec::new() }
+    }
+
+    /// Creates a new empty [`DispatchTable`] object with space for `capacity` registrations.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Registers `handler` for `message`. If a handler has been registered for the same message, it is replaced.
+    #[must_use]
+    pub fn on(mut self, message: u32, handler: MessageHandler<T>) -> Self {
+        self.entries.push((message, handler));
+
+        self
+    }
+
+    /// Sorts the registered handlers into a [`DispatchMap`], which can be shared by multiple thunks.
+    pub fn build(self) -> DispatchMap<T> {
+        let mut entries = self.entries;
+
+        // Stable sorting keeps registration order of duplicated messages, so the last one can be kept.
+        entries.sort_by_key(|&(message, _)| message);
+        entries.reverse();
+        entries.dedup_by_key(|&mut (message, _)| message);
+        entries.reverse();
+
+        DispatchMap {
+            entries: entries.into(),
+        }
+    }
+
+    /// Creates a new [`ContextThunk`] object that owns `context`, and dispatches messages with registered handlers,
+    /// see [`DispatchMap::build_thunk`].
+    #[track_caller]
+    pub fn build_thunk(self, context: T) -> crate::sys::core::Result<ContextThunk<Dispatcher<T>>> {
+        self.build().build_thunk(context)
+    }
+}
+
+// Message handlers are function pointers, so cloning does not require `T: Clone`.
+impl<T> Clone for DispatchTable<T> {
+    fn clone(&self) -> Self {
+        Self {
+            entries: self.entries.clone(),
+        }
+    }
+}
+
+impl<T> Default for DispatchTable<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Message handlers sorted by message, which are looked up with binary search, see [`DispatchTable::build`]. Cloning
+/// only increments a reference count, so the same handlers can be used by multiple thunks with different contexts.
+pub struct DispatchMap<T> {
+    entries: Arc<[(u32, MessageHandler<T>)]>,
+}
+
+impl<T> DispatchMap<T> {
+    /// Returns the handler registered for `message`.
+    pub fn get(&self, message: u32) -> Option<MessageHandler<T>> {
+        self.entries
+            .binary_search_by_key(&message, |&(message, _)| message)
+            .ok()
+            .map(|index| self.entries[index].1)
+    }
+
+    /// Returns the number of messages that have handlers.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether no message has a handler.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Creates a new [`ContextThunk`] object that owns `context`, and dispatches messages with the handlers. Other
+    /// messages are passed to
+    /// [`DefWindowProcW`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-defwindowprocw), which
+    /// needs the window bound with [`ContextThunk::bind_window`].
+    #[track_caller]
+    pub fn build_thunk(&self, context: T) -> crate::sys::core::Result<ContextThunk<Dispatcher<T>>> {
+        let dispatcher = Dispatcher {
+            map: self.clone(),
+            context,
+        };
+
+        unsafe { ContextThunk::try_new_with_procedure(dispatcher, dispatcher_window_procedure::<T>) }
+    }
+}
+
+impl<T> Default for DispatchMap<T> {
+    fn default() -> Self {
+        DispatchTable::new().build()
+    }
+}
+
+impl<T> Clone for DispatchMap<T> {
+    fn clone(&self) -> Self {
+        Self {
+            entries: Arc::clone(&self.entries),
+        }
+    }
+}
+
+/// Context object that dispatches messages through a table of message handlers, see [`DispatchMap::build_thunk`].
+pub struct Dispatcher<T> {
+    map: DispatchMap<T>,
+    context: T,
+}
+
+impl<T> Dispatcher<T> {
+    /// Returns the handler registered for `message`.
+    pub fn get(&self, message: u32) -> Option<MessageHandler<T>> {
+        self.map.get(message)
+    }
+
+    /// Returns the message handlers.
+    pub fn map(&self) -> &DispatchMap<T> {
+        &self.map
+    }
+
+    /// Returns the context object passed to message handlers.
+    pub fn context(&self) -> &T {
+        &self.context
+    }
+}
+
+unsafe extern "system" fn dispatcher_window_procedure<T>(
+    dispatcher: HWND,
+    message: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> LRESULT {
+    let dispatcher = dispatcher.0.cast::<Dispatcher<T>>().cast_const();
+    let header = unsafe { context::context_header(dispatcher) };
+
+    // Even the map lookup borrows the context object, so it must be inside the dispatch, which checks the borrow flag.
+    header.dispatch(|| {
+        let dispatcher = unsafe { &*dispatcher };
+
+        match dispatcher.get(message) {
+            None => header.default_process(message, w_param, l_param),
+            Some(handler) => handler(&dispatcher.context, w_param, l_param),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DispatchMap, DispatchTable};
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use crate::sys::Win32::UI::WindowsAndMessaging::{WM_CLOSE, WM_SIZE, WM_USER};
+    use crate::test_support::DEFAULT_RESULT;
+    use core::cell::Cell;
+
+    #[test]
+    fn test_dispatch_table_build_thunk() {
+        let thunk = DispatchTable::new()
+            .on(WM_SIZE, |count: &Cell<u32>, _, _| {
+                count.set(count.get() + 1);
+
+                LRESULT(1)
+            })
+            .on(WM_USER, |_, w_param, _| LRESULT(w_param.0 as _))
+            .on(WM_CLOSE, |_, _, _| LRESULT(2))
+            .on(WM_SIZE, |count, _, _| {
+                count.set(count.get() + 2);
+
+                LRESULT(3)
+            })
+            .build_thunk(Cell::new(0))
+            .unwrap();
+
+        let window_procedure = thunk.as_window_procedure();
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_SIZE, WPARAM(0), LPARAM(0)) }.0,
+            3,
+        );
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_USER, WPARAM(5), LPARAM(0)) }.0,
+            5,
+        );
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_CLOSE, WPARAM(0), LPARAM(0)) }.0,
+            2,
+        );
+
+        // Not registered messages are passed to the default window procedure.
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_USER + 1, WPARAM(0), LPARAM(0)) }.0,
+            DEFAULT_RESULT,
+        );
+
+        assert_eq!(thunk.context().context().get(), 2);
+        assert!(thunk.context().get(WM_USER + 1).is_none());
+    }
+
+    #[test]
+    fn test_context_thunk_reinterpret_context() {
+        let thunk = DispatchTable::new()
+            .on(WM_USER, |value: &u32, _, _| LRESULT(*value as _))
+            .build_thunk(u32::MAX)
+            .unwrap();
+
+        let thunk = unsafe { thunk.reinterpret_context::<super::Dispatcher<i32>>() };
+
+        assert_eq!(*thunk.context().context(), -1);
+
+        // The window procedure still reads the context object as `u32`.
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), WM_USER, WPARAM(0), LPARAM(0)) }.0,
+            u32::MAX as isize,
+        );
+    }
+
+    #[test]
+    fn test_dispatch_table_clone() {
+        struct Context(isize);
+
+        let table = DispatchTable::new().on(WM_USER, |context: &Context, _, _| LRESULT(context.0));
+
+        let thunk_1 = table.clone().build_thunk(Context(3)).unwrap();
+        let thunk_2 = table
+            .on(WM_CLOSE, |_, _, _| LRESULT(1))
+            .build_thunk(Context(5))
+            .unwrap();
+
+        assert_eq!(
+            unsafe { thunk_1.as_window_procedure()(HWND::default(), WM_USER, WPARAM(0), LPARAM(0)) }.0,
+            3,
+        );
+
+        assert_eq!(
+            unsafe { thunk_2.as_window_procedure()(HWND::default(), WM_USER, WPARAM(0), LPARAM(0)) }.0,
+            5,
+        );
+
+        // Registrations added to the original table do not affect the clone.
+        assert!(thunk_1.context().get(WM_CLOSE).is_none());
+        assert!(thunk_2.context().get(WM_CLOSE).is_some());
+    }
+
+    #[test]
+    fn test_dispatch_default() {
+        #[derive(Default)]
+        struct State {
+            table: DispatchTable<u32>,
+            map: DispatchMap<u32>,
+        }
+
+        let state = State::default();
+
+        assert!(state.table.build().is_empty());
+        assert!(state.map.get(WM_USER).is_none());
+    }
+
+    #[test]
+    fn test_dispatch_map_shared() {
+        let map = DispatchTable::with_capacity(2)
+            .on(WM_USER, |value: &isize, _, _| LRESULT(*value))
+            .on(WM_CLOSE, |value, _, _| LRESULT(-*value))
+            .build();
+
+        assert_eq!(map.len(), 2);
+
+        let thunk_1 = map.build_thunk(3).unwrap();
+        let thunk_2 = map.build_thunk(5).unwrap();
+
+        for (thunk, value) in [(&thunk_1, 3), (&thunk_2, 5)] {
+            let window_procedure = thunk.as_window_procedure();
+
+            assert_eq!(
+                unsafe { window_procedure(HWND::default(), WM_USER, WPARAM(0), LPARAM(0)) }.0,
+                value,
+            );
+
+            assert_eq!(
+                unsafe { window_procedure(HWND::default(), WM_CLOSE, WPARAM(0), LPARAM(0)) }.0,
+                -value,
+            );
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_dispatcher_while_mutably_borrowed() {
+        crate::test_support::assert_aborts("dispatch::tests::test_dispatcher_while_mutably_borrowed", || {
+            use crate::sys::Win32::UI::WindowsAndMessaging::WM_SETTEXT;
+
+            let mut thunk = DispatchTable::new()
+                .on(WM_USER, |_: &u32, _, _| LRESULT(1))
+                .build_thunk(0)
+                .unwrap();
+
+            let window_procedure = thunk.as_window_procedure();
+            let _context = thunk.get_mut();
+
+            // Like `SetWindowTextW` called while the context object is mutably borrowed, even though the message is not
+            // registered.
+            unsafe { window_procedure(HWND::default(), WM_SETTEXT, WPARAM(0), LPARAM(0)) };
+        });
+    }
+}
@@ -0,0 +1,87 @@
+//! Type erased handlers, so thunks of different handler types can share a single type.
+
+use crate::context::ContextThunk;
+use crate::sys::Win32::Foundation::{LPARAM, LRESULT, WPARAM};
+use crate::WindowHandler;
+use alloc::boxed::Box;
+use core::cell::UnsafeCell;
+
+/// A boxed [`WindowHandler`] trait object, see [`ContextThunk::from_dyn`]. For handlers that are not boxed already,
+/// [`ContextThunk::into_dyn`] avoids both the extra allocation and the dynamic dispatch.
+pub struct DynHandler<'a> {
+    handler: UnsafeCell<Box<dyn WindowHandler + 'a>>,
+}
+
+impl WindowHandler for DynHandler<'_> {
+    fn handle(&self, message: u32, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+        unsafe { &*self.handler.get() }.handle(message, w_param, l_param)
+    }
+}
+
+impl<'a> ContextThunk<DynHandler<'a>> {
+    /// Creates a new [`ContextThunk`] object that owns `handler`, and dispatches messages to it through its vtable.
+    /// Unlike [`ContextThunk::for_handler`], thunks of different handler types have the same type, at the cost of a
+    /// dynamic dispatch per message.
+    #[track_caller]
+    pub fn from_dyn(handler: Box<dyn WindowHandler + 'a>) -> crate::sys::core::Result<Self> {
+        Self::for_handler(DynHandler {
+            handler: UnsafeCell::new(handler),
+        })
+    }
+
+    /// Replaces the handler with `handler`, and drops the previous one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called while the window procedure is dispatching a message, since the previous handler may still be
+    /// running.
+    pub fn set_dyn(&mut self, handler: Box<dyn WindowHandler + 'a>) {
+        assert!(
+            !self.header().is_dispatching(),
+            "the handler can not be replaced while dispatching a message",
+        );
+
+        // No dispatch is in progress, so the handler is not borrowed.
+        drop(unsafe { self.context().handler.get().replace(handler) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use crate::ContextThunk;
+    use alloc::boxed::Box;
+    use alloc::rc::Rc;
+
+    #[test]
+    fn test_context_thunk_from_dyn() {
+        let mut thunks = [
+            ContextThunk::from_dyn(Box::new(|message: u32, _: WPARAM, _: LPARAM| LRESULT(message as isize))).unwrap(),
+            ContextThunk::from_dyn(Box::new(|message: u32, _: WPARAM, _: LPARAM| {
+                LRESULT(-(message as isize))
+            }))
+            .unwrap(),
+        ];
+
+        let call = |thunk: &ContextThunk<_>| {
+            unsafe { thunk.as_window_procedure()(HWND::default(), 2, WPARAM(0), LPARAM(0)) }.0
+        };
+
+        assert_eq!(call(&thunks[0]), 2);
+        assert_eq!(call(&thunks[1]), -2);
+
+        let rc = Rc::new(());
+        let captured = rc.clone();
+
+        thunks[0].set_dyn(Box::new(move |message: u32, _: WPARAM, _: LPARAM| {
+            LRESULT(message as isize * Rc::strong_count(&captured) as isize)
+        }));
+
+        assert_eq!(call(&thunks[0]), 4);
+
+        // The replaced handler is dropped.
+        thunks[0].set_dyn(Box::new(|_: u32, _: WPARAM, _: LPARAM| LRESULT(0)));
+
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+}
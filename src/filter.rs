@@ -0,0 +1,450 @@
+//! Message filtering for handlers that are only interested in a few messages.
+
+#[cfg(feature = "alloc")]
+use crate::context::{self, ContextThunk};
+#[cfg(feature = "alloc")]
+use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use crate::sys::Win32::UI::WindowsAndMessaging::{
+    WM_CLOSE, WM_CREATE, WM_DESTROY, WM_ERASEBKGND, WM_KEYFIRST, WM_KEYLAST, WM_MOUSEFIRST, WM_MOUSELAST, WM_NCCREATE,
+    WM_NCDESTROY, WM_NCMOUSEHOVER, WM_NCMOUSEMOVE, WM_NCPAINT, WM_NCXBUTTONDBLCLK, WM_PAINT, WM_PRINT, WM_PRINTCLIENT,
+    WM_QUIT, WM_SYNCPAINT,
+};
+#[cfg(feature = "alloc")]
+use crate::WindowHandler;
+use core::ops::{BitAnd, BitOr, BitOrAssign};
+
+/// Messages below this value are looked up with a bit set, other ones are looked up with a linear scan.
+const SMALL_MESSAGE_LIMIT: u32 = 0x400;
+
+const WORD_BITS: u32 = u64::BITS;
+
+/// A set of message categories, see [`MessageFilter::from_categories`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MessageCategories(u32);
+
+impl MessageCategories {
+    /// Mouse input messages of both the client area and the nonclient area, including mouse hovering and leaving
+    /// messages.
+    pub const MOUSE: Self = Self(1 << 0);
+
+    /// Keyboard input messages, from `WM_KEYFIRST` to `WM_KEYLAST`.
+    pub const KEYBOARD: Self = Self(1 << 1);
+
+    /// Painting messages, like `WM_PAINT`, `WM_NCPAINT`, `WM_ERASEBKGND` and `WM_PRINTCLIENT`.
+    pub const PAINT: Self = Self(1 << 2);
+
+    /// Window lifetime messages, like `WM_NCCREATE`, `WM_CREATE`, `WM_CLOSE`, `WM_DESTROY` and `WM_NCDESTROY`.
+    pub const LIFETIME: Self = Self(1 << 3);
+
+    /// Both [`MessageCategories::MOUSE`] and [`MessageCategories::KEYBOARD`].
+    pub const INPUT: Self = Self::MOUSE.union(Self::KEYBOARD);
+
+    /// Returns an empty set.
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Returns the set of all categories.
+    pub const fn all() -> Self {
+        Self::INPUT.union(Self::PAINT).union(Self::LIFETIME)
+    }
+
+    /// Returns the categories `message` belongs to, which is empty if `message` is not in any category.
+    pub const fn of(message: u32) -> Self {
+        let mut result = Self::empty();
+        let mut i = 0;
+
+        while i < CATEGORY_RANGES.len() {
+            let (categories, first, last) = CATEGORY_RANGES[i];
+
+            if first <= message && message <= last {
+                result = result.union(categories);
+            }
+
+            i += 1;
+        }
+
+        result
+    }
+
+    /// Returns the underlying bits.
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Returns whether no category is in the set.
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns whether all categories in `other` are in the set.
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns whether any category in `other` is in the set.
+    pub const fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// Returns the categories in either set.
+    #[must_use]
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl BitOr for MessageCategories {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        self.union(rhs)
+    }
+}
+
+impl BitOrAssign for MessageCategories {
+    fn bitor_assign(&mut self, rhs: Self) {
+        *self = self.union(rhs);
+    }
+}
+
+impl BitAnd for MessageCategories {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        Self(self.0 & rhs.0)
+    }
+}
+
+/// Inclusive message ranges of each category. All of them are below [`SMALL_MESSAGE_LIMIT`], so filters created from
+/// categories only need the bit set.
+const CATEGORY_RANGES: &[(MessageCategories, u32, u32)] = &[
+    (MessageCategories::MOUSE, WM_NCMOUSEMOVE, WM_NCXBUTTONDBLCLK),
+    (MessageCategories::MOUSE, WM_MOUSEFIRST, WM_MOUSELAST),
+    // `WM_NCMOUSEHOVER`, `WM_MOUSEHOVER`, `WM_NCMOUSELEAVE` and `WM_MOUSELEAVE`.
+    (MessageCategories::MOUSE, WM_NCMOUSEHOVER, WM_NCMOUSEHOVER + 3),
+    (MessageCategories::KEYBOARD, WM_KEYFIRST, WM_KEYLAST),
+    (MessageCategories::PAINT, WM_PAINT, WM_PAINT),
+    (MessageCategories::PAINT, WM_ERASEBKGND, WM_ERASEBKGND),
+    (MessageCategories::PAINT, WM_NCPAINT, WM_NCPAINT),
+    (MessageCategories::PAINT, WM_SYNCPAINT, WM_SYNCPAINT),
+    (MessageCategories::PAINT, WM_PRINT, WM_PRINTCLIENT),
+    (MessageCategories::LIFETIME, WM_CREATE, WM_DESTROY),
+    (MessageCategories::LIFETIME, WM_CLOSE, WM_CLOSE),
+    (MessageCategories::LIFETIME, WM_QUIT, WM_QUIT),
+    (MessageCategories::LIFETIME, WM_NCCREATE, WM_NCDESTROY),
+];
+
+/// A set of window messages that can be checked quickly.
+#[derive(Clone, Copy, Debug)]
+pub struct MessageFilter {
+    small_messages: [u64; (SMALL_MESSAGE_LIMIT / WORD_BITS) as usize],
+    messages: &'static [u32],
+}
+
+impl MessageFilter {
+    /// Creates a new [`MessageFilter`] object that contains `messages`.
+    pub const fn new(messages: &'static [u32]) -> Self {
+        let mut small_messages = [0; (SMALL_MESSAGE_LIMIT / WORD_BITS) as usize];
+        let mut i = 0;
+
+        while i < messages.len() {
+            let message = messages[i];
+
+            if message < SMALL_MESSAGE_LIMIT {
+                small_messages[(message / WORD_BITS) as usize] |= 1 << (message % WORD_BITS);
+            }
+
+            i += 1;
+        }
+
+        Self {
+            small_messages,
+            messages,
+        }
+    }
+
+    /// Creates a new [`MessageFilter`] object that contains all messages in `categories`.
+    pub const fn from_categories(categories: MessageCategories) -> Self {
+        Self::new(&[]).with_categories(categories)
+    }
+
+    /// Adds all messages in `categories` to the set.
+    #[must_use]
+    pub const fn with_categories(mut self, categories: MessageCategories) -> Self {
+        let mut i = 0;
+
+        while i < CATEGORY_RANGES.len() {
+            let (range_categories, first, last) = CATEGORY_RANGES[i];
+
+            if categories.intersects(range_categories) {
+                let mut message = first;
+
+                while message <= last {
+                    self.small_messages[(message / WORD_BITS) as usize] |= 1 << (message % WORD_BITS);
+                    message += 1;
+                }
+            }
+
+            i += 1;
+        }
+
+        self
+    }
+
+    /// Returns whether `message` is in the set.
+    #[inline]
+    pub fn contains(&self, message: u32) -> bool {
+        if message < SMALL_MESSAGE_LIMIT {
+            self.small_messages[(message / WORD_BITS) as usize] & (1 << (message % WORD_BITS)) != 0
+        } else {
+            self.messages.contains(&message)
+        }
+    }
+}
+
+/// A [`WindowHandler`] that only receives messages in a [`MessageFilter`], see
+/// [`ContextThunk::from_filtered_handler`].
+#[cfg(feature = "alloc")]
+pub struct FilteredHandler<H> {
+    filter: MessageFilter,
+    handler: H,
+}
+
+#[cfg(feature = "alloc")]
+impl<H> FilteredHandler<H> {
+    /// Returns the message filter.
+    pub fn filter(&self) -> &MessageFilter {
+        &self.filter
+    }
+
+    /// Returns the wrapped handler.
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+}
+
+#[cfg(feature = "alloc")]
+unsafe extern "system" fn filtered_window_procedure<H>(
+    filtered: HWND,
+    message: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> LRESULT
+where
+    H: WindowHandler,
+{
+    let filtered = filtered.0.cast::<FilteredHandler<H>>().cast_const();
+    let header = unsafe { context::context_header(filtered) };
+
+    // The filter is part of the context object, so it is only read inside the dispatch.
+    header.dispatch(|| {
+        let filtered = unsafe { &*filtered };
+
+        if filtered.filter.contains(message) {
+            filtered.handler.handle(message, w_param, l_param)
+        } else {
+            header.default_process(message, w_param, l_param)
+        }
+    })
+}
+
+#[cfg(feature = "alloc")]
+impl<H> ContextThunk<FilteredHandler<H>>
+where
+    H: WindowHandler,
+{
+    /// Creates a new [`ContextThunk`] object that dispatches messages in `interested` to `handler`. Other messages are
+    /// passed to [`DefWindowProcW`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-defwindowprocw)
+    /// without calling the handler, which needs the window bound with [`ContextThunk::bind_window`].
+    ///
+    /// Messages below `WM_USER` are checked with a bit set, other messages are checked by scanning `interested`.
+    #[track_caller]
+    pub fn from_filtered_handler(interested: &'static [u32], handler: H) -> crate::sys::core::Result<Self> {
+        Self::from_message_filter(MessageFilter::new(interested), handler)
+    }
+
+    /// Same as [`ContextThunk::from_filtered_handler`], but subscribes to all messages in `categories`.
+    ///
+    /// Messages are mapped to categories when the filter is created, so checking incoming messages only costs a bit
+    /// set lookup.
+    #[track_caller]
+    pub fn from_category_handler(categories: MessageCategories, handler: H) -> crate::sys::core::Result<Self> {
+        Self::from_message_filter(MessageFilter::from_categories(categories), handler)
+    }
+
+    /// Same as [`ContextThunk::from_filtered_handler`], but uses a prebuilt `filter`, which can be created with
+    /// [`MessageFilter::with_categories`] to combine explicit messages and message categories.
+    #[track_caller]
+    pub fn from_message_filter(filter: MessageFilter, handler: H) -> crate::sys::core::Result<Self> {
+        let filtered = FilteredHandler { filter, handler };
+
+        unsafe { Self::try_new_with_procedure(filtered, filtered_window_procedure::<H>) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MessageCategories, MessageFilter};
+    use crate::sys::Win32::UI::WindowsAndMessaging::{
+        WM_CHAR, WM_CLOSE, WM_COMMAND, WM_ERASEBKGND, WM_KEYDOWN, WM_LBUTTONDOWN, WM_MOUSEMOVE, WM_NCDESTROY,
+        WM_NCLBUTTONDOWN, WM_PAINT, WM_SIZE, WM_USER,
+    };
+
+    #[test]
+    fn test_message_filter() {
+        static FILTER: MessageFilter = MessageFilter::new(&[0, 63, 64, 0x3FF, 0x400, 0xC000]);
+
+        for message in [0, 63, 64, 0x3FF, 0x400, 0xC000] {
+            assert!(FILTER.contains(message));
+        }
+
+        for message in [1, 62, 65, 0x3FE, 0x401, 0xBFFF, u32::MAX] {
+            assert!(!FILTER.contains(message));
+        }
+    }
+
+    #[test]
+    fn test_message_categories_of() {
+        const WM_MOUSEHOVER: u32 = 0x2A1;
+        const WM_MOUSELEAVE: u32 = 0x2A3;
+
+        for message in [
+            WM_MOUSEMOVE,
+            WM_LBUTTONDOWN,
+            WM_NCLBUTTONDOWN,
+            WM_MOUSEHOVER,
+            WM_MOUSELEAVE,
+        ] {
+            assert_eq!(MessageCategories::of(message), MessageCategories::MOUSE);
+        }
+
+        for message in [WM_KEYDOWN, WM_CHAR] {
+            assert_eq!(MessageCategories::of(message), MessageCategories::KEYBOARD);
+        }
+
+        for message in [WM_PAINT, WM_ERASEBKGND] {
+            assert_eq!(MessageCategories::of(message), MessageCategories::PAINT);
+        }
+
+        for message in [WM_CLOSE, WM_NCDESTROY] {
+            assert_eq!(MessageCategories::of(message), MessageCategories::LIFETIME);
+        }
+
+        for message in [WM_SIZE, WM_COMMAND, WM_USER, u32::MAX] {
+            assert!(MessageCategories::of(message).is_empty());
+        }
+
+        assert_eq!(
+            MessageCategories::MOUSE | MessageCategories::KEYBOARD,
+            MessageCategories::INPUT
+        );
+
+        assert!(MessageCategories::all().contains(MessageCategories::INPUT | MessageCategories::LIFETIME));
+        assert!((MessageCategories::INPUT & MessageCategories::PAINT).is_empty());
+    }
+
+    #[test]
+    fn test_message_filter_from_categories() {
+        static FILTER: MessageFilter =
+            MessageFilter::from_categories(MessageCategories::INPUT).with_categories(MessageCategories::LIFETIME);
+
+        // The filter agrees with `MessageCategories::of` on all messages that can be in categories.
+        for message in 0..0x400 {
+            assert_eq!(
+                FILTER.contains(message),
+                MessageCategories::of(message).intersects(MessageCategories::INPUT | MessageCategories::LIFETIME),
+            );
+        }
+
+        assert!(!MessageFilter::from_categories(MessageCategories::empty()).contains(WM_PAINT));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_context_thunk_from_filtered_handler() {
+        use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+        use crate::sys::Win32::UI::WindowsAndMessaging::{WM_SIZE, WM_USER};
+        use crate::test_support::DEFAULT_RESULT;
+        use crate::ContextThunk;
+        use core::cell::Cell;
+
+        let count = Cell::new(0);
+
+        let thunk =
+            ContextThunk::from_filtered_handler(&[WM_SIZE, WM_USER + 1], |message: u32, _: WPARAM, _: LPARAM| {
+                count.set(count.get() + 1);
+
+                LRESULT(message as isize)
+            })
+            .unwrap();
+
+        let window_procedure = thunk.as_window_procedure();
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_SIZE, WPARAM(0), LPARAM(0)) }.0,
+            WM_SIZE as isize,
+        );
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_USER + 1, WPARAM(0), LPARAM(0)) }.0,
+            (WM_USER + 1) as isize,
+        );
+
+        // Not interested messages are passed to the default window procedure.
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_USER + 2, WPARAM(0), LPARAM(0)) }.0,
+            DEFAULT_RESULT,
+        );
+
+        assert_eq!(count.get(), 2);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_context_thunk_from_category_handler() {
+        use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+        use crate::test_support::DEFAULT_RESULT;
+        use crate::ContextThunk;
+
+        let thunk =
+            ContextThunk::from_category_handler(MessageCategories::INPUT, |message: u32, _: WPARAM, _: LPARAM| {
+                LRESULT(message as isize)
+            })
+            .unwrap();
+
+        let window_procedure = thunk.as_window_procedure();
+
+        for message in [WM_MOUSEMOVE, WM_KEYDOWN] {
+            assert_eq!(
+                unsafe { window_procedure(HWND::default(), message, WPARAM(0), LPARAM(0)) }.0,
+                message as isize,
+            );
+        }
+
+        // Unsubscribed categories are passed to the default window procedure.
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_USER, WPARAM(0), LPARAM(0)) }.0,
+            DEFAULT_RESULT,
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_filtered_handler_while_mutably_borrowed() {
+        crate::test_support::assert_aborts("filter::tests::test_filtered_handler_while_mutably_borrowed", || {
+            use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+            use crate::sys::Win32::UI::WindowsAndMessaging::{WM_SETTEXT, WM_USER};
+            use crate::ContextThunk;
+
+            let mut thunk =
+                ContextThunk::from_filtered_handler(&[WM_USER], |_: u32, _: WPARAM, _: LPARAM| LRESULT(1)).unwrap();
+
+            let window_procedure = thunk.as_window_procedure();
+            let _context = thunk.get_mut();
+
+            // Reading the filter already borrows the context object, so not interested messages are checked too.
+            unsafe { window_procedure(HWND::default(), WM_SETTEXT, WPARAM(0), LPARAM(0)) };
+        });
+    }
+}
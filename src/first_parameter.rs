@@ -0,0 +1,72 @@
+//! The value that replaces the window handle argument of a wrapped window procedure.
+
+use crate::sys::Win32::Foundation::HWND;
+
+/// The value passed by an [`AtlThunk`](crate::AtlThunk) as the first argument of its wrapped window procedure, in place
+/// of the window handle. Despite the argument is typed [`HWND`], the value is usually not a window handle, but a
+/// pointer to some context object.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FirstParameter(pub usize);
+
+impl FirstParameter {
+    /// Returns the value as a window handle, which is how the wrapped window procedure receives it.
+    pub fn as_hwnd(self) -> HWND {
+        HWND(self.0 as _)
+    }
+
+    /// Returns the value as a pointer.
+    pub fn as_ptr<T>(self) -> *mut T {
+        self.0 as _
+    }
+}
+
+impl From<usize> for FirstParameter {
+    fn from(value: usize) -> Self {
+        Self(value)
+    }
+}
+
+impl From<HWND> for FirstParameter {
+    fn from(value: HWND) -> Self {
+        Self(value.0 as _)
+    }
+}
+
+impl<T> From<*const T> for FirstParameter {
+    fn from(value: *const T) -> Self {
+        Self(value as _)
+    }
+}
+
+impl<T> From<*mut T> for FirstParameter {
+    fn from(value: *mut T) -> Self {
+        Self(value as _)
+    }
+}
+
+impl From<FirstParameter> for HWND {
+    fn from(value: FirstParameter) -> Self {
+        value.as_hwnd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FirstParameter;
+    use crate::sys::Win32::Foundation::HWND;
+    use core::ptr;
+
+    #[test]
+    fn test_first_parameter_conversions() {
+        let value = 7_u32;
+        let pointer = ptr::from_ref(&value);
+        let first_parameter = FirstParameter::from(pointer);
+
+        assert_eq!(first_parameter, FirstParameter(pointer as usize));
+        assert_eq!(first_parameter, FirstParameter::from(pointer.cast_mut()));
+        assert_eq!(first_parameter.as_ptr::<u32>().cast_const(), pointer);
+        assert_eq!(first_parameter.as_hwnd(), HWND(pointer.cast_mut().cast()));
+        assert_eq!(FirstParameter::from(first_parameter.as_hwnd()), first_parameter);
+        assert_eq!(FirstParameter::from(usize::MAX).as_hwnd(), HWND(usize::MAX as _));
+    }
+}
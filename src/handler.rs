@@ -0,0 +1,379 @@
+//! Window procedures that dispatch messages to Rust handler objects.
+
+use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use crate::{AtlThunk, WindowProcedure};
+use core::cell::{Cell, UnsafeCell};
+use core::ffi::c_void;
+use core::marker::PhantomData;
+use core::mem;
+use core::ptr;
+
+/// Handler of window messages. The associated data of an [`AtlThunk`] replaces the window handle argument, so a
+/// handler only receives the message and its parameters.
+///
+/// Window procedures are reentrant: sending a message to the window from inside a handler dispatches the new message
+/// before the current one returns. For this reason, handlers are called through shared references, and states that
+/// need to be updated should use interior mutability.
+///
+/// # Panics
+///
+/// Handlers are called from an `extern "system"` window procedure, which can not unwind. If a handler panics, the
+/// panic hook reports the panic as usual, including its location, then the process is aborted instead of unwinding
+/// into the operating system. With the `std` feature, `ContextThunk::for_handler_poison_on_panic`
+/// can be used to catch panics instead.
+pub trait WindowHandler {
+    /// Handles a window message.
+    fn handle(&self, message: u32, w_param: WPARAM, l_param: LPARAM) -> LRESULT;
+}
+
+impl<F> WindowHandler for F
+where
+    F: Fn(u32, WPARAM, LPARAM) -> LRESULT,
+{
+    fn handle(&self, message: u32, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+        self(message, w_param, l_param)
+    }
+}
+
+/// Handler of window messages that may leave messages to the default window procedure by returning [`None`], see
+/// [`ContextThunk::for_optional_handler`](crate::ContextThunk). Otherwise the same as [`WindowHandler`].
+pub trait OptionalWindowHandler {
+    /// Handles a window message. Returns [`None`] if the message should be passed to the default window procedure.
+    fn handle(&self, message: u32, w_param: WPARAM, l_param: LPARAM) -> Option<LRESULT>;
+}
+
+impl<F> OptionalWindowHandler for F
+where
+    F: Fn(u32, WPARAM, LPARAM) -> Option<LRESULT>,
+{
+    fn handle(&self, message: u32, w_param: WPARAM, l_param: LPARAM) -> Option<LRESULT> {
+        self(message, w_param, l_param)
+    }
+}
+
+/// Window procedure that forwards messages to the handler pointed by the associated data.
+pub(crate) unsafe extern "system" fn handler_window_procedure<H>(
+    handler: HWND,
+    message: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> LRESULT
+where
+    H: WindowHandler,
+{
+    unsafe { (*handler.0.cast::<H>()).handle(message, w_param, l_param) }
+}
+
+/// A table of functions that handle window messages with a raw context pointer, see [`AtlThunk::from_static_handler`].
+#[derive(Clone, Copy, Debug)]
+pub struct HandlerVTable {
+    /// Handles a window message with the context pointer passed to [`AtlThunk::from_static_handler`].
+    pub handle:
+        unsafe extern "system" fn(context: *mut c_void, message: u32, w_param: WPARAM, l_param: LPARAM) -> LRESULT,
+}
+
+/// An [`AtlThunk`] that dispatches messages to a borrowed [`WindowHandler`]. The borrow checker guarantees that the
+/// handler outlives the thunk.
+pub struct ScopedThunk<'a> {
+    thunk: AtlThunk,
+    handler: PhantomData<&'a dyn WindowHandler>,
+}
+
+impl<'a> ScopedThunk<'a> {
+    /// Makes the thunk dispatch messages to the closure in `holder` instead, see [`AtlThunk::borrowed_closure`].
+    pub fn set_borrowed_closure(&mut self, holder: &'a ClosureHolder<'_>) {
+        self.thunk
+            .set_data(borrowed_closure_window_procedure, ptr::from_ref(holder));
+    }
+
+    /// Returns a window procedure that dispatches messages to the borrowed handler. The returned function pointer is
+    /// only valid while the originating [`ScopedThunk`] object is alive, prefer [`ScopedThunk::bound_procedure`] to
+    /// have this checked by the borrow checker.
+    #[inline(always)]
+    pub fn as_window_procedure(&self) -> WindowProcedure {
+        self.thunk.as_window_procedure()
+    }
+
+    /// Returns the window procedure as a [`BoundProc`] object, which borrows the [`ScopedThunk`] object, so it can not
+    /// be stored in places that outlive either the thunk or the borrowed handler, like `'static` variables.
+    #[inline(always)]
+    pub fn bound_procedure(&self) -> BoundProc<'_> {
+        BoundProc {
+            window_procedure: self.as_window_procedure(),
+            thunk: PhantomData,
+        }
+    }
+}
+
+/// The window procedure of a [`ScopedThunk`], see [`ScopedThunk::bound_procedure`].
+#[derive(Clone, Copy, Debug)]
+pub struct BoundProc<'a> {
+    window_procedure: WindowProcedure,
+    thunk: PhantomData<&'a ScopedThunk<'a>>,
+}
+
+impl BoundProc<'_> {
+    /// Calls the window procedure, which dispatches the message to the borrowed handler. This is safe because the
+    /// handler is guaranteed to be alive, and the window handle argument is replaced by the thunk.
+    #[inline(always)]
+    pub fn call(&self, message: u32, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+        unsafe { (self.window_procedure)(HWND::default(), message, w_param, l_param) }
+    }
+
+    /// Returns the window procedure as a function pointer, which escapes the borrow checker. The same validity
+    /// requirements as the ones of [`ScopedThunk::as_window_procedure`] apply.
+    #[inline(always)]
+    pub fn as_window_procedure(&self) -> WindowProcedure {
+        self.window_procedure
+    }
+}
+
+/// Keeps a mutably borrowed closure reachable from an ATL thunk, see [`AtlThunk::borrowed_closure`]. The associated
+/// data of an ATL thunk is a single pointer, so it points to the holder, which stores the fat pointer to the closure.
+pub struct ClosureHolder<'f> {
+    closure: UnsafeCell<&'f mut dyn FnMut(HWND, u32, WPARAM, LPARAM) -> LRESULT>,
+    window: Cell<HWND>,
+    calling: Cell<bool>,
+}
+
+impl<'f> ClosureHolder<'f> {
+    /// Creates a new [`ClosureHolder`] object that borrows `closure`.
+    pub fn new(closure: &'f mut dyn FnMut(HWND, u32, WPARAM, LPARAM) -> LRESULT) -> Self {
+        Self {
+            closure: UnsafeCell::new(closure),
+            window: Cell::new(HWND::default()),
+            calling: Cell::new(false),
+        }
+    }
+
+    /// Sets the window handle passed to the closure, which is a null handle until this is called, because the ATL
+    /// thunk replaces the window handle argument.
+    pub fn bind_window(&self, window: HWND) {
+        self.window.set(window);
+    }
+}
+
+/// Window procedure that calls the closure of the [`ClosureHolder`] pointed by the associated data. A closure can not
+/// be called while it is already running, so reentrant messages are passed to
+/// [`default_procedure`](crate::default_procedure) with the bound window instead.
+unsafe extern "system" fn borrowed_closure_window_procedure(
+    holder: HWND,
+    message: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> LRESULT {
+    let holder = unsafe { &*holder.0.cast::<ClosureHolder<'_>>() };
+    let window = holder.window.get();
+
+    if holder.calling.replace(true) {
+        return unsafe { crate::default_procedure()(window, message, w_param, l_param) };
+    }
+
+    // Panics abort the process, so the flag does not need to be reset while unwinding.
+    let result = unsafe { (*holder.closure.get())(window, message, w_param, l_param) };
+
+    holder.calling.set(false);
+
+    result
+}
+
+impl AtlThunk {
+    /// Creates a new [`ScopedThunk`] object that calls the mutably borrowed closure in `holder`. No heap allocation is
+    /// made besides the ATL thunk itself, so this works for short-lived closures on the stack, like temporary message
+    /// filters. Unlike [`AtlThunk::scoped_handler`], the closure may mutate its captures directly, but messages sent
+    /// while the closure is running, which would call it reentrantly, are passed to
+    /// [`default_procedure`](crate::default_procedure) instead.
+    #[track_caller]
+    pub fn borrowed_closure<'a>(holder: &'a ClosureHolder<'_>) -> crate::sys::core::Result<ScopedThunk<'a>> {
+        Self::try_new_with(borrowed_closure_window_procedure, ptr::from_ref(holder)).map(|thunk| ScopedThunk {
+            thunk,
+            handler: PhantomData,
+        })
+    }
+
+    /// Creates a new [`ScopedThunk`] object that dispatches messages to `handler`. No heap allocation is made besides
+    /// the ATL thunk itself, so this works for handlers on the stack, like the ones used by modal loops.
+    #[track_caller]
+    pub fn scoped_handler<'a, H>(handler: &'a H) -> crate::sys::core::Result<ScopedThunk<'a>>
+    where
+        H: WindowHandler + 'a,
+    {
+        Self::try_new_with(handler_window_procedure::<H>, ptr::from_ref(handler)).map(|thunk| ScopedThunk {
+            thunk,
+            handler: PhantomData,
+        })
+    }
+
+    /// Creates a new [`AtlThunk`] object that dispatches messages to `vtable.handle` with `context`. No heap allocation
+    /// is made besides the ATL thunk itself, so this works without the `alloc` feature.
+    ///
+    /// The caller owns `context` entirely: it is passed to the handler as is, and must stay valid for as long as the
+    /// returned window procedure may be called.
+    #[track_caller]
+    pub fn from_static_handler(vtable: &'static HandlerVTable, context: *mut c_void) -> crate::sys::core::Result<Self> {
+        // `HWND` is a transparent wrapper of `*mut c_void`, so both function pointer types have the same ABI.
+        let window_procedure = unsafe {
+            mem::transmute::<unsafe extern "system" fn(*mut c_void, u32, WPARAM, LPARAM) -> LRESULT, WindowProcedure>(
+                vtable.handle,
+            )
+        };
+
+        Self::try_new_with(window_procedure, context)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HandlerVTable;
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use crate::test_support::DEFAULT_RESULT;
+    use crate::AtlThunk;
+    use core::cell::Cell;
+    use core::ffi::c_void;
+    use core::ptr;
+
+    #[test]
+    fn test_scoped_handler() {
+        let count = Cell::new(0);
+
+        let handler = |message: u32, w_param: WPARAM, l_param: LPARAM| {
+            count.set(count.get() + 1);
+
+            LRESULT(message as isize + w_param.0 as isize + l_param.0)
+        };
+
+        let thunk = AtlThunk::scoped_handler(&handler).unwrap();
+        let window_procedure = thunk.as_window_procedure();
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), 2, WPARAM(3), LPARAM(5)) }.0,
+            10,
+        );
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), 7, WPARAM(11), LPARAM(13)) }.0,
+            31,
+        );
+
+        drop(thunk);
+
+        assert_eq!(count.get(), 2);
+    }
+
+    #[test]
+    fn test_scoped_handler_bound_procedure() {
+        let handler = |message: u32, w_param: WPARAM, _: LPARAM| LRESULT(message as isize * w_param.0 as isize);
+        let thunk = AtlThunk::scoped_handler(&handler).unwrap();
+        let bound_procedure = thunk.bound_procedure();
+
+        assert_eq!(
+            bound_procedure.as_window_procedure() as usize,
+            thunk.as_window_procedure() as usize
+        );
+
+        assert_eq!(bound_procedure.call(3, WPARAM(5), LPARAM(0)).0, 15);
+    }
+
+    #[test]
+    fn test_thunk_borrowed_closure() {
+        use super::ClosureHolder;
+
+        let mut messages = 0;
+        let mut total = 0;
+
+        let mut count = |_: HWND, _: u32, _: WPARAM, _: LPARAM| {
+            messages += 1;
+
+            LRESULT(messages)
+        };
+
+        let mut sum = |window: HWND, _: u32, w_param: WPARAM, _: LPARAM| {
+            total += w_param.0;
+
+            LRESULT(window.0 as _)
+        };
+
+        let count_holder = ClosureHolder::new(&mut count);
+        let sum_holder = ClosureHolder::new(&mut sum);
+
+        sum_holder.bind_window(HWND(7 as _));
+
+        let mut thunk = AtlThunk::borrowed_closure(&count_holder).unwrap();
+
+        for expected in 1..=2 {
+            assert_eq!(
+                unsafe { thunk.as_window_procedure()(HWND::default(), 0, WPARAM(3), LPARAM(0)) }.0,
+                expected,
+            );
+        }
+
+        thunk.set_borrowed_closure(&sum_holder);
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 0, WPARAM(5), LPARAM(0)) }.0,
+            7,
+        );
+
+        drop(thunk);
+
+        assert_eq!(messages, 2);
+        assert_eq!(total, 5);
+    }
+
+    #[test]
+    fn test_thunk_borrowed_closure_reentrant() {
+        use super::ClosureHolder;
+        use crate::WindowProcedure;
+
+        let window_procedure = Cell::new(None::<WindowProcedure>);
+
+        let mut closure = |_: HWND, message: u32, _: WPARAM, _: LPARAM| {
+            if message == 1 {
+                let inner = unsafe { window_procedure.get().unwrap()(HWND::default(), 2, WPARAM(0), LPARAM(0)) };
+
+                LRESULT(inner.0 + 1)
+            } else {
+                LRESULT(message as _)
+            }
+        };
+
+        let holder = ClosureHolder::new(&mut closure);
+        let thunk = AtlThunk::borrowed_closure(&holder).unwrap();
+
+        window_procedure.set(Some(thunk.as_window_procedure()));
+
+        // The reentrant message is passed to the default window procedure.
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 1, WPARAM(0), LPARAM(0)) }.0,
+            DEFAULT_RESULT + 1,
+        );
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 2, WPARAM(0), LPARAM(0)) }.0,
+            2,
+        );
+    }
+
+    #[test]
+    fn test_thunk_from_static_handler() {
+        unsafe extern "system" fn handle(context: *mut c_void, message: u32, w_param: WPARAM, _: LPARAM) -> LRESULT {
+            let count = unsafe { &*context.cast::<Cell<u32>>() };
+
+            count.set(count.get() + 1);
+
+            LRESULT(message as isize * w_param.0 as isize)
+        }
+
+        static VTABLE: HandlerVTable = HandlerVTable { handle };
+
+        let count = Cell::new(0);
+        let thunk = AtlThunk::from_static_handler(&VTABLE, ptr::from_ref(&count).cast_mut().cast()).unwrap();
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 2, WPARAM(3), LPARAM(0)) }.0,
+            6,
+        );
+
+        assert_eq!(count.get(), 1);
+    }
+}
@@ -0,0 +1,261 @@
+//! Best-effort decoding of ATL thunk code stubs, meant to be used as a debugging aid only.
+//!
+//! The layout of code stubs is an implementation detail of `atlthunk.dll` that may differ between Windows versions and
+//! architectures, so decoding may fail even if the ATL thunk works correctly.
+
+use crate::sys::Win32::UI::WindowsAndMessaging::WNDPROC;
+use crate::AtlThunk;
+#[cfg(feature = "metadata")]
+use core::fmt::{self, Display, Formatter};
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod decode;
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use self::decode::read_stub;
+
+/// Layout of a recognized ATL thunk code stub.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StubLayout {
+    /// `mov dword ptr [esp + 4], first_parameter` followed by `jmp procedure`, used on x86.
+    X86Immediate,
+    /// `mov rcx, first_parameter`, `mov rax, procedure` and `jmp rax`, used on x86-64.
+    X64Immediate,
+    /// `mov rcx, qword ptr [rip + offset]` followed by `jmp qword ptr [rip + offset]`, used on x86-64 with the values
+    /// stored outside of the code stub.
+    X64Indirect,
+}
+
+/// Values decoded from an ATL thunk code stub.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecodedStub {
+    /// Layout of the code stub.
+    pub layout: StubLayout,
+    /// The first parameter value passed to the procedure.
+    pub first_parameter: usize,
+    /// Address of the procedure the code stub jumps to.
+    pub procedure: usize,
+}
+
+impl DecodedStub {
+    /// Decodes an arbitrary window procedure, like one returned by
+    /// [`GetWindowLongPtrW`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-getwindowlongptrw),
+    /// as an ATL thunk code stub. Returns [`None`] if `window_procedure` is [`None`] or does not look like a recognized
+    /// code stub. Like [`AtlThunk::describe`], this is a best-effort debugging aid.
+    ///
+    /// The ATL thunk that owns the code stub can not be recovered, because the relation between the code stub and the
+    /// [`AtlThunkData_t`](crate::windows::AtlThunkData_t) object is not documented.
+    ///
+    /// # Safety
+    ///
+    /// `window_procedure` must point to a function that is still loaded.
+    pub unsafe fn from_window_procedure(window_procedure: WNDPROC) -> Option<Self> {
+        unsafe { read_stub(window_procedure? as usize) }
+    }
+}
+
+/// Best-effort description of an ATL thunk, see [`AtlThunk::describe`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ThunkDescription {
+    /// Address of the code stub, which is also the address of the window procedure returned by
+    /// [`AtlThunk::as_window_procedure`].
+    pub code_address: usize,
+    /// Values decoded from the code stub, or [`None`] if the layout of the code stub is not recognized.
+    pub stub: Option<DecodedStub>,
+}
+
+/// Mismatch found by [`AtlThunk::validate`].
+#[cfg(feature = "metadata")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The associated data is not tracked, see [`AtlThunk::data`].
+    Untracked,
+    /// The layout of the code stub is not recognized, so there is nothing to compare with.
+    UnknownLayout,
+    /// The code stub jumps to a different procedure than the tracked one.
+    ProcedureMismatch {
+        /// Address of the tracked window procedure.
+        tracked: usize,
+        /// Address decoded from the code stub.
+        decoded: usize,
+    },
+    /// The code stub passes a different first parameter than the tracked one.
+    FirstParameterMismatch {
+        /// The tracked first parameter value.
+        tracked: usize,
+        /// The value decoded from the code stub.
+        decoded: usize,
+    },
+}
+
+#[cfg(feature = "metadata")]
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Untracked => f.write_str("the associated data of the ATL thunk is not tracked"),
+            Self::UnknownLayout => f.write_str("the layout of the ATL thunk code stub is not recognized"),
+            Self::ProcedureMismatch { tracked, decoded } => write!(
+                f,
+                "the ATL thunk code stub jumps to procedure {decoded:#x}, but the tracked procedure is {tracked:#x}",
+            ),
+            Self::FirstParameterMismatch { tracked, decoded } => write!(
+                f,
+                "the ATL thunk code stub passes first parameter {decoded:#x}, but the tracked first parameter is \
+                 {tracked:#x}",
+            ),
+        }
+    }
+}
+
+#[cfg(all(feature = "metadata", feature = "std"))]
+impl std::error::Error for ValidationError {}
+
+/// No code stub layout is known for the current architecture.
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+unsafe fn read_stub(address: usize) -> Option<DecodedStub> {
+    _ = address;
+
+    None
+}
+
+impl AtlThunk {
+    /// Reads the code stub of the ATL thunk and decodes the first parameter value and the procedure address encoded in
+    /// it. This is a best-effort debugging aid: the result only contains decoded values if the stub layout is
+    /// recognized, and it must not be relied on for program logic. The associated data must have been set before
+    /// calling this method.
+    pub fn describe(&self) -> ThunkDescription {
+        let code_address = self.as_window_procedure() as usize;
+
+        ThunkDescription {
+            code_address,
+            stub: unsafe { read_stub(code_address) },
+        }
+    }
+
+    /// Checks that the code stub encodes the same window procedure and first parameter as the tracked associated data
+    /// returned by [`AtlThunk::data`]. A mismatch means that the associated data has been changed by other means, like
+    /// [`AtlThunk::set_data_unchecked`], or that the code stub is decoded incorrectly. Like [`AtlThunk::describe`],
+    /// this is a best-effort debugging aid.
+    #[cfg(feature = "metadata")]
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let data = self.data().ok_or(ValidationError::Untracked)?;
+        let stub = self.describe().stub.ok_or(ValidationError::UnknownLayout)?;
+        let tracked_procedure = data.window_procedure as usize;
+
+        if stub.procedure != tracked_procedure {
+            Err(ValidationError::ProcedureMismatch {
+                tracked: tracked_procedure,
+                decoded: stub.procedure,
+            })
+        } else if stub.first_parameter != data.first_parameter.0 {
+            Err(ValidationError::FirstParameterMismatch {
+                tracked: data.first_parameter.0,
+                decoded: stub.first_parameter,
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DecodedStub;
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use crate::AtlThunk;
+
+    #[test]
+    fn test_thunk_describe() {
+        unsafe extern "system" fn callback(_: HWND, _: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+            LRESULT(0)
+        }
+
+        let thunk = AtlThunk::try_new_with(callback, HWND(0x1234 as _)).unwrap();
+        let description = thunk.describe();
+
+        assert_eq!(description.code_address, thunk.as_window_procedure() as usize);
+
+        if let Some(stub) = description.stub {
+            assert_eq!(stub.first_parameter, 0x1234);
+            assert_eq!(stub.procedure, callback as *const () as usize);
+        }
+
+        assert_eq!(
+            unsafe { DecodedStub::from_window_procedure(thunk.as_wndproc()) },
+            description.stub
+        );
+
+        assert_eq!(unsafe { DecodedStub::from_window_procedure(None) }, None);
+    }
+
+    #[cfg(feature = "metadata")]
+    #[test]
+    fn test_thunk_validate() {
+        use super::ValidationError;
+
+        unsafe extern "system" fn callback(_: HWND, _: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+            LRESULT(0)
+        }
+
+        assert_eq!(AtlThunk::try_new().unwrap().validate(), Err(ValidationError::Untracked));
+
+        let mut thunk = AtlThunk::try_new_with(callback, HWND(0x1234 as _)).unwrap();
+
+        if thunk.describe().stub.is_none() {
+            assert_eq!(thunk.validate(), Err(ValidationError::UnknownLayout));
+
+            return;
+        }
+
+        assert_eq!(thunk.validate(), Ok(()));
+
+        unsafe { thunk.set_data_unchecked(callback, HWND(0x5678 as _)) };
+
+        assert_eq!(
+            thunk.validate(),
+            Err(ValidationError::FirstParameterMismatch {
+                tracked: 0x1234,
+                decoded: 0x5678,
+            }),
+        );
+    }
+
+    #[cfg(all(feature = "alloc", feature = "metadata"))]
+    #[test]
+    fn test_validation_error_display() {
+        use super::ValidationError;
+        use alloc::string::ToString;
+
+        assert_eq!(
+            ValidationError::Untracked.to_string(),
+            "the associated data of the ATL thunk is not tracked",
+        );
+
+        assert_eq!(
+            ValidationError::ProcedureMismatch {
+                tracked: 0x10,
+                decoded: 0x20,
+            }
+            .to_string(),
+            "the ATL thunk code stub jumps to procedure 0x20, but the tracked procedure is 0x10",
+        );
+
+        assert_eq!(
+            ValidationError::FirstParameterMismatch {
+                tracked: 0x1234,
+                decoded: 0x5678,
+            }
+            .to_string(),
+            "the ATL thunk code stub passes first parameter 0x5678, but the tracked first parameter is 0x1234",
+        );
+    }
+
+    #[cfg(all(feature = "metadata", feature = "std"))]
+    #[test]
+    fn test_validation_error_source() {
+        use super::ValidationError;
+        use std::error::Error;
+
+        assert!(ValidationError::UnknownLayout.source().is_none());
+    }
+}
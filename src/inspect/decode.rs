@@ -0,0 +1,222 @@
+//! Decoders of known code stub layouts.
+
+use super::{DecodedStub, StubLayout};
+use core::ptr;
+
+/// Operand of a code stub instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operand {
+    /// The value is encoded in the instruction.
+    Immediate(usize),
+    /// The value is stored in memory at the specified address.
+    #[cfg(target_arch = "x86_64")]
+    Memory(usize),
+}
+
+impl Operand {
+    unsafe fn read(self) -> usize {
+        match self {
+            Self::Immediate(value) => value,
+            #[cfg(target_arch = "x86_64")]
+            Self::Memory(address) => unsafe { ptr::read_unaligned(address as *const usize) },
+        }
+    }
+}
+
+/// Code stub whose operands have been decoded but not yet read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct EncodedStub {
+    layout: StubLayout,
+    first_parameter: Operand,
+    procedure: Operand,
+}
+
+const MAX_STUB_LENGTH: usize = 22;
+const STUB_PREFIX_LENGTH: usize = 2;
+
+fn get_bytes<const N: usize>(code: &[u8], offset: usize) -> Option<[u8; N]> {
+    code.get(offset..offset + N)?.try_into().ok()
+}
+
+fn relative_address(base: usize, offset: [u8; 4]) -> usize {
+    base.wrapping_add_signed(i32::from_le_bytes(offset) as isize)
+}
+
+/// Returns the length of the code stub that starts with `prefix`, so that no more bytes than needed are read.
+fn get_stub_length(prefix: [u8; STUB_PREFIX_LENGTH]) -> Option<usize> {
+    match prefix {
+        #[cfg(target_arch = "x86")]
+        [0xC7, 0x44] => Some(13),
+        #[cfg(target_arch = "x86_64")]
+        [0x48, 0xB9] => Some(22),
+        #[cfg(target_arch = "x86_64")]
+        [0x48, 0x8B] => Some(13),
+        _ => None,
+    }
+}
+
+/// Decodes code stub bytes located at `address`. Returns [`None`] if the layout is not recognized.
+#[cfg(target_arch = "x86")]
+fn decode_stub(code: &[u8], address: usize) -> Option<EncodedStub> {
+    // mov dword ptr [esp + 4], imm32
+    // jmp rel32
+    if code.get(..4)? == [0xC7, 0x44, 0x24, 0x04] && *code.get(8)? == 0xE9 {
+        Some(EncodedStub {
+            layout: StubLayout::X86Immediate,
+            first_parameter: Operand::Immediate(u32::from_le_bytes(get_bytes(code, 4)?) as usize),
+            procedure: Operand::Immediate(relative_address(address.wrapping_add(13), get_bytes(code, 9)?)),
+        })
+    } else {
+        None
+    }
+}
+
+/// Decodes code stub bytes located at `address`. Returns [`None`] if the layout is not recognized.
+#[cfg(target_arch = "x86_64")]
+fn decode_stub(code: &[u8], address: usize) -> Option<EncodedStub> {
+    match code.get(..3)? {
+        // mov rcx, imm64
+        // mov rax, imm64
+        // jmp rax
+        [0x48, 0xB9, _] if code.get(10..12)? == [0x48, 0xB8] && code.get(20..22)? == [0xFF, 0xE0] => {
+            Some(EncodedStub {
+                layout: StubLayout::X64Immediate,
+                first_parameter: Operand::Immediate(u64::from_le_bytes(get_bytes(code, 2)?) as usize),
+                procedure: Operand::Immediate(u64::from_le_bytes(get_bytes(code, 12)?) as usize),
+            })
+        }
+        // mov rcx, qword ptr [rip + disp32]
+        // jmp qword ptr [rip + disp32]
+        [0x48, 0x8B, 0x0D] if code.get(7..9)? == [0xFF, 0x25] => Some(EncodedStub {
+            layout: StubLayout::X64Indirect,
+            first_parameter: Operand::Memory(relative_address(address.wrapping_add(7), get_bytes(code, 3)?)),
+            procedure: Operand::Memory(relative_address(address.wrapping_add(13), get_bytes(code, 9)?)),
+        }),
+        _ => None,
+    }
+}
+
+/// Reads and decodes the code stub at `address`.
+///
+/// # Safety
+///
+/// `address` must point to an initialized ATL thunk code stub.
+pub(super) unsafe fn read_stub(address: usize) -> Option<DecodedStub> {
+    let mut code = [0; MAX_STUB_LENGTH];
+
+    unsafe { ptr::copy_nonoverlapping(address as *const u8, code.as_mut_ptr(), STUB_PREFIX_LENGTH) };
+
+    let length = get_stub_length(get_bytes(&code, 0)?)?;
+
+    unsafe { ptr::copy_nonoverlapping(address as *const u8, code.as_mut_ptr(), length) };
+
+    let stub = decode_stub(&code[..length], address)?;
+
+    Some(DecodedStub {
+        layout: stub.layout,
+        first_parameter: unsafe { stub.first_parameter.read() },
+        procedure: unsafe { stub.procedure.read() },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EncodedStub, Operand};
+    use crate::inspect::StubLayout;
+
+    #[test]
+    fn test_decode_stub_arbitrary_bytes() {
+        // Xorshift generator, so failures are reproducible.
+        let mut state = 0x2545_F491_4F6C_DD1D_u64;
+
+        let mut next_byte = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+
+            state as u8
+        };
+
+        for prefix in 0..=u16::MAX {
+            if let Some(length) = super::get_stub_length(prefix.to_le_bytes()) {
+                assert!(length <= super::MAX_STUB_LENGTH);
+            }
+        }
+
+        for _ in 0..100_000 {
+            let mut code = [0; super::MAX_STUB_LENGTH];
+
+            code.fill_with(&mut next_byte);
+
+            // Make recognized prefixes likely, so that the operand checks are reached.
+            if let Some(prefix) = [[0xC7_u8, 0x44, 0x24, 0x04], [0x48, 0xB9, 0, 0], [0x48, 0x8B, 0x0D, 0]]
+                .get(usize::from(next_byte() % 4))
+            {
+                code[..4].copy_from_slice(prefix);
+            }
+
+            for length in 0..=code.len() {
+                // Decoding only parses bytes, so it must reject truncated or malformed stubs without panicking.
+                _ = super::decode_stub(&code[..length], usize::MAX);
+            }
+        }
+    }
+
+    #[cfg(target_arch = "x86")]
+    #[test]
+    fn test_decode_stub() {
+        let code = [
+            0xC7, 0x44, 0x24, 0x04, 0x78, 0x56, 0x34, 0x12, 0xE9, 0xF0, 0xFF, 0xFF, 0xFF,
+        ];
+
+        assert_eq!(super::get_stub_length([code[0], code[1]]), Some(code.len()));
+
+        assert_eq!(
+            super::decode_stub(&code, 0x1000),
+            Some(EncodedStub {
+                layout: StubLayout::X86Immediate,
+                first_parameter: Operand::Immediate(0x1234_5678),
+                procedure: Operand::Immediate(0xFFD),
+            }),
+        );
+
+        assert_eq!(super::decode_stub(&code[..12], 0x1000), None);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_decode_stub() {
+        let code = [
+            0x48, 0xB9, 0xEF, 0xCD, 0xAB, 0x89, 0x67, 0x45, 0x23, 0x01, 0x48, 0xB8, 0x10, 0x32, 0x54, 0x76, 0x98, 0xBA,
+            0xDC, 0xFE, 0xFF, 0xE0,
+        ];
+
+        assert_eq!(super::get_stub_length([code[0], code[1]]), Some(code.len()));
+
+        assert_eq!(
+            super::decode_stub(&code, 0x1000),
+            Some(EncodedStub {
+                layout: StubLayout::X64Immediate,
+                first_parameter: Operand::Immediate(0x0123_4567_89AB_CDEF),
+                procedure: Operand::Immediate(0xFEDC_BA98_7654_3210),
+            }),
+        );
+
+        assert_eq!(super::decode_stub(&code[..21], 0x1000), None);
+
+        let code = [
+            0x48, 0x8B, 0x0D, 0xF9, 0x0F, 0x00, 0x00, 0xFF, 0x25, 0xFA, 0x0F, 0x00, 0x00,
+        ];
+
+        assert_eq!(super::get_stub_length([code[0], code[1]]), Some(code.len()));
+
+        assert_eq!(
+            super::decode_stub(&code, 0x1000),
+            Some(EncodedStub {
+                layout: StubLayout::X64Indirect,
+                first_parameter: Operand::Memory(0x2000),
+                procedure: Operand::Memory(0x2007),
+            }),
+        );
+    }
+}
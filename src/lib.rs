@@ -1,19 +1,186 @@
-#![cfg(windows)]
+#![cfg(any(windows, feature = "mock-backend"))]
 #![no_std]
+#![warn(clippy::missing_panics_doc)]
 
 //! Rust wrapper of [ATL thunk](https://learn.microsoft.com/en-us/windows/win32/api/atlthunk/) type.
 
-use ::windows::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
-use ::windows::Win32::System::Memory::AtlThunkData_t;
-use ::windows::Win32::UI::WindowsAndMessaging::WNDPROC;
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "std")]
+extern crate std;
+
+#[cfg(all(not(windows), feature = "accessibility"))]
+compile_error!("the `accessibility` feature is only supported on Windows");
+
+#[cfg(all(not(windows), feature = "debug-checks"))]
+compile_error!("the `debug-checks` feature is only supported on Windows");
+
+#[cfg(all(not(windows), feature = "window-class"))]
+compile_error!("the `window-class` feature is only supported on Windows");
+
+use crate::sys::Win32::Foundation::{E_OUTOFMEMORY, E_POINTER, HWND, LPARAM, LRESULT, WPARAM};
+use crate::sys::Win32::System::Memory::AtlThunkData_t;
+use crate::sys::Win32::UI::WindowsAndMessaging::WNDPROC;
 use core::ffi::c_void;
+use core::fmt::{self, Debug, Formatter};
 use core::mem;
+use core::panic::Location;
 use core::ptr::NonNull;
+use core::slice;
+
+#[cfg(feature = "accessibility")]
+mod accessible;
+mod cached;
+#[cfg(feature = "alloc")]
+mod chain;
+#[cfg(feature = "std")]
+mod channel;
+#[cfg(all(feature = "debug-checks", debug_assertions))]
+mod checks;
+mod code;
+#[cfg(debug_assertions)]
+mod compatibility;
+#[cfg(doctest)]
+mod compile_fail;
+#[cfg(feature = "alloc")]
+mod context;
+#[cfg(all(feature = "alloc", windows))]
+mod create;
+mod default_procedure;
+#[cfg(feature = "alloc")]
+mod dialog;
+#[cfg(feature = "alloc")]
+mod dispatch;
+#[cfg(feature = "alloc")]
+mod dyn_handler;
+mod filter;
+mod first_parameter;
+mod handler;
+#[cfg(feature = "debug-inspect")]
+mod inspect;
+mod message;
+#[cfg(feature = "metadata")]
+mod metadata;
+#[cfg(feature = "mock-backend")]
+mod mock;
+#[cfg(feature = "alloc")]
+mod pool;
+pub mod raw;
+#[cfg(feature = "alloc")]
+mod rebind;
+mod self_test;
+#[cfg(feature = "alloc")]
+mod set;
+#[cfg(feature = "alloc")]
+mod shared;
+#[cfg(feature = "alloc")]
+mod shared_context;
+mod small;
+#[cfg(feature = "alloc")]
+mod state;
+#[cfg(feature = "stats")]
+mod stats;
+#[cfg(feature = "alloc")]
+mod subclass;
+#[cfg(not(windows))]
+mod sys;
+#[cfg(windows)]
+use ::windows as sys;
+#[cfg(test)]
+mod test_support;
+mod token;
+mod trace;
+mod uninit;
+#[cfg(feature = "std")]
+mod unwind;
+#[cfg(feature = "window-class")]
+mod window_class;
+#[cfg(feature = "alloc")]
+mod window_object;
+
+#[cfg(feature = "accessibility")]
+pub use self::accessible::AccessibleProvider;
+pub use self::cached::CachedAtlThunk;
+#[cfg(feature = "alloc")]
+pub use self::chain::{ChainThunk, HandlerChain};
+#[cfg(feature = "std")]
+pub use self::channel::{MessageSender, WindowMessage};
+pub use self::code::ThunkCode;
+#[cfg(feature = "alloc")]
+pub use self::context::{AnyContext, CharSet, ContextRefMut, ContextThunk, Reentrant};
+#[cfg(all(feature = "alloc", windows))]
+pub use self::create::{creation_window_procedure, SetWindowHandle, WindowHandle};
+pub use self::default_procedure::{default_procedure, default_window_procedure, set_default_procedure};
+#[cfg(feature = "alloc")]
+pub use self::dialog::{DialogHandler, DialogResult};
+#[cfg(feature = "alloc")]
+pub use self::dispatch::{DispatchMap, DispatchTable, Dispatcher, MessageHandler};
+#[cfg(feature = "alloc")]
+pub use self::dyn_handler::DynHandler;
+#[cfg(feature = "alloc")]
+pub use self::filter::FilteredHandler;
+pub use self::filter::{MessageCategories, MessageFilter};
+pub use self::first_parameter::FirstParameter;
+pub use self::handler::{BoundProc, ClosureHolder, HandlerVTable, OptionalWindowHandler, ScopedThunk, WindowHandler};
+#[cfg(all(feature = "debug-inspect", feature = "metadata"))]
+pub use self::inspect::ValidationError;
+#[cfg(feature = "debug-inspect")]
+pub use self::inspect::{DecodedStub, StubLayout, ThunkDescription};
+pub use self::message::{Message, TypedWindowHandler};
+#[cfg(feature = "metadata")]
+pub use self::metadata::AssociatedData;
+#[cfg(feature = "mock-backend")]
+use self::mock::{AtlThunk_AllocateData, AtlThunk_DataToCode, AtlThunk_FreeData, AtlThunk_InitData};
+#[cfg(all(feature = "alloc", feature = "debug-inspect"))]
+pub use self::pool::TypedContextThunks;
+#[cfg(feature = "alloc")]
+pub use self::pool::{AtlThunkPool, ContextThunkPool, PoolDrain};
+#[cfg(feature = "alloc")]
+pub use self::rebind::{RebindSender, Rebindable};
+pub use self::self_test::{self_test_arch, ArchReport};
+#[cfg(feature = "alloc")]
+pub use self::set::{ThunkSet, TryThunkSet};
+#[cfg(feature = "alloc")]
+pub use self::shared::{SharedAtlThunk, WeakAtlThunk};
+#[cfg(feature = "alloc")]
+pub use self::shared_context::{SharedContext, SharedContextThunk};
+pub use self::small::{SmallHandler, SmallHandlerStorage};
+#[cfg(feature = "alloc")]
+pub use self::state::StateHandler;
+#[cfg(feature = "stats")]
+pub use self::stats::{stats, ThunkStats};
+#[cfg(feature = "alloc")]
+pub use self::subclass::ForwardingHandler;
+#[cfg(feature = "alloc")]
+pub use self::token::ContextThunkToken;
+pub use self::token::ThunkToken;
+pub use self::trace::{message_name, TracedMessage, TracingHandler};
+pub use self::uninit::UninitAtlThunk;
+#[cfg(feature = "std")]
+pub use self::unwind::{PanicPolicy, PanicReport};
+#[cfg(all(feature = "alloc", feature = "window-class"))]
+pub use self::window_class::register_creation_class;
+#[cfg(feature = "window-class")]
+pub use self::window_class::{RegisteredClass, WindowClassOptions};
+#[cfg(feature = "alloc")]
+pub use self::window_object::WindowObject;
 
 pub mod windows {
-    pub use ::windows::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    #[cfg(feature = "window-class")]
+    pub use crate::sys::core::PCWSTR;
+    #[cfg(feature = "window-class")]
+    pub use crate::sys::Win32::Foundation::HINSTANCE;
+    pub use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    #[cfg(feature = "window-class")]
+    pub use crate::sys::Win32::Graphics::Gdi::HBRUSH;
+    pub use crate::sys::Win32::System::Memory::AtlThunkData_t;
+    pub use crate::sys::Win32::UI::WindowsAndMessaging::WNDPROC;
+    #[cfg(feature = "window-class")]
+    pub use crate::sys::Win32::UI::WindowsAndMessaging::{HCURSOR, HICON, WNDCLASS_STYLES};
 }
 
+#[cfg(not(feature = "mock-backend"))]
 #[cfg_attr(
     target_arch = "x86",
     link(
@@ -46,17 +213,74 @@ type WindowProcedure = unsafe extern "system" fn(HWND, u32, WPARAM, LPARAM) -> L
 /// Rust wrapper of [ATL thunk](https://learn.microsoft.com/en-us/windows/win32/api/atlthunk/) type. It is used as
 /// a [window procedure](https://learn.microsoft.com/en-us/windows/win32/winmsg/about-window-procedures) with associated
 /// data.
+///
+/// # Panics
+///
+/// Methods of [`AtlThunk`] do not panic, including [`Drop::drop`], so they are safe to call from window procedures and
+/// during teardown, where panicking aborts the process. Methods that may panic say so in their documents, which is
+/// checked by Clippy. The only exceptions are the ownership checks of the `debug-checks` feature in debug builds: with
+/// it, [`AtlThunk::from_raw`], [`AtlThunk::into_raw`], [`AtlThunk::into_window_procedure`] and dropping panic when they
+/// detect an ATL thunk owned twice or not owned at all, which is only possible after misusing [`AtlThunk::from_raw`].
 pub struct AtlThunk {
     raw_thunk_ptr: NonNull<AtlThunkData_t>,
+    #[cfg(feature = "metadata")]
+    data: Option<AssociatedData>,
 }
 
+// Keep panicking constructs out of the methods, see the "Panics" section of `AtlThunk`.
+#[deny(
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unreachable,
+    clippy::unwrap_used
+)]
 impl AtlThunk {
     /// Creates a new [`AtlThunk`] object. For more information, see document for
     /// [`AtlThunk_AllocateData`](<https://learn.microsoft.com/en-us/windows/win32/api/atlthunk/nf-atlthunk-atlthunk_allocatedata>).
-    pub fn try_new() -> ::windows::core::Result<Self> {
+    ///
+    /// `AtlThunk_AllocateData` makes no promise about the content of the allocated ATL thunk, so the returned object is
+    /// initialized with [`default_procedure`] and a null window handle, which makes [`AtlThunk::as_window_procedure`]
+    /// callable even if a message arrives before [`AtlThunk::set_data`] is called. With the default
+    /// [`DefWindowProcW`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-defwindowprocw), such
+    /// messages return zero. Use [`UninitAtlThunk`] to make sure the associated data is set before use.
+    ///
+    /// In debug builds, the first call also checks that `atlthunk.dll` works as expected with a round trip through all
+    /// ATL thunk functions, and returns an error with code `E_UNEXPECTED` if it does not, which may be caused by a
+    /// mismatch between the deployed DLL and the Windows SDK headers.
+    ///
+    /// If the allocation fails, the message of the returned error contains the location of the caller.
+    #[track_caller]
+    pub fn try_new() -> crate::sys::core::Result<Self> {
+        let thunk = Self::allocate()?;
+
+        unsafe { init_data(thunk.raw_thunk_ptr, default_procedure(), FirstParameter::default()) };
+
+        Ok(thunk)
+    }
+
+    /// Allocates a new ATL thunk without initializing its associated data, so each constructor initializes it only
+    /// once. The returned object must be initialized before its window procedure is exposed.
+    #[track_caller]
+    pub(crate) fn allocate() -> crate::sys::core::Result<Self> {
+        #[cfg(debug_assertions)]
+        compatibility::check()?;
+
         match NonNull::new(unsafe { AtlThunk_AllocateData() }) {
-            None => Err(::windows::core::Error::from_win32()),
-            Some(raw_thunk_ptr) => Ok(Self { raw_thunk_ptr }),
+            None => Err(allocation_error(Location::caller())),
+            Some(raw_thunk_ptr) => {
+                #[cfg(feature = "stats")]
+                stats::record_allocate();
+
+                #[cfg(all(feature = "debug-checks", debug_assertions))]
+                checks::track_thunk(raw_thunk_ptr);
+
+                Ok(Self {
+                    raw_thunk_ptr,
+                    #[cfg(feature = "metadata")]
+                    data: None,
+                })
+            }
         }
     }
 
@@ -65,20 +289,111 @@ impl AtlThunk {
     /// [`AtlThunk_AllocateData`](<https://learn.microsoft.com/en-us/windows/win32/api/atlthunk/nf-atlthunk-atlthunk_allocatedata>)
     /// and
     /// [`AtlThunk_InitData`](<https://learn.microsoft.com/en-us/windows/win32/api/atlthunk/nf-atlthunk-atlthunk_initdata>).
-    pub fn try_new_with(window_procedure: WindowProcedure, first_parameter: HWND) -> ::windows::core::Result<Self> {
-        let mut result = Self::try_new();
+    ///
+    /// The wrapped window procedure uses the `system` calling convention, which is `stdcall` on x86. When called, it
+    /// calls `window_procedure` with `first_parameter` in place of the window handle, other arguments and the return
+    /// value are passed through unchanged.
+    #[track_caller]
+    pub fn try_new_with(
+        window_procedure: WindowProcedure,
+        first_parameter: impl Into<FirstParameter>,
+    ) -> crate::sys::core::Result<Self> {
+        let mut thunk = Self::allocate()?;
+
+        thunk.set_data(window_procedure, first_parameter);
+
+        Ok(thunk)
+    }
+
+    /// Same as [`AtlThunk::try_new_with`], but also returns the wrapped window procedure, for the common case of
+    /// passing it to the operating system right after creating the [`AtlThunk`] object. The returned function pointer
+    /// has the same validity requirements as the one returned by [`AtlThunk::as_window_procedure`].
+    #[track_caller]
+    pub fn try_new_with_code(
+        window_procedure: WindowProcedure,
+        first_parameter: impl Into<FirstParameter>,
+    ) -> crate::sys::core::Result<(Self, WindowProcedure)> {
+        let thunk = Self::try_new_with(window_procedure, first_parameter)?;
+        let code = thunk.as_window_procedure();
 
-        if let Ok(thunk) = &mut result {
-            thunk.set_data(window_procedure, first_parameter);
+        Ok((thunk, code))
+    }
+
+    /// Creates a new [`AtlThunk`] object from specified [`WNDPROC`] value and associated first parameter value, see
+    /// [`AtlThunk::try_new_with`]. Returns an error with code `E_POINTER` if `window_procedure` is [`None`].
+    #[track_caller]
+    pub fn try_new_with_wndproc(
+        window_procedure: WNDPROC,
+        first_parameter: impl Into<FirstParameter>,
+    ) -> crate::sys::core::Result<Self> {
+        match window_procedure {
+            None => Err(E_POINTER.into()),
+            Some(window_procedure) => Self::try_new_with(window_procedure, first_parameter),
         }
+    }
+
+    /// Creates an [`AtlThunk`] object from a raw ATL thunk pointer, taking its ownership. This is the inverse of
+    /// [`AtlThunk::into_raw`].
+    ///
+    /// # Safety
+    ///
+    /// `raw_thunk_ptr` must be allocated by
+    /// [`AtlThunk_AllocateData`](<https://learn.microsoft.com/en-us/windows/win32/api/atlthunk/nf-atlthunk-atlthunk_allocatedata>)
+    /// and not yet freed. Since the returned object frees the ATL thunk on drop, the pointer must not be owned by
+    /// anything else, including other [`AtlThunk`] objects.
+    ///
+    /// With the `debug-checks` feature in debug builds, ATL thunks owned by [`AtlThunk`] objects are tracked, and this
+    /// function panics if `raw_thunk_ptr` is already owned by another one. Dropping an object whose ATL thunk is not
+    /// tracked, like one duplicated with [`ptr::read`](core::ptr::read), panics instead of freeing it again.
+    #[cfg_attr(all(feature = "debug-checks", debug_assertions), track_caller)]
+    pub unsafe fn from_raw(raw_thunk_ptr: NonNull<AtlThunkData_t>) -> Self {
+        #[cfg(all(feature = "debug-checks", debug_assertions))]
+        checks::track_thunk(raw_thunk_ptr);
 
-        result
+        Self {
+            raw_thunk_ptr,
+            #[cfg(feature = "metadata")]
+            data: None,
+        }
+    }
+
+    /// Consumes the [`AtlThunk`] object and returns the raw ATL thunk pointer without freeing it. The caller takes the
+    /// ownership of the ATL thunk, and is responsible for either converting it back with [`AtlThunk::from_raw`], or
+    /// freeing it with
+    /// [`AtlThunk_FreeData`](<https://learn.microsoft.com/en-us/windows/win32/api/atlthunk/nf-atlthunk-atlthunk_freedata>).
+    pub fn into_raw(self) -> NonNull<AtlThunkData_t> {
+        let raw_thunk_ptr = mem::ManuallyDrop::new(self).raw_thunk_ptr;
+
+        #[cfg(all(feature = "debug-checks", debug_assertions))]
+        checks::untrack_thunk(raw_thunk_ptr);
+
+        raw_thunk_ptr
+    }
+
+    /// Consumes the [`AtlThunk`] object without freeing the ATL thunk, and returns the wrapped window procedure, which
+    /// stays valid until the process exits. This is intended for window procedures handed to the operating system for
+    /// the whole process lifetime, like the ones of window classes that are never unregistered.
+    ///
+    /// The same requirement on the associated data as the one of [`AtlThunk::as_window_procedure`] applies, and the
+    /// associated data can no longer be changed.
+    #[must_use = "the ATL thunk is leaked, and the returned window procedure is the only way to use it"]
+    pub fn into_window_procedure(self) -> WindowProcedure {
+        let window_procedure = self.as_window_procedure();
+
+        #[cfg(all(feature = "debug-checks", debug_assertions))]
+        checks::untrack_thunk(self.raw_thunk_ptr);
+
+        mem::forget(self);
+
+        window_procedure
     }
 
     /// Returns a wrapped window procedure. The returned function pointer is only valid if the following conditions are
     /// met:
     ///
-    /// - Associated data has been set through either [`AtlThunk::try_new_with`] or [`AtlThunk::set_data`].
+    /// - Associated data has been set through [`AtlThunk::try_new`], [`AtlThunk::try_new_with`] or
+    ///   [`AtlThunk::set_data`], rather than left uninitialized by foreign code, like the ATL thunk passed to
+    ///   [`AtlThunk::from_raw`].
     /// - The originating [`AtlThunk`] object has not been dropped.
     /// - There is no concurrent [`AtlThunk::set_data`] operating on the originating [`AtlThunk`] object.
     ///
@@ -86,40 +401,346 @@ impl AtlThunk {
     /// [`AtlThunk_DataToCode`](<https://learn.microsoft.com/en-us/windows/win32/api/atlthunk/nf-atlthunk-atlthunk_datatocode>).
     #[inline(always)]
     pub fn as_window_procedure(&self) -> WindowProcedure {
+        // Every `AtlThunk` object owns an ATL thunk allocated by `AtlThunk_AllocateData` and not yet freed, which is
+        // guaranteed by its constructors, including the safety requirement of `AtlThunk::from_raw`.
+        unsafe { self.as_window_procedure_unchecked() }
+    }
+
+    /// Same as [`AtlThunk::as_window_procedure`], but assumes that
+    /// [`AtlThunk_DataToCode`](<https://learn.microsoft.com/en-us/windows/win32/api/atlthunk/nf-atlthunk-atlthunk_datatocode>)
+    /// returns a non-null pointer, without checking it. This is what [`AtlThunk::as_window_procedure`] relies on.
+    ///
+    /// # Safety
+    ///
+    /// The ATL thunk must be allocated by
+    /// [`AtlThunk_AllocateData`](<https://learn.microsoft.com/en-us/windows/win32/api/atlthunk/nf-atlthunk-atlthunk_allocatedata>)
+    /// and not yet freed, which holds for every [`AtlThunk`] object created in accordance with the safety requirement
+    /// of [`AtlThunk::from_raw`]. Calling the returned window procedure additionally requires the associated data to
+    /// have been set, see [`AtlThunk::as_window_procedure`].
+    #[inline(always)]
+    pub unsafe fn as_window_procedure_unchecked(&self) -> WindowProcedure {
         unsafe { AtlThunk_DataToCode(self.raw_thunk_ptr.as_ptr()).unwrap_unchecked() }
     }
 
-    /// Updates the associated window procedure and data. For more information, see document for
-    /// [`AtlThunk_InitData`](<https://learn.microsoft.com/en-us/windows/win32/api/atlthunk/nf-atlthunk-atlthunk_initdata>).
+    /// Returns the wrapped window procedure as a [`WNDPROC`] value, which is always [`Some`]. The returned function
+    /// pointer has the same validity requirements as the one returned by [`AtlThunk::as_window_procedure`].
     #[inline(always)]
-    pub fn set_data(&mut self, window_procedure: WindowProcedure, first_parameter: HWND) {
+    pub fn as_wndproc(&self) -> WNDPROC {
+        Some(self.as_window_procedure())
+    }
+
+    /// Returns the bytes of the [`AtlThunkData_t`] block pointed by the raw ATL thunk pointer, for hashing or comparing
+    /// configured ATL thunks. The slice covers `size_of::<AtlThunkData_t>()` bytes from the start of the block, which
+    /// the `windows` crate declares as an opaque pointer-sized value, so it is a prefix of the real block rather than
+    /// the whole of it.
+    ///
+    /// # Safety
+    ///
+    /// The layout of the block is an implementation detail of the operating system. It may contain encoded machine
+    /// code, may differ between Windows versions and architectures, and may not contain the associated data at all, so
+    /// the bytes must not be persisted or compared across processes, and identical bytes do not imply identical
+    /// behavior. The block must not be modified while the returned slice is alive, including through
+    /// [`AtlThunk::set_data`] on aliasing objects created by [`AtlThunk::from_raw`].
+    pub unsafe fn data_bytes(&self) -> &[u8] {
         unsafe {
-            #[expect(clippy::transmutes_expressible_as_ptr_casts, reason = "by-design")]
-            let procedure = mem::transmute::<WindowProcedure, *mut c_void>(window_procedure);
+            slice::from_raw_parts(
+                self.raw_thunk_ptr.as_ptr().cast_const().cast::<u8>(),
+                mem::size_of::<AtlThunkData_t>(),
+            )
+        }
+    }
+
+    /// Updates the associated window procedure and data. `first_parameter` can be any value convertible to
+    /// [`FirstParameter`], like a [`HWND`] or a pointer. For more information, see document for
+    /// [`AtlThunk_InitData`](<https://learn.microsoft.com/en-us/windows/win32/api/atlthunk/nf-atlthunk-atlthunk_initdata>).
+    ///
+    /// With the `debug-checks` feature in debug builds, a warning is written to the debugger output if
+    /// `first_parameter` is a valid window handle, which is a common sign of expecting the window procedure to
+    /// receive the window it is called with.
+    #[inline]
+    pub fn set_data(&mut self, window_procedure: WindowProcedure, first_parameter: impl Into<FirstParameter>) {
+        let first_parameter = first_parameter.into();
+
+        #[cfg(all(feature = "debug-checks", debug_assertions))]
+        checks::warn_if_window(first_parameter);
+
+        unsafe { init_data(self.raw_thunk_ptr, window_procedure, first_parameter) };
+
+        #[cfg(feature = "metadata")]
+        {
+            self.data = Some(AssociatedData {
+                window_procedure,
+                first_parameter,
+            });
+        }
+    }
+
+    /// Same as [`AtlThunk::set_data`], but skips updating the associated data tracked by the `metadata` feature, which
+    /// is the only difference. Without the `metadata` feature, this is identical to [`AtlThunk::set_data`].
+    ///
+    /// # Safety
+    ///
+    /// After calling this function, the associated data returned by `AtlThunk::data` is stale, along with everything
+    /// that relies on it. The caller must either not use them, or restore the tracked data with
+    /// [`AtlThunk::set_data`].
+    #[inline]
+    pub unsafe fn set_data_unchecked(
+        &mut self,
+        window_procedure: WindowProcedure,
+        first_parameter: impl Into<FirstParameter>,
+    ) {
+        unsafe { init_data(self.raw_thunk_ptr, window_procedure, first_parameter.into()) };
+    }
+}
+
+/// Creates the error for a failed ATL thunk allocation, with the error code from `GetLastError` and a message
+/// containing `location`.
+#[cold]
+fn allocation_error(location: &Location<'_>) -> crate::sys::core::Error {
+    /// Formats into a fixed size buffer without allocating, truncating overlong output.
+    struct Buffer {
+        data: [u8; 256],
+        length: usize,
+    }
+
+    impl fmt::Write for Buffer {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            let mut length = s.len().min(self.data.len() - self.length);
 
-            let first_parameter = mem::transmute::<HWND, usize>(first_parameter);
+            while !s.is_char_boundary(length) {
+                length -= 1;
+            }
 
-            AtlThunk_InitData(self.raw_thunk_ptr.as_mut(), procedure, first_parameter);
+            self.data[self.length..self.length + length].copy_from_slice(&s.as_bytes()[..length]);
+            self.length += length;
+
+            Ok(())
         }
     }
+
+    #[cfg(windows)]
+    let code = match crate::sys::core::Error::from_win32().code() {
+        code if code.is_ok() => E_OUTOFMEMORY,
+        code => code,
+    };
+
+    // There is no last error code on other hosts.
+    #[cfg(not(windows))]
+    let code = E_OUTOFMEMORY;
+    let mut buffer = Buffer {
+        data: [0; 256],
+        length: 0,
+    };
+
+    _ = fmt::write(
+        &mut buffer,
+        format_args!("failed to allocate an ATL thunk at {location}"),
+    );
+
+    // Only whole characters are written, so the buffer always contains valid UTF-8.
+    crate::sys::core::Error::new(
+        code,
+        core::str::from_utf8(&buffer.data[..buffer.length]).unwrap_or_default(),
+    )
+}
+
+/// Sets the associated data of the ATL thunk pointed by `raw_thunk_ptr`.
+///
+/// # Safety
+///
+/// `raw_thunk_ptr` must point to a live ATL thunk that is not being called or updated by anyone else.
+///
+/// This is only `#[inline]`, like its public callers, so it can still be inlined into them across crates, while keeping
+/// its own frame in unoptimized builds.
+#[inline]
+unsafe fn init_data(
+    raw_thunk_ptr: NonNull<AtlThunkData_t>,
+    window_procedure: WindowProcedure,
+    first_parameter: FirstParameter,
+) {
+    unsafe {
+        #[expect(clippy::transmutes_expressible_as_ptr_casts, reason = "by-design")]
+        let procedure = mem::transmute::<WindowProcedure, *mut c_void>(window_procedure);
+
+        AtlThunk_InitData(raw_thunk_ptr.as_ptr(), procedure, first_parameter.0);
+    }
+
+    #[cfg(feature = "stats")]
+    stats::record_initialize();
 }
 
+#[deny(
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unreachable,
+    clippy::unwrap_used
+)]
 impl Drop for AtlThunk {
     /// For more information, see document for
     /// [`AtlThunk_FreeData`](<https://learn.microsoft.com/en-us/windows/win32/api/atlthunk/nf-atlthunk-atlthunk_freedata>).
-    #[inline(always)]
+    #[inline]
     fn drop(&mut self) {
+        #[cfg(all(feature = "debug-checks", debug_assertions))]
+        checks::untrack_thunk(self.raw_thunk_ptr);
+
         unsafe { AtlThunk_FreeData(self.raw_thunk_ptr.as_ptr()) };
+
+        #[cfg(feature = "stats")]
+        stats::record_free();
+    }
+}
+
+impl From<AtlThunk> for NonNull<AtlThunkData_t> {
+    /// Transfers the ownership of the ATL thunk to the returned pointer, see [`AtlThunk::into_raw`]. The only way to
+    /// convert it back is the unsafe [`AtlThunk::from_raw`] function.
+    fn from(value: AtlThunk) -> Self {
+        value.into_raw()
+    }
+}
+
+impl TryFrom<(WindowProcedure, HWND)> for AtlThunk {
+    type Error = crate::sys::core::Error;
+
+    /// Same as [`AtlThunk::try_new_with`].
+    #[track_caller]
+    fn try_from((window_procedure, first_parameter): (WindowProcedure, HWND)) -> crate::sys::core::Result<Self> {
+        Self::try_new_with(window_procedure, first_parameter)
+    }
+}
+
+impl Debug for AtlThunk {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut debug_struct = f.debug_struct("AtlThunk");
+
+        debug_struct.field("raw_thunk_ptr", &self.raw_thunk_ptr);
+
+        // The emulated ATL thunk is readable, so show what it currently stores.
+        #[cfg(feature = "mock-backend")]
+        {
+            let (window_procedure, first_parameter) = unsafe { mock::stored_data(self.raw_thunk_ptr.as_ptr()) };
+
+            debug_struct
+                .field("stored_window_procedure", &window_procedure)
+                .field("stored_first_parameter", &first_parameter);
+        }
+
+        #[cfg(feature = "metadata")]
+        debug_struct.field("data", &self.data);
+
+        debug_struct.finish()
     }
 }
 
 unsafe impl Send for AtlThunk {}
 unsafe impl Sync for AtlThunk {}
 
+/// Moves `new` into `slot`, and returns the previous [`AtlThunk`] object, like [`mem::replace`]. The returned object
+/// still owns its ATL thunk, so windows that use its window procedure keep working until it is dropped.
+///
+/// [`AtlThunk`] objects do not track the windows that use them, so unlike dropping a `ContextThunk` object with the
+/// `debug-checks` feature, replacing does not check whether the window procedure is still installed. In debug builds,
+/// panics if both objects own the same ATL thunk, which is only possible after misusing [`AtlThunk::from_raw`].
+pub fn replace(slot: &mut AtlThunk, new: AtlThunk) -> AtlThunk {
+    debug_assert_ne!(
+        slot.raw_thunk_ptr, new.raw_thunk_ptr,
+        "replacing an ATL thunk with itself"
+    );
+
+    mem::replace(slot, new)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::AtlThunk;
-    use windows::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use super::{AtlThunk, WindowProcedure};
+    use crate::sys::Win32::Foundation::{E_POINTER, HWND, LPARAM, LRESULT, WPARAM};
+    use crate::sys::Win32::System::Memory::AtlThunkData_t;
+    use crate::test_support::DEFAULT_RESULT;
+    use core::hint;
+    use core::ptr::{self, NonNull};
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_thunk_try_new() {
+        use crate::sys::Win32::UI::WindowsAndMessaging::WM_USER;
+
+        let thunk = AtlThunk::try_new().unwrap();
+
+        // New ATL thunks pass messages to the default window procedure.
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), WM_USER, WPARAM(0), LPARAM(0)) }.0,
+            DEFAULT_RESULT,
+        );
+    }
+
+    #[test]
+    fn test_replace() {
+        unsafe extern "system" fn callback(first_parameter: HWND, _: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+            LRESULT(first_parameter.0 as _)
+        }
+
+        let mut slot = AtlThunk::try_new_with(callback, HWND(2 as _)).unwrap();
+        let old = super::replace(&mut slot, AtlThunk::try_new_with(callback, HWND(3 as _)).unwrap());
+
+        for (thunk, expected) in [(&slot, 3), (&old, 2)] {
+            assert_eq!(
+                unsafe { thunk.as_window_procedure()(HWND::default(), 0, WPARAM(0), LPARAM(0)) }.0,
+                expected,
+            );
+        }
+    }
+
+    #[cfg(all(debug_assertions, not(feature = "debug-checks")))]
+    #[test]
+    #[should_panic(expected = "with itself")]
+    fn test_replace_alias() {
+        use core::mem::ManuallyDrop;
+
+        // The alias frees the ATL thunk while unwinding, so the original object must not free it again.
+        let mut slot = ManuallyDrop::new(AtlThunk::try_new().unwrap());
+        let alias = unsafe { AtlThunk::from_raw(slot.raw_thunk_ptr) };
+
+        _ = super::replace(&mut slot, alias);
+    }
+
+    #[test]
+    fn test_thunk_try_new_with_code() {
+        unsafe extern "system" fn callback(first_parameter: HWND, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+            LRESULT(first_parameter.0 as isize + message as isize)
+        }
+
+        let (thunk, code) = AtlThunk::try_new_with_code(callback, HWND(2 as _)).unwrap();
+
+        assert_eq!(code as usize, thunk.as_window_procedure() as usize);
+        assert_eq!(unsafe { code(HWND::default(), 3, WPARAM(0), LPARAM(0)) }.0, 5);
+    }
+
+    #[test]
+    fn test_thunk_high_bit_first_parameter() {
+        static RECEIVED: AtomicUsize = AtomicUsize::new(0);
+
+        unsafe extern "system" fn callback(first_parameter: HWND, _: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+            RECEIVED.store(first_parameter.0 as _, Ordering::Relaxed);
+
+            LRESULT(0)
+        }
+
+        // All 32 bits are set, which would be mangled by a sign extension or a truncation on x86.
+        let thunk = AtlThunk::try_new_with(callback, 0xFFFF_FFFF_usize).unwrap();
+
+        unsafe { thunk.as_window_procedure()(HWND::default(), 0, WPARAM(0), LPARAM(0)) };
+
+        assert_eq!(RECEIVED.load(Ordering::Relaxed), 0xFFFF_FFFF);
+    }
+
+    #[test]
+    fn test_thunk_data_bytes() {
+        let thunk = AtlThunk::try_new().unwrap();
+        let bytes = unsafe { thunk.data_bytes() };
+
+        assert_eq!(bytes.len(), size_of::<AtlThunkData_t>());
+        assert_eq!(bytes.as_ptr(), thunk.raw_thunk_ptr.as_ptr().cast_const().cast());
+    }
 
     #[test]
     fn test_thunk_try_new_with() {
@@ -165,4 +786,269 @@ mod tests {
             29,
         );
     }
+
+    #[test]
+    fn test_thunk_try_from_tuple() {
+        unsafe extern "system" fn callback(first_parameter: HWND, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+            LRESULT(first_parameter.0 as isize * message as isize)
+        }
+
+        let thunk: AtlThunk = (callback as WindowProcedure, HWND(2 as _)).try_into().unwrap();
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 3, WPARAM(0), LPARAM(0)) }.0,
+            6,
+        );
+    }
+
+    #[cfg(all(feature = "std", windows))]
+    #[test]
+    fn test_thunks_dispatch_concurrently() {
+        use crate::context::CharSet;
+        use crate::sys::core::w;
+        use crate::sys::Win32::UI::WindowsAndMessaging::{
+            CreateWindowExW, DestroyWindow, SendMessageW, HWND_MESSAGE, WINDOW_EX_STYLE, WINDOW_STYLE, WM_USER,
+        };
+        use std::sync::Arc;
+        use std::thread;
+
+        const THREADS: usize = 8;
+        const MESSAGES: usize = 1000;
+
+        unsafe extern "system" fn callback(first_parameter: HWND, message: u32, w_param: WPARAM, _: LPARAM) -> LRESULT {
+            LRESULT((first_parameter.0 as usize * MESSAGES + w_param.0) as isize + (message - WM_USER) as isize)
+        }
+
+        // Shared by all threads, which only access it through shared references.
+        let shared = Arc::new(AtlThunk::try_new_with(callback, HWND(THREADS as _)).unwrap());
+
+        let workers = (0..THREADS)
+            .map(|index| {
+                let shared = Arc::clone(&shared);
+
+                thread::spawn(move || {
+                    // Each thread owns its window, which can only be dispatched to by the thread itself.
+                    let thunk = AtlThunk::try_new_with(callback, HWND(index as _)).unwrap();
+
+                    let window = unsafe {
+                        CreateWindowExW(
+                            WINDOW_EX_STYLE::default(),
+                            w!("STATIC"),
+                            None,
+                            WINDOW_STYLE::default(),
+                            0,
+                            0,
+                            0,
+                            0,
+                            HWND_MESSAGE,
+                            None,
+                            None,
+                            None,
+                        )
+                    }
+                    .unwrap();
+
+                    let base = unsafe {
+                        core::mem::transmute::<usize, WindowProcedure>(crate::subclass::current_window_procedure(
+                            window,
+                            CharSet::Wide,
+                        ))
+                    };
+
+                    unsafe {
+                        crate::subclass::set_window_procedure(window, CharSet::Wide, thunk.as_window_procedure())
+                    };
+
+                    for value in 0..MESSAGES {
+                        let expected = (index * MESSAGES + value) as isize;
+
+                        assert_eq!(
+                            unsafe { SendMessageW(window, WM_USER, WPARAM(value), LPARAM(0)) }.0,
+                            expected
+                        );
+
+                        assert_eq!(
+                            unsafe { shared.as_window_procedure()(HWND::default(), WM_USER, WPARAM(value), LPARAM(0)) }
+                                .0,
+                            (THREADS * MESSAGES + value) as isize,
+                        );
+
+                        #[cfg(feature = "metadata")]
+                        assert_eq!(
+                            shared.data().map(|data| data.first_parameter),
+                            Some(crate::FirstParameter(THREADS)),
+                        );
+                    }
+
+                    unsafe { crate::subclass::set_window_procedure(window, CharSet::Wide, base) };
+                    unsafe { DestroyWindow(window) }.unwrap();
+                })
+            })
+            .collect::<alloc::vec::Vec<_>>();
+
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        #[cfg(feature = "stats")]
+        assert!(crate::stats().allocated > THREADS);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_allocation_error() {
+        let location = core::panic::Location::caller();
+        let message = super::allocation_error(location).message();
+
+        assert!(message.starts_with("failed to allocate an ATL thunk at "));
+        assert!(message.contains(location.file()));
+    }
+
+    #[test]
+    fn test_thunk_as_window_procedure_unchecked() {
+        let thunk = AtlThunk::try_new().unwrap();
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure_unchecked() } as usize,
+            thunk.as_window_procedure() as usize,
+        );
+    }
+
+    #[test]
+    fn test_thunk_into_window_procedure() {
+        unsafe extern "system" fn callback(first_parameter: HWND, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+            LRESULT(first_parameter.0 as isize + message as isize)
+        }
+
+        let window_procedure = AtlThunk::try_new_with(callback, HWND(2 as _))
+            .unwrap()
+            .into_window_procedure();
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), 3, WPARAM(0), LPARAM(0)) }.0,
+            5,
+        );
+    }
+
+    #[test]
+    fn test_thunk_try_new_with_wndproc() {
+        unsafe extern "system" fn callback(
+            first_parameter: HWND,
+            message: u32,
+            w_param: WPARAM,
+            l_param: LPARAM,
+        ) -> LRESULT {
+            LRESULT(first_parameter.0 as isize * message as isize * w_param.0 as isize * l_param.0)
+        }
+
+        assert_eq!(
+            AtlThunk::try_new_with_wndproc(None, HWND(2 as _)).err().unwrap().code(),
+            E_POINTER,
+        );
+
+        let thunk = AtlThunk::try_new_with_wndproc(Some(callback), HWND(2 as _)).unwrap();
+
+        assert_eq!(
+            unsafe { thunk.as_wndproc().unwrap()(HWND::default(), 3, WPARAM(5), LPARAM(7)) }.0,
+            210,
+        );
+    }
+
+    /// Returns an address on the stack of the callee, which changes if the stack pointer of the caller changes.
+    #[inline(never)]
+    fn get_stack_address() -> usize {
+        let local = 0_u8;
+
+        hint::black_box(ptr::addr_of!(local)) as usize
+    }
+
+    #[test]
+    fn test_thunk_calling_convention() {
+        unsafe extern "system" fn callback(
+            first_parameter: HWND,
+            message: u32,
+            w_param: WPARAM,
+            l_param: LPARAM,
+        ) -> LRESULT {
+            // Mix all bits of all parameters, so that a wrong value of any parameter is detected.
+            LRESULT(
+                ((first_parameter.0 as usize).rotate_left(1)
+                    ^ (message as usize).rotate_left(2)
+                    ^ w_param.0.rotate_left(3)
+                    ^ l_param.0 as usize) as isize,
+            )
+        }
+
+        let values = [
+            (0, 0, 0, 0),
+            (usize::MAX, u32::MAX, usize::MAX, -1),
+            (1 << (usize::BITS - 1), 1 << 31, 1 << (usize::BITS - 1), isize::MIN),
+            (usize::MAX >> 1, u32::MAX >> 1, usize::MAX >> 1, isize::MAX),
+            (0x5A5A_5A5A, 0xA5A5_A5A5, 0x5A5A_5A5A, -0x5A5A_5A5A),
+        ];
+
+        let mut thunk = AtlThunk::try_new().unwrap();
+        let stack_address = get_stack_address();
+
+        for (first_parameter, message, w_param, l_param) in values {
+            thunk.set_data(callback, HWND(first_parameter as _));
+
+            let window_procedure = thunk.as_window_procedure();
+            let expected = unsafe { callback(HWND(first_parameter as _), message, WPARAM(w_param), LPARAM(l_param)) };
+
+            // If the calling convention is wrong, for example, the callee cleans the stack on x86 while the caller does
+            // not expect so, the stack pointer will drift after each call.
+            for _ in 0..10_000 {
+                let result = unsafe { window_procedure(HWND::default(), message, WPARAM(w_param), LPARAM(l_param)) };
+
+                assert_eq!(result, expected);
+                assert_eq!(get_stack_address(), stack_address);
+            }
+        }
+    }
+
+    #[test]
+    fn test_thunk_drop_uninitialized() {
+        for _ in 0..10_000 {
+            drop(AtlThunk::try_new().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_thunk_raw_round_trip() {
+        unsafe extern "system" fn callback(
+            first_parameter: HWND,
+            message: u32,
+            w_param: WPARAM,
+            l_param: LPARAM,
+        ) -> LRESULT {
+            LRESULT(first_parameter.0 as isize + message as isize + w_param.0 as isize + l_param.0)
+        }
+
+        let thunk = AtlThunk::try_new_with(callback, HWND(2 as _)).unwrap();
+        let window_procedure = thunk.as_window_procedure();
+        let raw_thunk_ptr = NonNull::<AtlThunkData_t>::from(thunk);
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), 3, WPARAM(5), LPARAM(7)) }.0,
+            17,
+        );
+
+        let thunk = unsafe { AtlThunk::from_raw(raw_thunk_ptr) };
+
+        assert_eq!(thunk.as_window_procedure() as usize, window_procedure as usize);
+        assert_eq!(thunk.into_raw(), raw_thunk_ptr);
+
+        drop(unsafe { AtlThunk::from_raw(raw_thunk_ptr) });
+    }
+
+    #[cfg(not(feature = "metadata"))]
+    #[test]
+    fn test_thunk_size() {
+        use core::mem;
+
+        // Without the `metadata` feature, there is no per-thunk storage besides the raw pointer.
+        assert_eq!(mem::size_of::<AtlThunk>(), mem::size_of::<NonNull<AtlThunkData_t>>());
+        assert_eq!(mem::size_of::<Option<AtlThunk>>(), mem::size_of::<AtlThunk>());
+    }
 }
@@ -0,0 +1,221 @@
+//! Decoded window messages for handlers that prefer typed parameters.
+
+#[cfg(feature = "alloc")]
+use crate::context::{self, ContextThunk};
+use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use crate::sys::Win32::UI::WindowsAndMessaging::{
+    WM_CLOSE, WM_COMMAND, WM_DESTROY, WM_INITDIALOG, WM_PAINT, WM_SIZE, WM_TIMER,
+};
+
+fn low_word(value: usize) -> u16 {
+    value as u16
+}
+
+fn high_word(value: usize) -> u16 {
+    (value >> 16) as u16
+}
+
+/// A window message with decoded parameters. Messages that are not decoded are kept in [`Message::Other`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Message {
+    /// [`WM_CLOSE`](https://learn.microsoft.com/en-us/windows/win32/winmsg/wm-close).
+    Close,
+    /// [`WM_COMMAND`](https://learn.microsoft.com/en-us/windows/win32/menurc/wm-command).
+    Command {
+        /// Identifier of the menu item, accelerator or control.
+        id: u16,
+        /// Notification code, which is 0 for menus and 1 for accelerators.
+        code: u16,
+        /// Handle of the control window, or a null handle for menus and accelerators.
+        control: HWND,
+    },
+    /// [`WM_DESTROY`](https://learn.microsoft.com/en-us/windows/win32/winmsg/wm-destroy).
+    Destroy,
+    /// [`WM_INITDIALOG`](https://learn.microsoft.com/en-us/windows/win32/dlgbox/wm-initdialog).
+    InitDialog {
+        /// Handle of the control that receives the default keyboard focus.
+        focus: HWND,
+        /// Initialization parameter passed to functions like `DialogBoxParamW`.
+        parameter: LPARAM,
+    },
+    /// [`WM_PAINT`](https://learn.microsoft.com/en-us/windows/win32/gdi/wm-paint).
+    Paint,
+    /// [`WM_SIZE`](https://learn.microsoft.com/en-us/windows/win32/winmsg/wm-size).
+    Size {
+        /// Type of resizing, like `SIZE_RESTORED` or `SIZE_MINIMIZED`.
+        kind: u32,
+        /// New width of the client area.
+        width: u16,
+        /// New height of the client area.
+        height: u16,
+    },
+    /// [`WM_TIMER`](https://learn.microsoft.com/en-us/windows/win32/winmsg/wm-timer).
+    Timer {
+        /// Identifier of the timer.
+        id: usize,
+    },
+    /// Any other message, with raw parameters.
+    Other {
+        /// The message.
+        message: u32,
+        /// The `WPARAM` parameter.
+        w_param: WPARAM,
+        /// The `LPARAM` parameter.
+        l_param: LPARAM,
+    },
+}
+
+impl Message {
+    /// Decodes a window message from raw parameters.
+    pub fn decode(message: u32, w_param: WPARAM, l_param: LPARAM) -> Self {
+        match message {
+            WM_CLOSE => Self::Close,
+            WM_COMMAND => Self::Command {
+                id: low_word(w_param.0),
+                code: high_word(w_param.0),
+                control: HWND(l_param.0 as _),
+            },
+            WM_DESTROY => Self::Destroy,
+            WM_INITDIALOG => Self::InitDialog {
+                focus: HWND(w_param.0 as _),
+                parameter: l_param,
+            },
+            WM_PAINT => Self::Paint,
+            WM_SIZE => Self::Size {
+                kind: w_param.0 as _,
+                width: low_word(l_param.0 as _),
+                height: high_word(l_param.0 as _),
+            },
+            WM_TIMER => Self::Timer { id: w_param.0 },
+            _ => Self::Other {
+                message,
+                w_param,
+                l_param,
+            },
+        }
+    }
+}
+
+/// Handler of decoded window messages. Returning [`None`] passes the message to the default window procedure. See
+/// [`WindowHandler`](crate::WindowHandler) for reentrancy and panic behaviors, which also apply to this trait.
+pub trait TypedWindowHandler {
+    /// Handles a decoded window message.
+    fn handle(&self, message: Message) -> Option<LRESULT>;
+}
+
+impl<F> TypedWindowHandler for F
+where
+    F: Fn(Message) -> Option<LRESULT>,
+{
+    fn handle(&self, message: Message) -> Option<LRESULT> {
+        self(message)
+    }
+}
+
+#[cfg(feature = "alloc")]
+unsafe extern "system" fn typed_handler_window_procedure<H>(
+    handler: HWND,
+    message: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> LRESULT
+where
+    H: TypedWindowHandler,
+{
+    let handler = handler.0.cast::<H>().cast_const();
+    let header = unsafe { context::context_header(handler) };
+
+    match header.dispatch(|| unsafe { (*handler).handle(Message::decode(message, w_param, l_param)) }) {
+        None => header.default_process(message, w_param, l_param),
+        Some(result) => result,
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<H> ContextThunk<H>
+where
+    H: TypedWindowHandler,
+{
+    /// Creates a new [`ContextThunk`] object that owns `handler`, and dispatches decoded messages to it. Messages for
+    /// which the handler returns [`None`] are passed to
+    /// [`DefWindowProcW`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-defwindowprocw), which
+    /// needs the window bound with [`ContextThunk::bind_window`].
+    #[track_caller]
+    pub fn for_typed_handler(handler: H) -> crate::sys::core::Result<Self> {
+        unsafe { Self::try_new_with_procedure(handler, typed_handler_window_procedure::<H>) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Message;
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, WPARAM};
+    use crate::sys::Win32::UI::WindowsAndMessaging::{SIZE_MAXIMIZED, WM_COMMAND, WM_PAINT, WM_SIZE, WM_USER};
+
+    #[test]
+    fn test_message_decode() {
+        assert_eq!(
+            Message::decode(WM_COMMAND, WPARAM(0x0001_0002), LPARAM(3)),
+            Message::Command {
+                id: 2,
+                code: 1,
+                control: HWND(3 as _),
+            },
+        );
+
+        assert_eq!(
+            Message::decode(WM_SIZE, WPARAM(SIZE_MAXIMIZED as _), LPARAM(0x0300_0400)),
+            Message::Size {
+                kind: SIZE_MAXIMIZED,
+                width: 0x400,
+                height: 0x300,
+            },
+        );
+
+        assert_eq!(Message::decode(WM_PAINT, WPARAM(0), LPARAM(0)), Message::Paint);
+
+        assert_eq!(
+            Message::decode(WM_USER, WPARAM(5), LPARAM(-7)),
+            Message::Other {
+                message: WM_USER,
+                w_param: WPARAM(5),
+                l_param: LPARAM(-7),
+            },
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_context_thunk_for_typed_handler() {
+        use crate::sys::Win32::Foundation::LRESULT;
+        use crate::sys::Win32::UI::WindowsAndMessaging::WM_CLOSE;
+        use crate::test_support::DEFAULT_RESULT;
+        use crate::ContextThunk;
+
+        let thunk = ContextThunk::for_typed_handler(|message| match message {
+            Message::Close => Some(LRESULT(1)),
+            Message::Size { width, height, .. } => Some(LRESULT(width as isize * height as isize)),
+            _ => None,
+        })
+        .unwrap();
+
+        let window_procedure = thunk.as_window_procedure();
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_CLOSE, WPARAM(0), LPARAM(0)) }.0,
+            1,
+        );
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_SIZE, WPARAM(0), LPARAM(0x0002_0003)) }.0,
+            6,
+        );
+
+        // Messages that are not handled are passed to the default window procedure.
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_USER, WPARAM(0), LPARAM(0)) }.0,
+            DEFAULT_RESULT,
+        );
+    }
+}
@@ -0,0 +1,237 @@
+//! Tracking of the associated data last set through [`AtlThunk`] methods.
+
+use crate::{AtlThunk, FirstParameter, WindowProcedure};
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+/// The associated data of an ATL thunk, see [`AtlThunk::data`].
+#[derive(Clone, Copy, Debug)]
+pub struct AssociatedData {
+    /// The wrapped window procedure.
+    pub window_procedure: WindowProcedure,
+    /// The value passed to the wrapped window procedure in place of the window handle.
+    pub first_parameter: FirstParameter,
+}
+
+impl AtlThunk {
+    /// Returns the associated data last set through [`AtlThunk::set_data`] or the constructors that call it. Returns
+    /// [`None`] if the associated data has not been set, or has been set by other means, like objects created by
+    /// [`AtlThunk::from_raw`] or [`UninitAtlThunk::assume_init`](crate::UninitAtlThunk::assume_init).
+    pub fn data(&self) -> Option<AssociatedData> {
+        self.data
+    }
+
+    fn tracked_data(&self) -> AssociatedData {
+        self.data.expect(
+            "the associated data of the ATL thunk is not tracked, it must be set with `AtlThunk::set_data` first",
+        )
+    }
+
+    /// Updates the associated window procedure, and keeps the current first parameter.
+    ///
+    /// # Panics
+    ///
+    /// Panics if [`AtlThunk::data`] returns [`None`].
+    pub fn set_procedure(&mut self, window_procedure: WindowProcedure) {
+        let data = self.tracked_data();
+
+        self.set_data(window_procedure, data.first_parameter);
+    }
+
+    /// Updates the associated first parameter, and keeps the current window procedure.
+    ///
+    /// # Panics
+    ///
+    /// Panics if [`AtlThunk::data`] returns [`None`].
+    pub fn set_first_parameter(&mut self, first_parameter: impl Into<FirstParameter>) {
+        let data = self.tracked_data();
+
+        self.set_data(data.window_procedure, first_parameter);
+    }
+
+    /// Creates a new [`AtlThunk`] object with the same window procedure and `first_parameter`, for creating multiple
+    /// instances from a template.
+    ///
+    /// # Panics
+    ///
+    /// Panics if [`AtlThunk::data`] returns [`None`].
+    #[track_caller]
+    pub fn clone_with(&self, first_parameter: impl Into<FirstParameter>) -> crate::sys::core::Result<Self> {
+        Self::try_new_with(self.tracked_data().window_procedure, first_parameter)
+    }
+
+    /// Same as [`AtlThunk::clone_with`], but creates an [`AtlThunk`] object for each of `first_parameters`. If any of
+    /// the allocations fails, the already created objects are dropped and the error is returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if [`AtlThunk::data`] returns [`None`].
+    #[cfg(feature = "alloc")]
+    #[track_caller]
+    pub fn clone_many_with<P>(
+        &self,
+        first_parameters: impl IntoIterator<Item = P>,
+    ) -> crate::sys::core::Result<Vec<Self>>
+    where
+        P: Into<FirstParameter>,
+    {
+        let window_procedure = self.tracked_data().window_procedure;
+        let first_parameters = first_parameters.into_iter();
+        let mut thunks = Vec::with_capacity(first_parameters.size_hint().0);
+
+        // A loop instead of a closure, so allocation errors carry the location of the caller.
+        for first_parameter in first_parameters {
+            thunks.push(Self::try_new_with(window_procedure, first_parameter)?);
+        }
+
+        Ok(thunks)
+    }
+
+    /// Exchanges the associated data with `other`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if [`AtlThunk::data`] returns [`None`] for either object. In debug builds, also panics if both objects
+    /// own the same ATL thunk, which is only possible after misusing [`AtlThunk::from_raw`].
+    pub fn swap_data(&mut self, other: &mut Self) {
+        debug_assert_ne!(
+            self.raw_thunk_ptr, other.raw_thunk_ptr,
+            "swapping the associated data of an ATL thunk with itself"
+        );
+
+        let data = self.tracked_data();
+        let other_data = other.tracked_data();
+
+        self.set_data(other_data.window_procedure, other_data.first_parameter);
+        other.set_data(data.window_procedure, data.first_parameter);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use crate::{AtlThunk, FirstParameter};
+
+    unsafe extern "system" fn callback_1(first_parameter: HWND, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+        LRESULT(first_parameter.0 as isize + message as isize)
+    }
+
+    unsafe extern "system" fn callback_2(first_parameter: HWND, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+        LRESULT(first_parameter.0 as isize * message as isize)
+    }
+
+    #[test]
+    fn test_thunk_partial_updates() {
+        let mut thunk = AtlThunk::try_new_with(callback_1, HWND(2 as _)).unwrap();
+
+        assert_eq!(thunk.data().map(|data| data.first_parameter), Some(FirstParameter(2)));
+
+        thunk.set_procedure(callback_2);
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 3, WPARAM(0), LPARAM(0)) }.0,
+            6,
+        );
+
+        thunk.set_first_parameter(HWND(5 as _));
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 3, WPARAM(0), LPARAM(0)) }.0,
+            15,
+        );
+
+        assert_eq!(
+            thunk.data().map(|data| data.window_procedure as usize),
+            Some(callback_2 as *const () as usize),
+        );
+
+        assert_eq!(thunk.data().map(|data| data.first_parameter), Some(FirstParameter(5)));
+
+        assert!(AtlThunk::try_new().unwrap().data().is_none());
+    }
+
+    #[test]
+    fn test_thunk_set_data_unchecked() {
+        let mut thunk = AtlThunk::try_new_with(callback_1, HWND(2 as _)).unwrap();
+
+        unsafe { thunk.set_data_unchecked(callback_2, HWND(3 as _)) };
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 5, WPARAM(0), LPARAM(0)) }.0,
+            15,
+        );
+
+        // The tracked data is stale.
+        assert_eq!(thunk.data().map(|data| data.first_parameter), Some(FirstParameter(2)));
+    }
+
+    #[test]
+    fn test_thunk_clone_with() {
+        let thunk = AtlThunk::try_new_with(callback_1, HWND(2 as _)).unwrap();
+        let clone = thunk.clone_with(HWND(3 as _)).unwrap();
+
+        assert_eq!(
+            unsafe { clone.as_window_procedure()(HWND::default(), 5, WPARAM(0), LPARAM(0)) }.0,
+            8,
+        );
+
+        assert_eq!(clone.data().map(|data| data.first_parameter), Some(FirstParameter(3)));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_thunk_clone_many_with() {
+        let thunk = AtlThunk::try_new_with(callback_2, HWND(2 as _)).unwrap();
+        let clones = thunk.clone_many_with([3, 5, 7].map(|value| HWND(value as _))).unwrap();
+
+        assert_eq!(clones.len(), 3);
+
+        for (clone, expected) in clones.iter().zip([33, 55, 77]) {
+            assert_eq!(
+                unsafe { clone.as_window_procedure()(HWND::default(), 11, WPARAM(0), LPARAM(0)) }.0,
+                expected,
+            );
+        }
+    }
+
+    #[test]
+    fn test_thunk_swap_data() {
+        let mut thunk_1 = AtlThunk::try_new_with(callback_1, HWND(2 as _)).unwrap();
+        let mut thunk_2 = AtlThunk::try_new_with(callback_2, HWND(3 as _)).unwrap();
+
+        thunk_1.swap_data(&mut thunk_2);
+
+        assert_eq!(
+            unsafe { thunk_1.as_window_procedure()(HWND::default(), 5, WPARAM(0), LPARAM(0)) }.0,
+            15,
+        );
+
+        assert_eq!(
+            unsafe { thunk_2.as_window_procedure()(HWND::default(), 5, WPARAM(0), LPARAM(0)) }.0,
+            7,
+        );
+
+        assert_eq!(thunk_1.data().map(|data| data.first_parameter), Some(FirstParameter(3)));
+        assert_eq!(thunk_2.data().map(|data| data.first_parameter), Some(FirstParameter(2)));
+    }
+
+    #[cfg(all(debug_assertions, not(feature = "debug-checks")))]
+    #[test]
+    #[should_panic(expected = "with itself")]
+    fn test_thunk_swap_data_alias() {
+        use core::mem::ManuallyDrop;
+
+        let mut thunk = AtlThunk::try_new_with(callback_1, HWND(2 as _)).unwrap();
+
+        // The alias must not free the ATL thunk again.
+        let mut alias = ManuallyDrop::new(unsafe { AtlThunk::from_raw(thunk.raw_thunk_ptr) });
+
+        thunk.swap_data(&mut alias);
+    }
+
+    #[test]
+    #[should_panic(expected = "not tracked")]
+    fn test_thunk_set_procedure_without_data() {
+        AtlThunk::try_new().unwrap().set_procedure(callback_1);
+    }
+}
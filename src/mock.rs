@@ -0,0 +1,353 @@
+//! Pure Rust emulation of `atlthunk.dll`, for testing without the real DLL, including testing with Miri.
+//!
+//! With the `mock-backend` feature, the crate also builds on hosts other than Windows, where the items of the `windows`
+//! crate are replaced by the stand-ins in `sys.rs`, so that the pure Rust parts can be tested anywhere.
+//!
+//! ATL thunks are emulated with a fixed number of statically allocated slots, each of which has its own trampoline
+//! function, so no executable memory is needed.
+
+use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use crate::sys::Win32::System::Memory::AtlThunkData_t;
+use crate::sys::Win32::UI::WindowsAndMessaging::WNDPROC;
+use crate::{FirstParameter, WindowProcedure};
+use core::ffi::c_void;
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+const ROW_LENGTH: usize = 16;
+const SLOT_COUNT: usize = ROW_LENGTH * ROW_LENGTH;
+
+struct Slot {
+    used: AtomicBool,
+    procedure: AtomicPtr<c_void>,
+    first_parameter: AtomicUsize,
+}
+
+#[expect(
+    clippy::declare_interior_mutable_const,
+    reason = "only used for initializing `SLOTS`"
+)]
+const EMPTY_SLOT: Slot = Slot {
+    used: AtomicBool::new(false),
+    procedure: AtomicPtr::new(ptr::null_mut()),
+    first_parameter: AtomicUsize::new(0),
+};
+
+static SLOTS: [Slot; SLOT_COUNT] = [EMPTY_SLOT; SLOT_COUNT];
+
+unsafe extern "system" fn trampoline<const ROW: usize, const COLUMN: usize>(
+    _: HWND,
+    message: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> LRESULT {
+    let slot = &SLOTS[ROW * ROW_LENGTH + COLUMN];
+    let procedure = unsafe { mem::transmute::<*mut c_void, WindowProcedure>(slot.procedure.load(Ordering::Acquire)) };
+    let first_parameter = HWND(slot.first_parameter.load(Ordering::Relaxed) as _);
+
+    unsafe { procedure(first_parameter, message, w_param, l_param) }
+}
+
+const fn trampoline_row<const ROW: usize>() -> [WindowProcedure; ROW_LENGTH] {
+    [
+        trampoline::<ROW, 0>,
+        trampoline::<ROW, 1>,
+        trampoline::<ROW, 2>,
+        trampoline::<ROW, 3>,
+        trampoline::<ROW, 4>,
+        trampoline::<ROW, 5>,
+        trampoline::<ROW, 6>,
+        trampoline::<ROW, 7>,
+        trampoline::<ROW, 8>,
+        trampoline::<ROW, 9>,
+        trampoline::<ROW, 10>,
+        trampoline::<ROW, 11>,
+        trampoline::<ROW, 12>,
+        trampoline::<ROW, 13>,
+        trampoline::<ROW, 14>,
+        trampoline::<ROW, 15>,
+    ]
+}
+
+static TRAMPOLINES: [[WindowProcedure; ROW_LENGTH]; ROW_LENGTH] = [
+    trampoline_row::<0>(),
+    trampoline_row::<1>(),
+    trampoline_row::<2>(),
+    trampoline_row::<3>(),
+    trampoline_row::<4>(),
+    trampoline_row::<5>(),
+    trampoline_row::<6>(),
+    trampoline_row::<7>(),
+    trampoline_row::<8>(),
+    trampoline_row::<9>(),
+    trampoline_row::<10>(),
+    trampoline_row::<11>(),
+    trampoline_row::<12>(),
+    trampoline_row::<13>(),
+    trampoline_row::<14>(),
+    trampoline_row::<15>(),
+];
+
+unsafe fn get_slot<'a>(thunk: *mut AtlThunkData_t) -> (usize, &'a Slot) {
+    let slot = thunk.cast_const().cast::<Slot>();
+    let index = unsafe { slot.offset_from(SLOTS.as_ptr()) } as usize;
+
+    (index, unsafe { &*slot })
+}
+
+/// Emulates `AtlThunk_AllocateData`. Returns a null pointer if all slots are in use.
+#[expect(non_snake_case, reason = "emulated function")]
+pub(crate) unsafe fn AtlThunk_AllocateData() -> *mut AtlThunkData_t {
+    SLOTS
+        .iter()
+        .find(|slot| !slot.used.swap(true, Ordering::Acquire))
+        .map_or(ptr::null_mut(), |slot| ptr::from_ref(slot).cast_mut().cast())
+}
+
+/// Emulates `AtlThunk_DataToCode`.
+#[expect(non_snake_case, reason = "emulated function")]
+pub(crate) unsafe fn AtlThunk_DataToCode(thunk: *mut AtlThunkData_t) -> WNDPROC {
+    let (index, _) = unsafe { get_slot(thunk) };
+
+    Some(TRAMPOLINES[index / ROW_LENGTH][index % ROW_LENGTH])
+}
+
+/// Emulates `AtlThunk_FreeData`.
+#[expect(non_snake_case, reason = "emulated function")]
+pub(crate) unsafe fn AtlThunk_FreeData(thunk: *mut AtlThunkData_t) {
+    let (_, slot) = unsafe { get_slot(thunk) };
+
+    slot.procedure.store(ptr::null_mut(), Ordering::Relaxed);
+    slot.used.store(false, Ordering::Release);
+}
+
+/// Emulates `AtlThunk_InitData`.
+#[expect(non_snake_case, reason = "emulated function")]
+pub(crate) unsafe fn AtlThunk_InitData(thunk: *mut AtlThunkData_t, proc: *mut c_void, first_parameter: usize) {
+    let (_, slot) = unsafe { get_slot(thunk) };
+
+    slot.first_parameter.store(first_parameter, Ordering::Relaxed);
+    slot.procedure.store(proc, Ordering::Release);
+}
+
+/// Returns the window procedure and the first parameter currently stored in an emulated ATL thunk.
+pub(crate) unsafe fn stored_data(thunk: *mut AtlThunkData_t) -> (*mut c_void, FirstParameter) {
+    let (_, slot) = unsafe { get_slot(thunk) };
+
+    (
+        slot.procedure.load(Ordering::Acquire),
+        FirstParameter(slot.first_parameter.load(Ordering::Relaxed)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SLOT_COUNT;
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use crate::sys::Win32::System::Memory::AtlThunkData_t;
+    use crate::sys::Win32::UI::WindowsAndMessaging::WNDPROC;
+    use crate::{AtlThunk, WindowProcedure};
+    use core::ffi::c_void;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_mock_backend_distinct_trampolines() {
+        unsafe extern "system" fn callback(first_parameter: HWND, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+            LRESULT(first_parameter.0 as isize * message as isize)
+        }
+
+        let first = AtlThunk::try_new_with(callback, HWND(2 as _)).unwrap();
+        let second = AtlThunk::try_new_with(callback, HWND(3 as _)).unwrap();
+
+        assert_ne!(
+            first.as_window_procedure() as usize,
+            second.as_window_procedure() as usize
+        );
+
+        assert_eq!(
+            unsafe { first.as_window_procedure()(HWND::default(), 5, WPARAM(0), LPARAM(0)) }.0,
+            10,
+        );
+
+        assert_eq!(
+            unsafe { second.as_window_procedure()(HWND::default(), 5, WPARAM(0), LPARAM(0)) }.0,
+            15,
+        );
+
+        // Freed slots are reused.
+        for _ in 0..SLOT_COUNT * 2 {
+            drop(AtlThunk::try_new().unwrap());
+        }
+    }
+
+    /// The ATL thunk functions of a backend, so the same operations can be run against both backends.
+    trait Backend {
+        unsafe fn allocate_data() -> *mut AtlThunkData_t;
+        unsafe fn data_to_code(thunk: *mut AtlThunkData_t) -> WNDPROC;
+        unsafe fn free_data(thunk: *mut AtlThunkData_t);
+        unsafe fn init_data(thunk: *mut AtlThunkData_t, proc: *mut c_void, first_parameter: usize);
+    }
+
+    struct Mock;
+
+    impl Backend for Mock {
+        unsafe fn allocate_data() -> *mut AtlThunkData_t {
+            unsafe { super::AtlThunk_AllocateData() }
+        }
+
+        unsafe fn data_to_code(thunk: *mut AtlThunkData_t) -> WNDPROC {
+            unsafe { super::AtlThunk_DataToCode(thunk) }
+        }
+
+        unsafe fn free_data(thunk: *mut AtlThunkData_t) {
+            unsafe { super::AtlThunk_FreeData(thunk) }
+        }
+
+        unsafe fn init_data(thunk: *mut AtlThunkData_t, proc: *mut c_void, first_parameter: usize) {
+            unsafe { super::AtlThunk_InitData(thunk, proc, first_parameter) }
+        }
+    }
+
+    #[cfg(windows)]
+    struct Real;
+
+    #[cfg(windows)]
+    #[cfg_attr(
+        target_arch = "x86",
+        link(
+            name = "atlthunk.dll",
+            kind = "raw-dylib",
+            modifiers = "+verbatim",
+            import_name_type = "undecorated"
+        )
+    )]
+    #[cfg_attr(
+        not(target_arch = "x86"),
+        link(name = "atlthunk.dll", kind = "raw-dylib", modifiers = "+verbatim")
+    )]
+    extern "system" {
+        fn AtlThunk_AllocateData() -> *mut AtlThunkData_t;
+        fn AtlThunk_DataToCode(thunk: *mut AtlThunkData_t) -> WNDPROC;
+        fn AtlThunk_FreeData(thunk: *mut AtlThunkData_t);
+        fn AtlThunk_InitData(thunk: *mut AtlThunkData_t, proc: *mut c_void, first_parameter: usize);
+    }
+
+    #[cfg(windows)]
+    impl Backend for Real {
+        unsafe fn allocate_data() -> *mut AtlThunkData_t {
+            unsafe { AtlThunk_AllocateData() }
+        }
+
+        unsafe fn data_to_code(thunk: *mut AtlThunkData_t) -> WNDPROC {
+            unsafe { AtlThunk_DataToCode(thunk) }
+        }
+
+        unsafe fn free_data(thunk: *mut AtlThunkData_t) {
+            unsafe { AtlThunk_FreeData(thunk) }
+        }
+
+        unsafe fn init_data(thunk: *mut AtlThunkData_t, proc: *mut c_void, first_parameter: usize) {
+            unsafe { AtlThunk_InitData(thunk, proc, first_parameter) }
+        }
+    }
+
+    /// Runs a fixed sequence of operations, and returns the arguments received by the window procedures along with the
+    /// returned values.
+    fn run_sequence<B>() -> [(usize, u32, usize, isize, isize); 4]
+    where
+        B: Backend,
+    {
+        static RECEIVED: [AtomicUsize; 4] = [const { AtomicUsize::new(0) }; 4];
+
+        unsafe extern "system" fn record(
+            first_parameter: HWND,
+            message: u32,
+            w_param: WPARAM,
+            l_param: LPARAM,
+        ) -> LRESULT {
+            RECEIVED[0].store(first_parameter.0 as _, Ordering::Relaxed);
+            RECEIVED[1].store(message as _, Ordering::Relaxed);
+            RECEIVED[2].store(w_param.0, Ordering::Relaxed);
+            RECEIVED[3].store(l_param.0 as _, Ordering::Relaxed);
+
+            LRESULT(first_parameter.0 as isize ^ l_param.0)
+        }
+
+        unsafe extern "system" fn negate(
+            first_parameter: HWND,
+            message: u32,
+            w_param: WPARAM,
+            l_param: LPARAM,
+        ) -> LRESULT {
+            LRESULT(-unsafe { record(first_parameter, message, w_param, l_param) }.0)
+        }
+
+        let mut results = [(0, 0, 0, 0, 0); 4];
+
+        unsafe {
+            let thunk = B::allocate_data();
+
+            assert!(!thunk.is_null());
+
+            let calls = [
+                (record as WindowProcedure, 2, 3, 5, -7),
+                (record, usize::MAX, u32::MAX, usize::MAX, isize::MIN),
+                (negate, 11, 13, 17, 19),
+                (negate, 0, 0, 0, 0),
+            ];
+
+            for (result, (procedure, first_parameter, message, w_param, l_param)) in results.iter_mut().zip(calls) {
+                B::init_data(thunk, procedure as *mut c_void, first_parameter);
+
+                // The window handle argument is replaced, so it must not be observable.
+                let returned = B::data_to_code(thunk).unwrap()(HWND(1 as _), message, WPARAM(w_param), LPARAM(l_param));
+
+                *result = (
+                    RECEIVED[0].load(Ordering::Relaxed),
+                    RECEIVED[1].load(Ordering::Relaxed) as _,
+                    RECEIVED[2].load(Ordering::Relaxed),
+                    RECEIVED[3].load(Ordering::Relaxed) as _,
+                    returned.0,
+                );
+            }
+
+            B::free_data(thunk);
+        }
+
+        results
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_mock_backend_debug() {
+        unsafe extern "system" fn callback(_: HWND, _: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+            LRESULT(0)
+        }
+
+        let thunk = AtlThunk::try_new_with(callback, HWND(0x1234 as _)).unwrap();
+        let debug = alloc::format!("{thunk:?}");
+
+        assert!(debug.contains(&alloc::format!("stored_window_procedure: {:?}", callback as *const ())));
+        assert!(debug.contains("stored_first_parameter: FirstParameter(4660)"));
+    }
+
+    #[test]
+    fn test_mock_backend_sequence() {
+        assert_eq!(
+            run_sequence::<Mock>(),
+            [
+                (2, 3, 5, -7, -5),
+                (usize::MAX, u32::MAX, usize::MAX, isize::MIN, isize::MAX),
+                (11, 13, 17, 19, -24),
+                (0, 0, 0, 0, 0),
+            ],
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_mock_backend_matches_real_backend() {
+        assert_eq!(run_sequence::<Mock>(), run_sequence::<Real>());
+    }
+}
@@ -0,0 +1,448 @@
+//! Reusable ATL thunk allocations.
+
+#[cfg(feature = "stats")]
+use crate::stats;
+use crate::{AtlThunk, ContextThunk, UninitAtlThunk, WindowHandler, WindowProcedure};
+use alloc::boxed::Box;
+use alloc::collections::BTreeSet;
+use alloc::vec::{self, Vec};
+#[cfg(feature = "debug-inspect")]
+use core::any::TypeId;
+#[cfg(feature = "debug-inspect")]
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+#[cfg(feature = "debug-inspect")]
+use core::slice;
+
+/// A pool of idle ATL thunks, for reusing allocations instead of allocating a new ATL thunk for every window. Empty
+/// pools, like the ones created by [`AtlThunkPool::new`] or [`Default::default`], do not allocate or free any ATL
+/// thunk until used.
+///
+/// With the `stats` feature, the counts of all pools are also summed in [`ThunkStats`](crate::ThunkStats).
+#[derive(Default)]
+pub struct AtlThunkPool {
+    idle: Vec<AtlThunk>,
+    /// Addresses of the ATL thunks acquired from the pool and not yet released.
+    acquired: BTreeSet<usize>,
+}
+
+impl AtlThunkPool {
+    /// Creates a new empty [`AtlThunkPool`] object.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes an idle ATL thunk from the pool, or allocates a new one if the pool is empty. The associated data of a
+    /// reused ATL thunk is left over from its previous user, so it is returned as an [`UninitAtlThunk`] object which
+    /// needs to be initialized before use.
+    #[track_caller]
+    pub fn acquire(&mut self) -> crate::sys::core::Result<UninitAtlThunk> {
+        let reused = self.idle.pop();
+
+        #[cfg(feature = "stats")]
+        stats::record_pool_acquire(reused.is_some());
+
+        let thunk = match reused {
+            None => AtlThunk::allocate()?,
+            Some(thunk) => thunk,
+        };
+
+        self.acquired.insert(thunk.raw_thunk_ptr.as_ptr() as usize);
+
+        Ok(UninitAtlThunk::from_thunk(thunk))
+    }
+
+    /// Returns an ATL thunk to the pool for later reuse. The caller must make sure the ATL thunk is no longer used as
+    /// the window procedure of any window. ATL thunks that are not acquired from this pool are also kept for reuse,
+    /// but do not change [`AtlThunkPool::live_count`].
+    pub fn release(&mut self, thunk: AtlThunk) {
+        #[cfg_attr(
+            not(feature = "stats"),
+            expect(unused_variables, reason = "only used by `stats` feature")
+        )]
+        let acquired = self.acquired.remove(&(thunk.raw_thunk_ptr.as_ptr() as usize));
+
+        #[cfg(feature = "stats")]
+        stats::record_pool_release(acquired);
+
+        self.idle.push(thunk);
+    }
+
+    /// Returns the number of idle ATL thunks in the pool.
+    pub fn idle_count(&self) -> usize {
+        self.idle.len()
+    }
+
+    /// Returns the number of ATL thunks acquired from the pool and not yet released to it.
+    pub fn live_count(&self) -> usize {
+        self.acquired.len()
+    }
+
+    /// Returns the number of idle ATL thunks the pool can hold without reallocating its storage.
+    pub fn capacity(&self) -> usize {
+        self.idle.capacity()
+    }
+
+    /// Removes all idle ATL thunks from the pool, and returns them through an iterator. Unlike dropping the pool, this
+    /// allows inspecting each ATL thunk before it is freed.
+    pub fn drain(&mut self) -> PoolDrain<'_> {
+        #[cfg(feature = "stats")]
+        stats::record_pool_forget(self.idle.len(), 0);
+
+        PoolDrain {
+            inner: self.idle.drain(..),
+        }
+    }
+}
+
+#[cfg(feature = "stats")]
+impl Drop for AtlThunkPool {
+    fn drop(&mut self) {
+        stats::record_pool_forget(self.idle.len(), self.acquired.len());
+    }
+}
+
+/// An iterator that takes idle ATL thunks out of an [`AtlThunkPool`] object, see [`AtlThunkPool::drain`]. ATL thunks
+/// that are not yielded are freed when the iterator is dropped.
+pub struct PoolDrain<'a> {
+    inner: vec::Drain<'a, AtlThunk>,
+}
+
+impl Iterator for PoolDrain<'_> {
+    type Item = AtlThunk;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for PoolDrain<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back()
+    }
+}
+
+impl ExactSizeIterator for PoolDrain<'_> {}
+
+/// A [`ContextThunk`] object owned by a [`ContextThunkPool`], with its context type erased.
+struct PooledContext {
+    thunk: NonNull<()>,
+    window_procedure: WindowProcedure,
+    release: unsafe fn(NonNull<()>) -> AtlThunk,
+    #[cfg(feature = "debug-inspect")]
+    type_id: TypeId,
+}
+
+impl PooledContext {
+    fn new<H>(thunk: ContextThunk<H>) -> Self
+    where
+        H: 'static,
+    {
+        unsafe fn release<H>(thunk: NonNull<()>) -> AtlThunk {
+            unsafe { Box::from_raw(thunk.cast::<ContextThunk<H>>().as_ptr()) }.into_thunk()
+        }
+
+        Self {
+            window_procedure: thunk.as_window_procedure(),
+            thunk: NonNull::from(Box::leak(Box::new(thunk))).cast(),
+            release: release::<H>,
+            #[cfg(feature = "debug-inspect")]
+            type_id: TypeId::of::<H>(),
+        }
+    }
+
+    fn into_thunk(self) -> AtlThunk {
+        unsafe { (self.release)(self.thunk) }
+    }
+}
+
+/// A pool of [`ContextThunk`] objects with different context types. The pool owns the objects it creates, and reuses
+/// the ATL thunks of released objects for new ones.
+///
+/// With the `debug-inspect` feature, the pool also records the context type of each object, so objects can be found
+/// by context type with [`ContextThunkPool::iter_typed`].
+#[derive(Default)]
+pub struct ContextThunkPool {
+    thunks: AtlThunkPool,
+    live: Vec<PooledContext>,
+}
+
+impl ContextThunkPool {
+    /// Creates a new empty [`ContextThunkPool`] object.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new [`ContextThunk`] object that owns `handler`, like [`ContextThunk::for_handler`], but reuses an
+    /// idle ATL thunk if there is one. The object is kept in the pool until released with
+    /// [`ContextThunkPool::release`].
+    #[track_caller]
+    pub fn acquire<H>(&mut self, handler: H) -> crate::sys::core::Result<&ContextThunk<H>>
+    where
+        H: WindowHandler + 'static,
+    {
+        let thunk = ContextThunk::for_handler_in(self.thunks.acquire()?, handler);
+
+        let pooled = PooledContext::new(thunk);
+        let thunk = pooled.thunk.cast::<ContextThunk<H>>();
+
+        self.live.push(pooled);
+
+        Ok(unsafe { thunk.as_ref() })
+    }
+
+    /// Drops the [`ContextThunk`] object whose window procedure is `window_procedure` along with its context object,
+    /// and keeps its ATL thunk for reuse. Returns whether such an object is found. The caller must make sure the window
+    /// procedure is no longer used by any window.
+    pub fn release(&mut self, window_procedure: WindowProcedure) -> bool {
+        let Some(index) = self
+            .live
+            .iter()
+            .position(|pooled| pooled.window_procedure as usize == window_procedure as usize)
+        else {
+            return false;
+        };
+
+        let thunk = self.live.swap_remove(index).into_thunk();
+
+        self.thunks.release(thunk);
+
+        true
+    }
+
+    /// Returns the number of [`ContextThunk`] objects in use.
+    pub fn len(&self) -> usize {
+        self.live.len()
+    }
+
+    /// Returns whether no [`ContextThunk`] object is in use.
+    pub fn is_empty(&self) -> bool {
+        self.live.is_empty()
+    }
+
+    /// Returns an iterator over the [`ContextThunk`] objects in use whose context type is `T`.
+    #[cfg(feature = "debug-inspect")]
+    pub fn iter_typed<T>(&self) -> TypedContextThunks<'_, T>
+    where
+        T: 'static,
+    {
+        TypedContextThunks {
+            inner: self.live.iter(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl Drop for ContextThunkPool {
+    fn drop(&mut self) {
+        for pooled in self.live.drain(..) {
+            drop(pooled.into_thunk());
+        }
+    }
+}
+
+/// An iterator over the [`ContextThunk`] objects in a [`ContextThunkPool`] with a specific context type, see
+/// [`ContextThunkPool::iter_typed`].
+#[cfg(feature = "debug-inspect")]
+pub struct TypedContextThunks<'a, T> {
+    inner: slice::Iter<'a, PooledContext>,
+    _phantom: PhantomData<&'a ContextThunk<T>>,
+}
+
+#[cfg(feature = "debug-inspect")]
+impl<'a, T> Iterator for TypedContextThunks<'a, T>
+where
+    T: 'static,
+{
+    type Item = &'a ContextThunk<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .by_ref()
+            .find(|pooled| pooled.type_id == TypeId::of::<T>())
+            .map(|pooled| unsafe { pooled.thunk.cast::<ContextThunk<T>>().as_ref() })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.inner.size_hint().1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AtlThunkPool, ContextThunkPool};
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use crate::AtlThunk;
+    use alloc::rc::Rc;
+
+    unsafe extern "system" fn callback(first_parameter: HWND, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+        LRESULT(first_parameter.0 as isize + message as isize)
+    }
+
+    #[test]
+    fn test_thunk_pool_drain() {
+        let mut pool = AtlThunkPool::new();
+        let first = pool.acquire().unwrap().init(callback, HWND(1 as _));
+        let second = pool.acquire().unwrap().init(callback, HWND(2 as _));
+        let first_code = first.as_window_procedure() as usize;
+
+        pool.release(first);
+        pool.release(second);
+
+        let reused = pool.acquire().unwrap().init(callback, HWND(3 as _));
+
+        assert_eq!(
+            unsafe { reused.as_window_procedure()(HWND::default(), 4, WPARAM(0), LPARAM(0)) }.0,
+            7,
+        );
+
+        pool.release(reused);
+
+        let drained = pool.drain();
+
+        assert_eq!(drained.len(), 2);
+
+        let codes = drained
+            .map(|thunk| thunk.as_window_procedure() as usize)
+            .collect::<alloc::vec::Vec<_>>();
+
+        assert!(codes.contains(&first_code));
+        assert_eq!(pool.drain().len(), 0);
+    }
+
+    #[test]
+    fn test_thunk_pool_counts() {
+        let mut pool = AtlThunkPool::new();
+
+        assert_eq!((pool.idle_count(), pool.live_count(), pool.capacity()), (0, 0, 0));
+
+        let first = pool.acquire().unwrap().init(callback, HWND(1 as _));
+        let second = pool.acquire().unwrap().init(callback, HWND(2 as _));
+
+        assert_eq!((pool.idle_count(), pool.live_count()), (0, 2));
+
+        pool.release(first);
+
+        assert_eq!((pool.idle_count(), pool.live_count()), (1, 1));
+        assert!(pool.capacity() >= 1);
+
+        // Reusing an idle ATL thunk moves it back to live.
+        let reused = pool.acquire().unwrap();
+
+        assert_eq!((pool.idle_count(), pool.live_count()), (0, 2));
+
+        pool.release(second);
+
+        // ATL thunks not acquired from the pool do not change the live count, even if others are still acquired.
+        pool.release(AtlThunk::try_new().unwrap());
+
+        assert_eq!((pool.idle_count(), pool.live_count()), (2, 1));
+
+        #[cfg(feature = "stats")]
+        assert!(crate::stats().pool_live >= 1);
+
+        pool.release(unsafe { reused.assume_init() });
+
+        assert_eq!((pool.idle_count(), pool.live_count()), (3, 0));
+
+        drop(pool.drain());
+
+        assert_eq!((pool.idle_count(), pool.live_count()), (0, 0));
+    }
+
+    #[test]
+    fn test_thunk_pool_default() {
+        #[derive(Default)]
+        struct State {
+            thunks: AtlThunkPool,
+            contexts: ContextThunkPool,
+        }
+
+        let mut state = State::default();
+
+        assert_eq!(state.thunks.drain().len(), 0);
+        assert!(state.contexts.is_empty());
+    }
+
+    #[test]
+    fn test_context_thunk_pool_release() {
+        let value = Rc::new(());
+        let mut pool = ContextThunkPool::new();
+
+        let first = {
+            let value = Rc::clone(&value);
+
+            pool.acquire(move |message: u32, _: WPARAM, _: LPARAM| {
+                LRESULT((Rc::strong_count(&value) + message as usize) as _)
+            })
+            .unwrap()
+            .as_window_procedure()
+        };
+
+        let second = pool
+            .acquire(|message: u32, _: WPARAM, _: LPARAM| LRESULT(-(message as isize)))
+            .unwrap()
+            .as_window_procedure();
+
+        assert_eq!(pool.len(), 2);
+
+        assert_eq!(unsafe { first(HWND::default(), 3, WPARAM(0), LPARAM(0)) }.0, 5,);
+
+        // The context object is dropped on release, and the ATL thunk is reused.
+        assert!(pool.release(first));
+        assert!(!pool.release(first));
+        assert_eq!(Rc::strong_count(&value), 1);
+
+        let third = pool
+            .acquire(|message: u32, _: WPARAM, _: LPARAM| LRESULT(message as isize * 2))
+            .unwrap()
+            .as_window_procedure();
+
+        assert_eq!(third as usize, first as usize);
+
+        assert_eq!(unsafe { third(HWND::default(), 3, WPARAM(0), LPARAM(0)) }.0, 6,);
+
+        assert_eq!(unsafe { second(HWND::default(), 3, WPARAM(0), LPARAM(0)) }.0, -3,);
+
+        assert_eq!(pool.len(), 2);
+    }
+
+    #[cfg(feature = "debug-inspect")]
+    #[test]
+    fn test_context_thunk_pool_iter_typed() {
+        use crate::sys::Win32::UI::WindowsAndMessaging::WM_USER;
+        use core::cell::Cell;
+
+        struct Editor(Cell<isize>);
+
+        impl crate::WindowHandler for Editor {
+            fn handle(&self, _: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+                LRESULT(self.0.get())
+            }
+        }
+
+        let mut pool = ContextThunkPool::new();
+
+        pool.acquire(Editor(Cell::new(1))).unwrap();
+        pool.acquire(|_: u32, _: WPARAM, _: LPARAM| LRESULT(0)).unwrap();
+        pool.acquire(Editor(Cell::new(2))).unwrap();
+
+        for thunk in pool.iter_typed::<Editor>() {
+            thunk.context().0.set(thunk.context().0.get() * 10);
+        }
+
+        let mut results = pool
+            .iter_typed::<Editor>()
+            .map(|thunk| unsafe { thunk.as_window_procedure()(HWND::default(), WM_USER, WPARAM(0), LPARAM(0)) }.0)
+            .collect::<alloc::vec::Vec<_>>();
+
+        results.sort_unstable();
+
+        assert_eq!(results, [10, 20]);
+        assert_eq!(pool.iter_typed::<u32>().count(), 0);
+    }
+}
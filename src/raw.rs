@@ -0,0 +1,158 @@
+//! Direct access to the ATL thunk functions, for callers that need to manage ATL thunks themselves.
+//!
+//! These functions are thin wrappers of the ones imported by this crate, so callers do not need to declare the imports
+//! themselves, which is easy to get wrong on x86 because of name decoration. Unlike [`AtlThunk`](crate::AtlThunk), they
+//! do not update the statistics of the `stats` feature, and ATL thunks managed with them are not tracked by the
+//! `metadata` feature.
+
+#[cfg(feature = "test-util")]
+use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use crate::sys::Win32::System::Memory::AtlThunkData_t;
+use crate::{FirstParameter, WindowProcedure};
+use core::ffi::c_void;
+use core::mem;
+use core::ptr::NonNull;
+
+/// Allocates a new ATL thunk, returns [`None`] on failure, in which case the error code can be retrieved with
+/// [`GetLastError`](https://learn.microsoft.com/en-us/windows/win32/api/errhandlingapi/nf-errhandlingapi-getlasterror).
+/// For more information, see document for
+/// [`AtlThunk_AllocateData`](<https://learn.microsoft.com/en-us/windows/win32/api/atlthunk/nf-atlthunk-atlthunk_allocatedata>).
+///
+/// # Safety
+///
+/// The returned ATL thunk is owned by the caller, and must be freed with [`free_data`] exactly once, or handed over to
+/// [`AtlThunk::from_raw`](crate::AtlThunk::from_raw). Its associated data is not initialized, so the window procedure
+/// returned by [`data_to_code`] must not be called before [`init_data`] is called.
+#[inline(always)]
+pub unsafe fn allocate_data() -> Option<NonNull<AtlThunkData_t>> {
+    NonNull::new(unsafe { crate::AtlThunk_AllocateData() })
+}
+
+/// Sets the associated data of an ATL thunk. For more information, see document for
+/// [`AtlThunk_InitData`](<https://learn.microsoft.com/en-us/windows/win32/api/atlthunk/nf-atlthunk-atlthunk_initdata>).
+///
+/// # Safety
+///
+/// `thunk` must be a live ATL thunk allocated by [`allocate_data`] or `AtlThunk_AllocateData`, and must not be called
+/// or updated concurrently.
+#[inline(always)]
+pub unsafe fn init_data(
+    thunk: NonNull<AtlThunkData_t>,
+    window_procedure: WindowProcedure,
+    first_parameter: impl Into<FirstParameter>,
+) {
+    #[expect(clippy::transmutes_expressible_as_ptr_casts, reason = "by-design")]
+    let procedure = unsafe { mem::transmute::<WindowProcedure, *mut c_void>(window_procedure) };
+
+    unsafe { crate::AtlThunk_InitData(thunk.as_ptr(), procedure, first_parameter.into().0) };
+}
+
+/// Returns the window procedure of an ATL thunk, which calls the associated window procedure with the associated first
+/// parameter in place of the window handle. For more information, see document for
+/// [`AtlThunk_DataToCode`](<https://learn.microsoft.com/en-us/windows/win32/api/atlthunk/nf-atlthunk-atlthunk_datatocode>).
+///
+/// # Safety
+///
+/// `thunk` must be a live ATL thunk. The returned window procedure must only be called after the associated data is
+/// set with [`init_data`], and before the ATL thunk is freed.
+#[inline(always)]
+pub unsafe fn data_to_code(thunk: NonNull<AtlThunkData_t>) -> WindowProcedure {
+    unsafe { crate::AtlThunk_DataToCode(thunk.as_ptr()).unwrap_unchecked() }
+}
+
+/// Frees an ATL thunk. For more information, see document for
+/// [`AtlThunk_FreeData`](<https://learn.microsoft.com/en-us/windows/win32/api/atlthunk/nf-atlthunk-atlthunk_freedata>).
+///
+/// # Safety
+///
+/// `thunk` must be a live ATL thunk owned by the caller, which is not owned by an [`AtlThunk`](crate::AtlThunk)
+/// object. The ATL thunk and its window procedure must not be used afterwards.
+#[inline(always)]
+pub unsafe fn free_data(thunk: NonNull<AtlThunkData_t>) {
+    unsafe { crate::AtlThunk_FreeData(thunk.as_ptr()) };
+}
+
+/// Test helper for crates built on this module, enabled by the `test-util` feature. Allocates an ATL thunk with
+/// [`allocate_data`], sets `window_procedure` and `first_parameter` with [`init_data`], calls the window procedure
+/// returned by [`data_to_code`] with `message`, `w_param` and `l_param`, frees the ATL thunk with [`free_data`], then
+/// checks that `window_procedure` received `first_parameter` by comparing the result with `expected`.
+///
+/// # Panics
+///
+/// Panics if the allocation fails, or if the result is not `expected`. The ATL thunk is freed before panicking.
+///
+/// # Safety
+///
+/// `window_procedure` must be safe to call with `first_parameter` in place of the window handle, and with the other
+/// arguments.
+#[cfg(feature = "test-util")]
+#[doc(hidden)]
+#[track_caller]
+pub unsafe fn assert_roundtrip(
+    window_procedure: WindowProcedure,
+    first_parameter: impl Into<FirstParameter>,
+    message: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+    expected: LRESULT,
+) {
+    let thunk = unsafe { allocate_data() }.expect("failed to allocate an ATL thunk");
+
+    let result = unsafe {
+        init_data(thunk, window_procedure, first_parameter);
+
+        let result = data_to_code(thunk)(HWND::default(), message, w_param, l_param);
+
+        free_data(thunk);
+
+        result
+    };
+
+    assert_eq!(
+        result, expected,
+        "the window procedure returns an unexpected result through the ATL thunk",
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+
+    #[test]
+    fn test_raw_functions() {
+        unsafe extern "system" fn callback(first_parameter: HWND, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+            LRESULT(first_parameter.0 as isize + message as isize)
+        }
+
+        unsafe {
+            let thunk = super::allocate_data().unwrap();
+
+            super::init_data(thunk, callback, HWND(2 as _));
+
+            assert_eq!(
+                super::data_to_code(thunk)(HWND::default(), 3, WPARAM(0), LPARAM(0)).0,
+                5
+            );
+
+            super::free_data(thunk);
+        }
+    }
+
+    #[cfg(feature = "test-util")]
+    unsafe extern "system" fn sum(first_parameter: HWND, message: u32, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+        LRESULT(first_parameter.0 as isize + message as isize + w_param.0 as isize + l_param.0)
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn test_assert_roundtrip() {
+        unsafe { super::assert_roundtrip(sum, HWND(2 as _), 3, WPARAM(5), LPARAM(7), LRESULT(17)) };
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    #[should_panic(expected = "unexpected result")]
+    fn test_assert_roundtrip_mismatch() {
+        unsafe { super::assert_roundtrip(sum, HWND(2 as _), 3, WPARAM(5), LPARAM(7), LRESULT(0)) };
+    }
+}
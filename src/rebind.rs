@@ -0,0 +1,355 @@
+//! Replacing handlers of windows through the message queue.
+
+use crate::context::{self, ContextThunk};
+use crate::sys::core::w;
+use crate::sys::Win32::Foundation::{E_HANDLE, HWND, LPARAM, LRESULT, WPARAM};
+use crate::sys::Win32::UI::WindowsAndMessaging::{PostMessageW, RegisterWindowMessageW};
+use crate::WindowHandler;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::marker::PhantomData;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, AtomicU32, Ordering};
+
+/// The registered rebinding message, zero means not registered yet.
+static REBIND_MESSAGE: AtomicU32 = AtomicU32::new(0);
+
+fn rebind_message() -> crate::sys::core::Result<u32> {
+    match REBIND_MESSAGE.load(Ordering::Relaxed) {
+        0 => match unsafe { RegisterWindowMessageW(w!("AtlThunkRebind")) } {
+            0 => Err(crate::sys::core::Error::from_win32()),
+            message => {
+                REBIND_MESSAGE.store(message, Ordering::Relaxed);
+
+                Ok(message)
+            }
+        },
+        message => Ok(message),
+    }
+}
+
+fn drop_handler<H>(handler: *mut H) {
+    if !handler.is_null() {
+        drop(unsafe { Box::from_raw(handler) });
+    }
+}
+
+/// The last handler posted by [`RebindSender::post_rebind`] that has not been received yet, shared by the context
+/// object and its senders. Posted messages carry no pointers, so forged or stale messages can not make the window
+/// procedure take the ownership of anything but its own handlers.
+struct Mailbox<H> {
+    handler: AtomicPtr<H>,
+    _owns: PhantomData<Box<H>>,
+}
+
+impl<H> Mailbox<H> {
+    fn put(&self, handler: *mut H) {
+        // Handlers that have not been received are dropped right away, so only the last one is kept.
+        drop_handler(self.handler.swap(handler, Ordering::AcqRel));
+    }
+
+    fn take(&self) -> *mut H {
+        self.handler.swap(ptr::null_mut(), Ordering::Acquire)
+    }
+
+    /// Takes `handler` back if the window procedure has not received it, and it has not been replaced.
+    fn take_back(&self, handler: *mut H) -> bool {
+        self.handler
+            .compare_exchange(handler, ptr::null_mut(), Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+}
+
+impl<H> Drop for Mailbox<H> {
+    fn drop(&mut self) {
+        drop_handler(*self.handler.get_mut());
+    }
+}
+
+// Handlers are only moved through the mailbox and never borrowed, like through a `Mutex<Option<Box<H>>>`.
+unsafe impl<H> Send for Mailbox<H> where H: Send {}
+unsafe impl<H> Sync for Mailbox<H> where H: Send {}
+
+/// Context object that dispatches messages to a handler that can be replaced with [`RebindSender::post_rebind`], see
+/// [`ContextThunk::for_rebindable_handler`].
+pub struct Rebindable<H> {
+    current: AtomicPtr<H>,
+    pending: AtomicPtr<H>,
+    mailbox: Arc<Mailbox<H>>,
+    /// The handlers are owned like in a `Box<H>`, so [`Rebindable`] is only [`Send`] and [`Sync`] if `H` is, which
+    /// `AtomicPtr<H>` alone does not require.
+    _owns: PhantomData<Box<H>>,
+}
+
+impl<H> Rebindable<H> {
+    /// Returns the current handler.
+    pub fn handler(&self) -> &H {
+        unsafe { &*self.current.load(Ordering::Relaxed) }
+    }
+
+    fn stage(&self) {
+        let handler = self.mailbox.take();
+
+        // A pending handler has never been called, so it can be dropped right away.
+        if !handler.is_null() {
+            drop_handler(self.pending.swap(handler, Ordering::Relaxed));
+        }
+    }
+
+    /// Must only be called when the current handler is not borrowed.
+    fn apply_pending(&self) {
+        let pending = self.pending.swap(ptr::null_mut(), Ordering::Relaxed);
+
+        if !pending.is_null() {
+            drop_handler(self.current.swap(pending, Ordering::Relaxed));
+        }
+    }
+}
+
+impl<H> Drop for Rebindable<H> {
+    fn drop(&mut self) {
+        drop_handler(*self.current.get_mut());
+        drop_handler(*self.pending.get_mut());
+    }
+}
+
+unsafe extern "system" fn rebindable_window_procedure<H>(
+    rebindable: HWND,
+    message: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> LRESULT
+where
+    H: WindowHandler,
+{
+    let rebindable = rebindable.0.cast::<Rebindable<H>>().cast_const();
+    let header = unsafe { context::context_header(rebindable) };
+
+    header.dispatch(|| {
+        let rebindable = unsafe { &*rebindable };
+        let rebind_message = REBIND_MESSAGE.load(Ordering::Relaxed);
+
+        // The rebinding message is only registered after a sender is created. It only wakes up the window procedure,
+        // and the handler is taken from the mailbox of this context object.
+        let result = if rebind_message != 0 && message == rebind_message {
+            rebindable.stage();
+
+            LRESULT(0)
+        } else {
+            rebindable.handler().handle(message, w_param, l_param)
+        };
+
+        // Outer dispatches may still borrow the current handler, in which case it is replaced after they return.
+        if header.is_outermost_dispatch() {
+            rebindable.apply_pending();
+        }
+
+        result
+    })
+}
+
+impl<H> ContextThunk<Rebindable<H>>
+where
+    H: WindowHandler,
+{
+    /// Creates a new [`ContextThunk`] object that dispatches messages to `handler`, which can be replaced later by
+    /// posting a message with a [`RebindSender`] from [`ContextThunk::rebind_sender`].
+    #[track_caller]
+    pub fn for_rebindable_handler(handler: H) -> crate::sys::core::Result<Self> {
+        let rebindable = Rebindable {
+            current: AtomicPtr::new(Box::into_raw(Box::new(handler))),
+            pending: AtomicPtr::new(ptr::null_mut()),
+            mailbox: Arc::new(Mailbox {
+                handler: AtomicPtr::new(ptr::null_mut()),
+                _owns: PhantomData,
+            }),
+            _owns: PhantomData,
+        };
+
+        unsafe { Self::try_new_with_procedure(rebindable, rebindable_window_procedure::<H>) }
+    }
+
+    /// Returns a [`RebindSender`] that replaces the handler by posting messages to the window bound with
+    /// [`ContextThunk::bind_window`]. Returns an error with code `E_HANDLE` if no window is bound, or an error from
+    /// [`RegisterWindowMessageW`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-registerwindowmessagew)
+    /// if the rebinding message can not be registered.
+    ///
+    /// The ATL thunk should be the window procedure of the bound window, otherwise posted handlers are never received,
+    /// and are only dropped with the [`ContextThunk`] object and the senders.
+    pub fn rebind_sender(&self) -> crate::sys::core::Result<RebindSender<H>> {
+        if self.window().is_invalid() {
+            return Err(E_HANDLE.into());
+        }
+
+        rebind_message()?;
+
+        Ok(RebindSender {
+            window: self.window(),
+            mailbox: Arc::clone(&self.context().mailbox),
+        })
+    }
+}
+
+/// Sender of new handlers for a [`ContextThunk`] created by [`ContextThunk::for_rebindable_handler`]. It can be sent
+/// to other threads if the handler can, and the replacement happens on the thread of the window.
+///
+/// Posted handlers are passed through a mailbox shared with the context object instead of the message itself, so a
+/// sender that outlives its [`ContextThunk`] object, or a forged message, can not replace the handler of another
+/// thunk installed on the same window.
+pub struct RebindSender<H> {
+    window: HWND,
+    mailbox: Arc<Mailbox<H>>,
+}
+
+impl<H> RebindSender<H> {
+    /// Posts `handler` to the window, which replaces the current handler when the message is received. If the current
+    /// handler is being called, the replacement happens after the outermost call returns, and if another handler is
+    /// posted before that, only the last one is kept.
+    ///
+    /// If the window is destroyed before the message is received, `handler` is dropped with the [`ContextThunk`] object
+    /// and the senders.
+    pub fn post_rebind(&self, handler: H) -> crate::sys::core::Result<()> {
+        let handler = Box::into_raw(Box::new(handler));
+
+        self.mailbox.put(handler);
+
+        let result = unsafe {
+            PostMessageW(
+                self.window,
+                REBIND_MESSAGE.load(Ordering::Relaxed),
+                WPARAM(0),
+                LPARAM(0),
+            )
+        };
+
+        if result.is_err() && self.mailbox.take_back(handler) {
+            drop_handler(handler);
+        }
+
+        result
+    }
+}
+
+impl<H> Clone for RebindSender<H> {
+    fn clone(&self) -> Self {
+        Self {
+            window: self.window,
+            mailbox: Arc::clone(&self.mailbox),
+        }
+    }
+}
+
+// The handler is moved to the thread of the window, and the other fields are plain values.
+unsafe impl<H> Send for RebindSender<H> where H: Send {}
+unsafe impl<H> Sync for RebindSender<H> where H: Send {}
+
+#[cfg(test)]
+mod tests {
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use crate::sys::Win32::UI::WindowsAndMessaging::WM_USER;
+    use crate::ContextThunk;
+    use alloc::boxed::Box;
+    use alloc::rc::Rc;
+    use core::sync::atomic::Ordering;
+
+    type Handler = fn(u32, WPARAM, LPARAM) -> LRESULT;
+
+    #[test]
+    fn test_context_thunk_rebindable_handler() {
+        let thunk =
+            ContextThunk::for_rebindable_handler((|message, _, _| LRESULT(message as isize)) as Handler).unwrap();
+
+        let window_procedure = thunk.as_window_procedure();
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_USER, WPARAM(0), LPARAM(0)) }.0,
+            WM_USER as isize,
+        );
+
+        // A sender needs a bound window.
+        assert!(thunk.rebind_sender().is_err());
+
+        // Posting to an invalid window fails, and the handler is taken back.
+        thunk.bind_window(HWND(1 as _));
+
+        let sender = thunk.rebind_sender().unwrap();
+
+        assert!(sender.post_rebind(|_, _, _| LRESULT(-2)).is_err());
+        assert!(sender.mailbox.take().is_null());
+
+        // Deliver the message directly, like the message loop of the window would.
+        let message = super::REBIND_MESSAGE.load(Ordering::Relaxed);
+
+        sender
+            .mailbox
+            .put(Box::into_raw(Box::new((|_, _, _| LRESULT(-1)) as Handler)));
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), message, WPARAM(0), LPARAM(0)) }.0,
+            0,
+        );
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_USER, WPARAM(0), LPARAM(0)) }.0,
+            -1,
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_rebindable_handler_while_mutably_borrowed() {
+        crate::test_support::assert_aborts("rebind::tests::test_rebindable_handler_while_mutably_borrowed", || {
+            use crate::sys::Win32::UI::WindowsAndMessaging::WM_SETTEXT;
+
+            let mut thunk = ContextThunk::for_rebindable_handler((|_, _, _| LRESULT(1)) as Handler).unwrap();
+            let window_procedure = thunk.as_window_procedure();
+            let _context = thunk.get_mut();
+
+            unsafe { window_procedure(HWND::default(), WM_SETTEXT, WPARAM(0), LPARAM(0)) };
+        });
+    }
+
+    #[test]
+    fn test_rebind_sender_stale() {
+        fn counting_handler(counter: Rc<()>) -> impl Fn(u32, WPARAM, LPARAM) -> LRESULT {
+            move |_, _, _| LRESULT(Rc::strong_count(&counter) as _)
+        }
+
+        let counter = Rc::new(());
+        let thunk = ContextThunk::for_rebindable_handler(counting_handler(Rc::clone(&counter))).unwrap();
+
+        thunk.bind_window(HWND(1 as _));
+
+        let stale_sender = thunk.rebind_sender().unwrap();
+
+        drop(thunk);
+
+        // Like a handler posted after the thunk is dropped, which stays in the mailbox of the old context object.
+        stale_sender
+            .mailbox
+            .put(Box::into_raw(Box::new(counting_handler(Rc::clone(&counter)))));
+
+        // A thunk with another handler type installed on the same window.
+        let thunk = ContextThunk::for_rebindable_handler((|_, _, _| LRESULT(-1)) as Handler).unwrap();
+        let window_procedure = thunk.as_window_procedure();
+        let message = super::REBIND_MESSAGE.load(Ordering::Relaxed);
+
+        // Stale or forged messages carry nothing the window procedure takes the ownership of.
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), message, WPARAM(1), LPARAM(1)) }.0,
+            0,
+        );
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_USER, WPARAM(0), LPARAM(0)) }.0,
+            -1,
+        );
+
+        // The stale handler is dropped with the last sender.
+        assert_eq!(Rc::strong_count(&counter), 2);
+
+        drop(stale_sender);
+
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+}
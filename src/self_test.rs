@@ -0,0 +1,116 @@
+//! Runtime diagnostics of the ATL thunk stub of the current architecture.
+
+use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use crate::{AtlThunk, FirstParameter};
+
+/// Result of [`self_test_arch`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArchReport {
+    /// The architecture the crate is compiled for, like `"x86"`, `"x86_64"` or `"aarch64"`.
+    pub architecture: &'static str,
+    /// Whether the message, `WPARAM` and `LPARAM` arguments and the return value are passed through unchanged.
+    pub arguments_exact: bool,
+    /// The first test pattern of the first parameter that is not passed through bit exactly, or [`None`] if all of
+    /// them are.
+    pub mismatched_first_parameter: Option<usize>,
+}
+
+impl ArchReport {
+    /// Returns whether all checks passed.
+    pub fn is_ok(&self) -> bool {
+        self.arguments_exact && self.mismatched_first_parameter.is_none()
+    }
+}
+
+const fn architecture() -> &'static str {
+    if cfg!(target_arch = "x86") {
+        "x86"
+    } else if cfg!(target_arch = "x86_64") {
+        "x86_64"
+    } else if cfg!(target_arch = "aarch64") {
+        "aarch64"
+    } else if cfg!(target_arch = "arm") {
+        "arm"
+    } else {
+        "unknown"
+    }
+}
+
+/// Test patterns of the first parameter, including ones with the high bit of each half set, which would be changed by
+/// sign or zero extension bugs in the stub.
+const FIRST_PARAMETER_PATTERNS: &[u64] = &[
+    0,
+    1,
+    0x7FFF_FFFF,
+    0x8000_0000,
+    0xFFFF_FFFF,
+    0x5555_5555_5555_5555,
+    0xAAAA_AAAA_AAAA_AAAA,
+    0x0000_0001_0000_0000,
+    0x7FFF_FFFF_FFFF_FFFF,
+    0x8000_0000_0000_0000,
+    0xFFFF_FFFF_0000_0000,
+    0xFFFF_FFFF_FFFF_FFFF,
+];
+
+unsafe extern "system" fn echo_first_parameter(first_parameter: HWND, _: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+    LRESULT(first_parameter.0 as _)
+}
+
+unsafe extern "system" fn echo_message(_: HWND, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+    LRESULT(message as _)
+}
+
+unsafe extern "system" fn echo_w_param(_: HWND, _: u32, w_param: WPARAM, _: LPARAM) -> LRESULT {
+    LRESULT(w_param.0 as _)
+}
+
+unsafe extern "system" fn echo_l_param(_: HWND, _: u32, _: WPARAM, l_param: LPARAM) -> LRESULT {
+    LRESULT(l_param.0)
+}
+
+/// Checks that the ATL thunk stub of the current architecture passes arguments through unchanged. Unlike the check
+/// performed by [`AtlThunk::try_new`] in debug builds, this checks the first parameter with a set of bit patterns,
+/// and also checks the other arguments. Returns an error if an ATL thunk can not be allocated.
+pub fn self_test_arch() -> crate::sys::core::Result<ArchReport> {
+    let mut thunk = AtlThunk::try_new()?;
+
+    let mut call = |window_procedure, first_parameter: usize, message: u32, w_param: usize, l_param: isize| {
+        thunk.set_data(window_procedure, FirstParameter(first_parameter));
+
+        // A non-null window handle makes sure it is replaced rather than passed through.
+        unsafe { thunk.as_window_procedure()(HWND(usize::MAX as _), message, WPARAM(w_param), LPARAM(l_param)) }.0
+    };
+
+    let mismatched_first_parameter = FIRST_PARAMETER_PATTERNS
+        .iter()
+        .map(|&pattern| pattern as usize)
+        .find(|&pattern| call(echo_first_parameter, pattern, 0, 0, 0) as usize != pattern);
+
+    let arguments_exact = [0, 1, 0x8000_0000, u32::MAX]
+        .into_iter()
+        .all(|message| call(echo_message, 0, message, 0, 0) as u32 == message)
+        && [0, 1, 1 << (usize::BITS - 1), usize::MAX]
+            .into_iter()
+            .all(|w_param| call(echo_w_param, 0, 0, w_param, 0) as usize == w_param)
+        && [0, -1, isize::MIN, isize::MAX]
+            .into_iter()
+            .all(|l_param| call(echo_l_param, 0, 0, 0, l_param) == l_param);
+
+    Ok(ArchReport {
+        architecture: architecture(),
+        arguments_exact,
+        mismatched_first_parameter,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_self_test_arch() {
+        let report = super::self_test_arch().unwrap();
+
+        assert!(report.is_ok(), "{report:?}");
+        assert_ne!(report.architecture, "unknown");
+    }
+}
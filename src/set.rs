@@ -0,0 +1,108 @@
+//! Collections of ATL thunks built from iterators.
+
+use crate::{AtlThunk, FirstParameter, WindowProcedure};
+use alloc::vec::Vec;
+use core::ops::Deref;
+
+/// A collection of [`AtlThunk`] objects, usually built by collecting into a [`TryThunkSet`].
+#[derive(Debug, Default)]
+pub struct ThunkSet {
+    thunks: Vec<AtlThunk>,
+}
+
+impl ThunkSet {
+    /// Returns the contained [`AtlThunk`] objects.
+    pub fn into_vec(self) -> Vec<AtlThunk> {
+        self.thunks
+    }
+}
+
+impl Deref for ThunkSet {
+    type Target = [AtlThunk];
+
+    fn deref(&self) -> &Self::Target {
+        &self.thunks
+    }
+}
+
+impl IntoIterator for ThunkSet {
+    type Item = AtlThunk;
+    type IntoIter = alloc::vec::IntoIter<AtlThunk>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.thunks.into_iter()
+    }
+}
+
+/// The result of collecting `(window_procedure, first_parameter)` pairs, which creates an [`AtlThunk`] object for each
+/// pair with [`AtlThunk::try_new_with`]. Collecting stops at the first allocation failure, and the already created
+/// objects are freed.
+#[derive(Debug)]
+pub struct TryThunkSet {
+    result: crate::sys::core::Result<ThunkSet>,
+}
+
+impl TryThunkSet {
+    /// Returns the collected [`ThunkSet`], or the first allocation error.
+    pub fn into_result(self) -> crate::sys::core::Result<ThunkSet> {
+        self.result
+    }
+}
+
+impl<P> FromIterator<(WindowProcedure, P)> for TryThunkSet
+where
+    P: Into<FirstParameter>,
+{
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (WindowProcedure, P)>,
+    {
+        Self {
+            result: iter
+                .into_iter()
+                .map(|(window_procedure, first_parameter)| AtlThunk::try_new_with(window_procedure, first_parameter))
+                .collect::<crate::sys::core::Result<_>>()
+                .map(|thunks| ThunkSet { thunks }),
+        }
+    }
+}
+
+impl From<TryThunkSet> for crate::sys::core::Result<ThunkSet> {
+    fn from(value: TryThunkSet) -> Self {
+        value.into_result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TryThunkSet;
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use crate::WindowProcedure;
+
+    #[test]
+    fn test_try_thunk_set_collect() {
+        unsafe extern "system" fn add(first_parameter: HWND, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+            LRESULT(first_parameter.0 as isize + message as isize)
+        }
+
+        unsafe extern "system" fn multiply(first_parameter: HWND, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+            LRESULT(first_parameter.0 as isize * message as isize)
+        }
+
+        let set = [(add as WindowProcedure, 2), (multiply, 3), (add, 5)]
+            .into_iter()
+            .map(|(window_procedure, value)| (window_procedure, HWND(value as _)))
+            .collect::<TryThunkSet>()
+            .into_result()
+            .unwrap();
+
+        assert_eq!(set.len(), 3);
+
+        let results = set
+            .iter()
+            .map(|thunk| unsafe { thunk.as_window_procedure()(HWND::default(), 7, WPARAM(0), LPARAM(0)) }.0);
+
+        assert!(results.eq([9, 21, 12]));
+        assert_eq!(set.into_vec().len(), 3);
+    }
+}
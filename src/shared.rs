@@ -0,0 +1,109 @@
+//! Reference counted ATL thunks.
+
+use crate::{AtlThunk, FirstParameter, WindowProcedure};
+use alloc::sync::{Arc, Weak};
+
+/// A reference counted [`AtlThunk`] for sharing one window procedure between multiple owners. Cloning a
+/// [`SharedAtlThunk`] object only increases the reference count, the ATL thunk is freed after all clones are dropped.
+#[derive(Clone)]
+pub struct SharedAtlThunk {
+    thunk: Arc<AtlThunk>,
+}
+
+impl SharedAtlThunk {
+    /// Creates a new [`SharedAtlThunk`] object that takes ownership of `thunk`.
+    pub fn new(thunk: AtlThunk) -> Self {
+        Self { thunk: Arc::new(thunk) }
+    }
+
+    /// Creates a new [`SharedAtlThunk`] object from specified window procedure and associated first parameter value,
+    /// see [`AtlThunk::try_new_with`].
+    #[track_caller]
+    pub fn try_new_with(
+        window_procedure: WindowProcedure,
+        first_parameter: impl Into<FirstParameter>,
+    ) -> crate::sys::core::Result<Self> {
+        AtlThunk::try_new_with(window_procedure, first_parameter).map(Self::new)
+    }
+
+    /// Returns the wrapped window procedure, see [`AtlThunk::as_window_procedure`]. The returned function pointer is
+    /// valid as long as any clone of the [`SharedAtlThunk`] object is alive.
+    #[inline(always)]
+    pub fn as_window_procedure(&self) -> WindowProcedure {
+        self.thunk.as_window_procedure()
+    }
+
+    /// Creates a [`WeakAtlThunk`] object that refers to the same ATL thunk without keeping it alive.
+    pub fn downgrade(this: &Self) -> WeakAtlThunk {
+        WeakAtlThunk {
+            thunk: Arc::downgrade(&this.thunk),
+        }
+    }
+
+    /// Returns whether two [`SharedAtlThunk`] objects refer to the same ATL thunk.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        Arc::ptr_eq(&this.thunk, &other.thunk)
+    }
+}
+
+impl From<AtlThunk> for SharedAtlThunk {
+    fn from(value: AtlThunk) -> Self {
+        Self::new(value)
+    }
+}
+
+/// A non-owning reference to the ATL thunk of a [`SharedAtlThunk`] object. Cloning a [`WeakAtlThunk`] object only
+/// increases the weak reference count.
+#[derive(Clone, Default)]
+pub struct WeakAtlThunk {
+    thunk: Weak<AtlThunk>,
+}
+
+impl WeakAtlThunk {
+    /// Creates a new [`WeakAtlThunk`] object that refers to nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a [`SharedAtlThunk`] object if the ATL thunk has not been freed yet.
+    pub fn upgrade(&self) -> Option<SharedAtlThunk> {
+        self.thunk.upgrade().map(|thunk| SharedAtlThunk { thunk })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SharedAtlThunk, WeakAtlThunk};
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+
+    #[test]
+    fn test_shared_thunk_clone() {
+        unsafe extern "system" fn callback(first_parameter: HWND, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+            LRESULT(first_parameter.0 as isize + message as isize)
+        }
+
+        let thunk = SharedAtlThunk::try_new_with(callback, HWND(2 as _)).unwrap();
+        let cloned = thunk.clone();
+        let weak = SharedAtlThunk::downgrade(&thunk);
+
+        assert!(SharedAtlThunk::ptr_eq(&thunk, &cloned));
+        assert_eq!(
+            thunk.as_window_procedure() as usize,
+            cloned.as_window_procedure() as usize
+        );
+
+        drop(thunk);
+
+        assert_eq!(
+            unsafe { cloned.as_window_procedure()(HWND::default(), 3, WPARAM(0), LPARAM(0)) }.0,
+            5,
+        );
+
+        assert!(SharedAtlThunk::ptr_eq(&weak.clone().upgrade().unwrap(), &cloned));
+
+        drop(cloned);
+
+        assert!(weak.upgrade().is_none());
+        assert!(WeakAtlThunk::new().upgrade().is_none());
+    }
+}
@@ -0,0 +1,157 @@
+//! Context thunks whose context objects are shared with other threads.
+
+use crate::context::ContextThunk;
+use alloc::sync::Arc;
+use core::marker::PhantomData;
+use core::ops::Deref;
+
+/// A [`ContextThunk`] whose context object can be read from other threads through [`SharedContext`] handles, see
+/// [`ContextThunk::into_shared`]. The [`SharedContextThunk`] object itself is neither [`Send`] nor [`Sync`], so the
+/// window procedure and other [`ContextThunk`] methods, which are reachable through [`Deref`], stay on the thread that
+/// created it, which is usually the thread of the window.
+pub struct SharedContextThunk<T> {
+    thunk: Arc<ContextThunk<T>>,
+    _thread_affine: PhantomData<*const ()>,
+}
+
+impl<T> SharedContextThunk<T> {
+    /// Creates a handle for reading the context object from other threads.
+    pub fn shared_context(&self) -> SharedContext<T> {
+        SharedContext {
+            thunk: Arc::clone(&self.thunk),
+        }
+    }
+}
+
+impl<T> Deref for SharedContextThunk<T> {
+    type Target = ContextThunk<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.thunk
+    }
+}
+
+/// A handle for reading the context object of a [`SharedContextThunk`] object from any thread, see
+/// [`SharedContextThunk::shared_context`].
+///
+/// The ATL thunk and the context object are freed after the [`SharedContextThunk`] object and all handles are
+/// dropped, so the last handle may drop the context object on its own thread. That is why sending a handle needs the
+/// context object to be [`Send`], in addition to [`Sync`] for reading it concurrently with the window procedure.
+pub struct SharedContext<T> {
+    thunk: Arc<ContextThunk<T>>,
+}
+
+impl<T> SharedContext<T> {
+    /// Returns a reference to the context object.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the context object has been dropped because of [`ContextThunk::drop_context_on_ncdestroy`] before the
+    /// thunk is converted by [`ContextThunk::into_shared`].
+    pub fn context(&self) -> &T {
+        self.thunk.context()
+    }
+
+    /// Returns a reference to the context object, or [`None`] if it has been dropped because of
+    /// [`ContextThunk::drop_context_on_ncdestroy`] before the thunk is converted by [`ContextThunk::into_shared`].
+    pub fn try_context(&self) -> Option<&T> {
+        self.thunk.try_context()
+    }
+}
+
+impl<T> Clone for SharedContext<T> {
+    fn clone(&self) -> Self {
+        Self {
+            thunk: Arc::clone(&self.thunk),
+        }
+    }
+}
+
+impl<T> ContextThunk<T> {
+    /// Converts the [`ContextThunk`] object into a [`SharedContextThunk`] object, which keeps the window procedure on
+    /// the current thread, and hands out [`SharedContext`] handles for reading the context object from other threads.
+    ///
+    /// Since handles may outlive the window, this disables [`ContextThunk::drop_context_on_ncdestroy`], and the context
+    /// object is only dropped with the [`SharedContextThunk`] object and the last handle.
+    pub fn into_shared(self) -> SharedContextThunk<T> {
+        self.header().set_context_shared();
+
+        SharedContextThunk {
+            thunk: Arc::new(self),
+            _thread_affine: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use crate::sys::Win32::UI::WindowsAndMessaging::WM_USER;
+    use crate::ContextThunk;
+
+    #[test]
+    fn test_context_thunk_into_shared() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+        let thunk = ContextThunk::for_handler(|message: u32, _: WPARAM, _: LPARAM| LRESULT(message as _))
+            .unwrap()
+            .into_shared();
+
+        let shared_context = thunk.shared_context();
+
+        assert_send_sync(&shared_context);
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), WM_USER, WPARAM(0), LPARAM(0)) }.0,
+            WM_USER as isize,
+        );
+
+        // The handle keeps the context object alive.
+        drop(thunk);
+
+        assert_eq!(
+            shared_context
+                .try_context()
+                .map(|handler| handler(1, WPARAM(0), LPARAM(0)).0),
+            Some(1),
+        );
+    }
+
+    #[test]
+    fn test_context_thunk_into_shared_ncdestroy() {
+        use crate::sys::Win32::UI::WindowsAndMessaging::WM_NCDESTROY;
+
+        let thunk = ContextThunk::for_handler(|message: u32, _: WPARAM, _: LPARAM| LRESULT(message as _)).unwrap();
+        let thunk = unsafe { thunk.drop_context_on_ncdestroy(true) }.into_shared();
+        let shared_context = thunk.shared_context();
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), WM_NCDESTROY, WPARAM(0), LPARAM(0)) }.0,
+            WM_NCDESTROY as isize,
+        );
+
+        // Handles may still be reading the context object, so it is not dropped.
+        assert_eq!(
+            shared_context
+                .try_context()
+                .map(|handler| handler(1, WPARAM(0), LPARAM(0)).0),
+            Some(1),
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_shared_context_other_thread() {
+        let thunk = ContextThunk::for_handler(|_: u32, _: WPARAM, _: LPARAM| LRESULT(0))
+            .unwrap()
+            .into_shared();
+
+        let shared_context = thunk.shared_context();
+
+        std::thread::spawn(move || {
+            assert_eq!(shared_context.context()(0, WPARAM(0), LPARAM(0)).0, 0);
+        })
+        .join()
+        .unwrap();
+    }
+}
@@ -0,0 +1,196 @@
+//! Handlers stored inline in caller provided storage, which needs no heap allocation.
+
+use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use crate::{AtlThunk, WindowHandler, WindowProcedure};
+use core::marker::PhantomData;
+use core::mem::{self, ManuallyDrop, MaybeUninit};
+use core::ptr::{self, NonNull};
+
+/// Maximum alignment of handlers stored in a [`SmallHandlerStorage`].
+const STORAGE_ALIGN: usize = 16;
+
+/// Inline storage for a handler of up to `N` bytes with an alignment of up to 16, see [`AtlThunk::small_handler`].
+#[repr(C, align(16))]
+pub struct SmallHandlerStorage<const N: usize> {
+    bytes: [MaybeUninit<u8>; N],
+}
+
+impl<const N: usize> SmallHandlerStorage<N> {
+    /// Creates a new empty [`SmallHandlerStorage`] object.
+    pub const fn new() -> Self {
+        Self {
+            bytes: [MaybeUninit::uninit(); N],
+        }
+    }
+}
+
+impl<const N: usize> Default for SmallHandlerStorage<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An [`AtlThunk`] that dispatches messages to a handler stored in a borrowed [`SmallHandlerStorage`]. The handler is
+/// dropped along with the [`SmallHandler`] object.
+pub struct SmallHandler<'a, const N: usize> {
+    thunk: ManuallyDrop<AtlThunk>,
+    handler: NonNull<u8>,
+    drop_handler: unsafe fn(*mut u8),
+    storage: PhantomData<&'a mut SmallHandlerStorage<N>>,
+}
+
+impl<const N: usize> SmallHandler<'_, N> {
+    /// Returns a window procedure that dispatches messages to the stored handler. The returned function pointer is
+    /// only valid while the originating [`SmallHandler`] object is alive.
+    #[inline(always)]
+    pub fn as_window_procedure(&self) -> WindowProcedure {
+        self.thunk.as_window_procedure()
+    }
+}
+
+impl<const N: usize> Drop for SmallHandler<'_, N> {
+    fn drop(&mut self) {
+        // Free the ATL thunk first, so the handler can not be reached while being dropped.
+        unsafe { ManuallyDrop::drop(&mut self.thunk) };
+        unsafe { (self.drop_handler)(self.handler.as_ptr()) };
+    }
+}
+
+unsafe fn drop_handler<H>(handler: *mut u8) {
+    unsafe { ptr::drop_in_place(handler.cast::<H>()) };
+}
+
+/// Window procedure that forwards messages to the handler stored at the address of the associated data.
+unsafe extern "system" fn small_handler_window_procedure<H>(
+    handler: HWND,
+    message: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> LRESULT
+where
+    H: WindowHandler,
+{
+    unsafe { (*handler.0.cast::<H>().cast_const()).handle(message, w_param, l_param) }
+}
+
+impl AtlThunk {
+    /// Creates a new [`SmallHandler`] object that moves `handler` into `storage`, and dispatches messages to it. The
+    /// address of `storage` is used as the associated first parameter, so no heap allocation is made besides the ATL
+    /// thunk itself, which suits handlers that only capture a few references.
+    ///
+    /// Handlers larger than `N` bytes or aligned to more than 16 bytes are rejected at compile time.
+    #[track_caller]
+    pub fn small_handler<'a, H, const N: usize>(
+        storage: &'a mut SmallHandlerStorage<N>,
+        handler: H,
+    ) -> crate::sys::core::Result<SmallHandler<'a, N>>
+    where
+        H: WindowHandler + 'a,
+    {
+        const {
+            assert!(mem::size_of::<H>() <= N, "the handler does not fit in the storage");
+            assert!(
+                mem::align_of::<H>() <= STORAGE_ALIGN,
+                "the handler is over-aligned for the storage"
+            );
+        }
+
+        let mut thunk = Self::allocate()?;
+        let handler_ptr = storage.bytes.as_mut_ptr().cast::<H>();
+
+        unsafe { handler_ptr.write(handler) };
+
+        thunk.set_data(small_handler_window_procedure::<H>, handler_ptr.cast_const());
+
+        Ok(SmallHandler {
+            thunk: ManuallyDrop::new(thunk),
+            handler: unsafe { NonNull::new_unchecked(handler_ptr.cast()) },
+            drop_handler: drop_handler::<H>,
+            storage: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SmallHandlerStorage;
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use crate::{AtlThunk, WindowHandler};
+    use core::cell::Cell;
+    use core::mem;
+
+    #[test]
+    fn test_thunk_small_handler() {
+        let count = Cell::new(0);
+        let base = Cell::new(10);
+
+        let mut storage = SmallHandlerStorage::<{ 2 * mem::size_of::<usize>() }>::new();
+
+        let thunk = AtlThunk::small_handler(&mut storage, |message: u32, _: WPARAM, _: LPARAM| {
+            count.set(count.get() + 1);
+
+            LRESULT(base.get() + message as isize)
+        })
+        .unwrap();
+
+        let window_procedure = thunk.as_window_procedure();
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), 2, WPARAM(0), LPARAM(0)) }.0,
+            12,
+        );
+
+        base.set(20);
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), 3, WPARAM(0), LPARAM(0)) }.0,
+            23,
+        );
+
+        drop(thunk);
+
+        assert_eq!(count.get(), 2);
+    }
+
+    #[test]
+    fn test_thunk_small_handler_drop() {
+        struct Handler<'a> {
+            drops: &'a Cell<u32>,
+            value: u64,
+        }
+
+        impl WindowHandler for Handler<'_> {
+            fn handle(&self, _: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+                LRESULT(self.value as _)
+            }
+        }
+
+        impl Drop for Handler<'_> {
+            fn drop(&mut self) {
+                self.drops.set(self.drops.get() + 1);
+            }
+        }
+
+        let drops = Cell::new(0);
+        let mut storage = SmallHandlerStorage::<16>::default();
+        let thunk = AtlThunk::small_handler(
+            &mut storage,
+            Handler {
+                drops: &drops,
+                value: 7,
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 0, WPARAM(0), LPARAM(0)) }.0,
+            7,
+        );
+
+        assert_eq!(drops.get(), 0);
+
+        drop(thunk);
+
+        assert_eq!(drops.get(), 1);
+    }
+}
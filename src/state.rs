@@ -0,0 +1,115 @@
+//! Handlers of window messages that keep their states in a [`RefCell`].
+
+use crate::context::ContextThunk;
+use crate::sys::Win32::Foundation::{LPARAM, LRESULT, WPARAM};
+use crate::WindowHandler;
+use core::cell::RefCell;
+
+/// Context object that passes a [`RefCell`] state to a handler function, see [`ContextThunk::for_state`].
+///
+/// The handler receives `&RefCell<S>` instead of `&mut S`, because a reentrant message may be dispatched while an
+/// outer call of the handler still holds a borrow of the state. Keeping borrows short, or using
+/// [`RefCell::try_borrow_mut`], turns such conflicts into recoverable errors instead of aliased mutable references.
+pub struct StateHandler<S, F> {
+    state: RefCell<S>,
+    handler: F,
+}
+
+impl<S, F> StateHandler<S, F> {
+    /// Returns the state passed to the handler.
+    pub fn state(&self) -> &RefCell<S> {
+        &self.state
+    }
+
+    /// Returns the state, dropping the handler.
+    pub fn into_state(self) -> S {
+        self.state.into_inner()
+    }
+}
+
+impl<S, F> WindowHandler for StateHandler<S, F>
+where
+    F: Fn(&RefCell<S>, u32, WPARAM, LPARAM) -> LRESULT,
+{
+    fn handle(&self, message: u32, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+        (self.handler)(&self.state, message, w_param, l_param)
+    }
+}
+
+impl<S, F> ContextThunk<StateHandler<S, F>>
+where
+    F: Fn(&RefCell<S>, u32, WPARAM, LPARAM) -> LRESULT,
+{
+    /// Creates a new [`ContextThunk`] object that owns `state` in a [`RefCell`], and dispatches messages to `handler`
+    /// with a reference to it, see [`StateHandler`].
+    #[track_caller]
+    pub fn for_state(state: S, handler: F) -> crate::sys::core::Result<Self> {
+        Self::for_handler(StateHandler {
+            state: RefCell::new(state),
+            handler,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use crate::sys::Win32::UI::WindowsAndMessaging::WM_USER;
+    use crate::{ContextThunk, WindowProcedure};
+    use core::cell::{Cell, RefCell};
+
+    #[test]
+    fn test_context_thunk_for_state() {
+        struct State {
+            window_procedure: Option<WindowProcedure>,
+            count: u32,
+        }
+
+        let rejected = Cell::new(0);
+
+        let mut thunk = ContextThunk::for_state(
+            State {
+                window_procedure: None,
+                count: 0,
+            },
+            |state: &RefCell<State>, message, w_param, _| {
+                let Ok(mut state) = state.try_borrow_mut() else {
+                    // The outer call still borrows the state.
+                    rejected.set(rejected.get() + 1);
+
+                    return LRESULT(-1);
+                };
+
+                state.count += 1;
+
+                if message == WM_USER {
+                    if let Some(window_procedure) = state.window_procedure {
+                        // Like a message sent to the window by the handler.
+                        let result = unsafe { window_procedure(HWND::default(), WM_USER + 1, WPARAM(0), LPARAM(0)) };
+
+                        assert_eq!(result.0, -1);
+                    }
+                }
+
+                LRESULT(w_param.0 as _)
+            },
+        )
+        .unwrap();
+
+        let window_procedure = thunk.as_window_procedure();
+
+        thunk.context().state().borrow_mut().window_procedure = Some(window_procedure);
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_USER, WPARAM(3), LPARAM(0)) }.0,
+            3,
+        );
+
+        assert_eq!(thunk.context().state().borrow().count, 1);
+        assert_eq!(rejected.get(), 1);
+
+        let state = thunk.take_context().unwrap().into_state();
+
+        assert_eq!(state.count, 1);
+    }
+}
@@ -0,0 +1,113 @@
+//! Global statistics of ATL thunk operations.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static INITIALIZED: AtomicUsize = AtomicUsize::new(0);
+static PEAK_LIVE: AtomicUsize = AtomicUsize::new(0);
+static POOL_IDLE: AtomicUsize = AtomicUsize::new(0);
+static POOL_LIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// Statistics of ATL thunk operations made through this crate in the current process.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ThunkStats {
+    /// Total number of allocated ATL thunks.
+    pub allocated: usize,
+    /// Number of ATL thunks that are allocated but not yet freed.
+    pub live: usize,
+    /// Total number of ATL thunk data initializations. Each constructor initializes the associated data once, except
+    /// [`UninitAtlThunk::try_new`](crate::UninitAtlThunk::try_new), which leaves it to the caller, and each update,
+    /// like [`AtlThunk::set_data`](crate::AtlThunk::set_data), counts once more.
+    pub initialized: usize,
+    /// Highest value of [`ThunkStats::live`] so far.
+    pub peak_live: usize,
+    /// Sum of [`AtlThunkPool::idle_count`](crate::AtlThunkPool::idle_count) over all live pools.
+    pub pool_idle: usize,
+    /// Sum of [`AtlThunkPool::live_count`](crate::AtlThunkPool::live_count) over all live pools.
+    pub pool_live: usize,
+}
+
+/// Returns the current ATL thunk statistics. Each counter is read individually, so the result may not be a consistent
+/// snapshot if other threads are operating on ATL thunks concurrently.
+pub fn stats() -> ThunkStats {
+    ThunkStats {
+        allocated: ALLOCATED.load(Ordering::Relaxed),
+        live: LIVE.load(Ordering::Relaxed),
+        initialized: INITIALIZED.load(Ordering::Relaxed),
+        peak_live: PEAK_LIVE.load(Ordering::Relaxed),
+        pool_idle: POOL_IDLE.load(Ordering::Relaxed),
+        pool_live: POOL_LIVE.load(Ordering::Relaxed),
+    }
+}
+
+pub(crate) fn record_allocate() {
+    ALLOCATED.fetch_add(1, Ordering::Relaxed);
+
+    let live = LIVE.fetch_add(1, Ordering::Relaxed) + 1;
+
+    PEAK_LIVE.fetch_max(live, Ordering::Relaxed);
+}
+
+pub(crate) fn record_initialize() {
+    INITIALIZED.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn record_free() {
+    LIVE.fetch_sub(1, Ordering::Relaxed);
+}
+
+#[cfg_attr(not(feature = "alloc"), expect(dead_code, reason = "only used by `alloc` feature"))]
+pub(crate) fn record_pool_acquire(reused: bool) {
+    if reused {
+        POOL_IDLE.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    POOL_LIVE.fetch_add(1, Ordering::Relaxed);
+}
+
+#[cfg_attr(not(feature = "alloc"), expect(dead_code, reason = "only used by `alloc` feature"))]
+pub(crate) fn record_pool_release(acquired: bool) {
+    if acquired {
+        POOL_LIVE.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    POOL_IDLE.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Records that a pool stops counting `idle` idle ATL thunks and `live` acquired ones, because it is drained or
+/// dropped.
+#[cfg_attr(not(feature = "alloc"), expect(dead_code, reason = "only used by `alloc` feature"))]
+pub(crate) fn record_pool_forget(idle: usize, live: usize) {
+    POOL_IDLE.fetch_sub(idle, Ordering::Relaxed);
+    POOL_LIVE.fetch_sub(live, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use crate::AtlThunk;
+
+    #[test]
+    fn test_stats() {
+        unsafe extern "system" fn callback(_: HWND, _: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+            LRESULT(0)
+        }
+
+        let before = super::stats();
+        let mut thunk = AtlThunk::try_new_with(callback, HWND::default()).unwrap();
+
+        thunk.set_data(callback, HWND::default());
+
+        let during = super::stats();
+
+        drop(thunk);
+
+        // Other tests may operate on ATL thunks concurrently, so only lower bounds can be checked.
+        assert!(during.allocated > before.allocated);
+        assert!(during.initialized >= before.initialized + 2);
+        assert!(during.live >= 1);
+        assert!(during.peak_live >= during.live);
+        assert!(super::stats().allocated >= during.allocated);
+    }
+}
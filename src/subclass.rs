@@ -0,0 +1,317 @@
+//! Window procedures for subclassed windows, which forward unhandled messages to the original window procedure.
+
+use crate::context::{self, CharSet, ContextThunk};
+use crate::sys::Win32::Foundation::{E_POINTER, HWND, LPARAM, LRESULT, WPARAM};
+use crate::sys::Win32::UI::WindowsAndMessaging::{CallWindowProcA, CallWindowProcW, GWLP_WNDPROC, WNDPROC};
+use crate::{OptionalWindowHandler, WindowProcedure};
+
+/// Returns the current window procedure of `window`.
+pub(crate) fn current_window_procedure(window: HWND, char_set: CharSet) -> usize {
+    #[cfg(target_pointer_width = "64")]
+    let result = unsafe {
+        use crate::sys::Win32::UI::WindowsAndMessaging::{GetWindowLongPtrA, GetWindowLongPtrW};
+
+        match char_set {
+            CharSet::Wide => GetWindowLongPtrW(window, GWLP_WNDPROC),
+            CharSet::Ansi => GetWindowLongPtrA(window, GWLP_WNDPROC),
+        }
+    } as usize;
+
+    #[cfg(target_pointer_width = "32")]
+    let result = unsafe {
+        use crate::sys::Win32::UI::WindowsAndMessaging::{GetWindowLongA, GetWindowLongW};
+
+        match char_set {
+            CharSet::Wide => GetWindowLongW(window, GWLP_WNDPROC),
+            CharSet::Ansi => GetWindowLongA(window, GWLP_WNDPROC),
+        }
+    } as u32 as usize;
+
+    result
+}
+
+/// Replaces the window procedure of `window` with `window_procedure`.
+///
+/// # Safety
+///
+/// `window_procedure` must stay valid for as long as `window` may receive messages with it.
+pub(crate) unsafe fn set_window_procedure(window: HWND, char_set: CharSet, window_procedure: WindowProcedure) {
+    #[cfg(target_pointer_width = "64")]
+    unsafe {
+        use crate::sys::Win32::UI::WindowsAndMessaging::{SetWindowLongPtrA, SetWindowLongPtrW};
+
+        let value = window_procedure as usize as isize;
+
+        match char_set {
+            CharSet::Wide => SetWindowLongPtrW(window, GWLP_WNDPROC, value),
+            CharSet::Ansi => SetWindowLongPtrA(window, GWLP_WNDPROC, value),
+        };
+    }
+
+    #[cfg(target_pointer_width = "32")]
+    unsafe {
+        use crate::sys::Win32::UI::WindowsAndMessaging::{SetWindowLongA, SetWindowLongW};
+
+        let value = window_procedure as usize as u32 as i32;
+
+        match char_set {
+            CharSet::Wide => SetWindowLongW(window, GWLP_WNDPROC, value),
+            CharSet::Ansi => SetWindowLongA(window, GWLP_WNDPROC, value),
+        };
+    }
+}
+
+/// Context object that forwards messages not handled by an [`OptionalWindowHandler`] to a base window procedure, see
+/// [`ContextThunk::from_handler_forwarding`].
+pub struct ForwardingHandler<H> {
+    base: WindowProcedure,
+    handler: H,
+}
+
+impl<H> ForwardingHandler<H> {
+    /// Returns the window procedure that receives messages not handled by the handler.
+    pub fn base(&self) -> WindowProcedure {
+        self.base
+    }
+
+    /// Returns the handler.
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+}
+
+unsafe extern "system" fn forwarding_handler_window_procedure<H>(
+    forwarding_handler: HWND,
+    message: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> LRESULT
+where
+    H: OptionalWindowHandler,
+{
+    let forwarding_handler = forwarding_handler.0.cast::<ForwardingHandler<H>>().cast_const();
+    let header = unsafe { context::context_header(forwarding_handler) };
+
+    // The base window procedure is part of the context object, so it is also read inside the dispatch.
+    header.dispatch(|| {
+        let forwarding_handler = unsafe { &*forwarding_handler };
+
+        match forwarding_handler.handler.handle(message, w_param, l_param) {
+            None => {
+                let base = Some(forwarding_handler.base);
+                let window = header.window();
+
+                match header.char_set() {
+                    CharSet::Wide => unsafe { CallWindowProcW(base, window, message, w_param, l_param) },
+                    CharSet::Ansi => unsafe { CallWindowProcA(base, window, message, w_param, l_param) },
+                }
+            }
+            Some(result) => result,
+        }
+    })
+}
+
+impl<H> ContextThunk<ForwardingHandler<H>>
+where
+    H: OptionalWindowHandler,
+{
+    /// Creates a new [`ContextThunk`] object that owns `handler`, and dispatches messages to it. Messages for which the
+    /// handler returns [`None`] are passed to `base` with
+    /// [`CallWindowProcW`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-callwindowprocw),
+    /// which is how subclassed controls, like `EDIT` or `BUTTON`, get their default processing. `base` is usually the
+    /// window procedure returned when the thunk is installed with
+    /// [`SetWindowLongPtrW`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-setwindowlongptrw),
+    /// and it needs the window bound with [`ContextThunk::bind_window`]. For windows with ANSI window classes, use
+    /// [`ContextThunk::set_char_set`] to select
+    /// [`CallWindowProcA`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-callwindowproca)
+    /// instead.
+    ///
+    /// Returns an error with code `E_POINTER` if `base` is [`None`].
+    #[track_caller]
+    pub fn from_handler_forwarding(base: WNDPROC, handler: H) -> crate::sys::core::Result<Self> {
+        let base = base.ok_or(E_POINTER)?;
+
+        unsafe {
+            Self::try_new_with_procedure(
+                ForwardingHandler { base, handler },
+                forwarding_handler_window_procedure::<H>,
+            )
+        }
+    }
+
+    /// Replaces the window procedure of the bound window with the one of the thunk, if it is still the base window
+    /// procedure. Returns whether the window procedure is replaced, so installing an already installed thunk returns
+    /// `false`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the context object has been taken by [`ContextThunk::take_context`].
+    ///
+    /// # Safety
+    ///
+    /// The thunk must be uninstalled with [`ContextThunk::uninstall`] before it is dropped, or the window must be
+    /// destroyed by then.
+    pub unsafe fn install(&self) -> bool {
+        let window = self.window();
+        let char_set = self.char_set();
+        let base = self.context().base;
+
+        let installed = !window.is_invalid() && current_window_procedure(window, char_set) == base as usize;
+
+        if installed {
+            unsafe { set_window_procedure(window, char_set, self.as_window_procedure()) };
+        }
+
+        installed
+    }
+
+    /// Restores the base window procedure of the bound window, if the window procedure of the thunk is installed.
+    /// Returns whether the window procedure is restored, so uninstalling twice returns `false` the second time. This
+    /// must be done before the thunk is dropped, or the window would be left with a dangling window procedure.
+    ///
+    /// If another window procedure has been installed on top of the thunk, the thunk is still reachable from it, and
+    /// can not be uninstalled here.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the thunk is installed, but the context object has been taken by [`ContextThunk::take_context`].
+    pub fn uninstall(&self) -> bool {
+        let window = self.window();
+        let char_set = self.char_set();
+
+        let installed =
+            !window.is_invalid() && current_window_procedure(window, char_set) == self.as_window_procedure() as usize;
+
+        if installed {
+            // The base window procedure has been valid enough to forward messages to.
+            unsafe { set_window_procedure(window, char_set, self.context().base) };
+        }
+
+        installed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sys::Win32::Foundation::{E_POINTER, HWND, LPARAM, LRESULT, WPARAM};
+    use crate::sys::Win32::UI::WindowsAndMessaging::WM_USER;
+    use crate::ContextThunk;
+
+    #[test]
+    fn test_context_thunk_from_handler_forwarding() {
+        unsafe extern "system" fn base(window: HWND, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+            LRESULT(window.0 as isize + message as isize)
+        }
+
+        let thunk = ContextThunk::from_handler_forwarding(Some(base), |message: u32, w_param: WPARAM, _: LPARAM| {
+            (message == WM_USER).then_some(LRESULT(w_param.0 as _))
+        })
+        .unwrap();
+
+        thunk.bind_window(HWND(2 as _));
+
+        let window_procedure = thunk.as_window_procedure();
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_USER, WPARAM(3), LPARAM(0)) }.0,
+            3,
+        );
+
+        // Other messages are forwarded to the base window procedure with the bound window.
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), 5, WPARAM(3), LPARAM(0)) }.0,
+            7,
+        );
+
+        assert_eq!(thunk.context().base() as usize, base as *const () as usize);
+
+        assert_eq!(
+            ContextThunk::from_handler_forwarding(None, |_: u32, _: WPARAM, _: LPARAM| None)
+                .err()
+                .map(|error| error.code()),
+            Some(E_POINTER),
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_context_thunk_install() {
+        use crate::sys::core::w;
+        use crate::sys::Win32::UI::WindowsAndMessaging::{
+            CreateWindowExW, DestroyWindow, SendMessageW, HWND_MESSAGE, WINDOW_EX_STYLE, WINDOW_STYLE, WNDPROC,
+        };
+        use crate::CharSet;
+        use core::mem;
+
+        let window = unsafe {
+            CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                w!("STATIC"),
+                None,
+                WINDOW_STYLE::default(),
+                0,
+                0,
+                0,
+                0,
+                HWND_MESSAGE,
+                None,
+                None,
+                None,
+            )
+        }
+        .unwrap();
+
+        let base = super::current_window_procedure(window, CharSet::Wide);
+
+        let thunk = ContextThunk::from_handler_forwarding(
+            unsafe { mem::transmute::<usize, WNDPROC>(base) },
+            |message: u32, w_param: WPARAM, _: LPARAM| (message == WM_USER).then_some(LRESULT(w_param.0 as _)),
+        )
+        .unwrap();
+
+        // Not installed yet.
+        assert!(!thunk.uninstall());
+
+        thunk.bind_window(window);
+
+        assert!(unsafe { thunk.install() });
+        assert!(!unsafe { thunk.install() });
+
+        assert_eq!(
+            super::current_window_procedure(window, CharSet::Wide),
+            thunk.as_window_procedure() as usize,
+        );
+
+        assert_eq!(unsafe { SendMessageW(window, WM_USER, WPARAM(3), LPARAM(0)) }.0, 3);
+
+        assert!(thunk.uninstall());
+        assert!(!thunk.uninstall());
+
+        assert_eq!(super::current_window_procedure(window, CharSet::Wide), base);
+
+        unsafe { DestroyWindow(window) }.unwrap();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_forwarding_handler_while_mutably_borrowed() {
+        crate::test_support::assert_aborts(
+            "subclass::tests::test_forwarding_handler_while_mutably_borrowed",
+            || {
+                use crate::sys::Win32::UI::WindowsAndMessaging::WM_SETTEXT;
+
+                let mut thunk = ContextThunk::from_handler_forwarding(
+                    Some(crate::default_window_procedure()),
+                    |_: u32, _: WPARAM, _: LPARAM| None,
+                )
+                .unwrap();
+
+                let window_procedure = thunk.as_window_procedure();
+                let _context = thunk.get_mut();
+
+                // Forwarding reads the base window procedure from the context object, so it is checked too.
+                unsafe { window_procedure(HWND::default(), WM_SETTEXT, WPARAM(0), LPARAM(0)) };
+            },
+        );
+    }
+}
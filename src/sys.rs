@@ -0,0 +1,340 @@
+//! Stand-ins for the items of the `windows` crate used by this crate, so that with the `mock-backend` feature, the crate
+//! builds and its tests run on hosts other than Windows, where the `windows` crate is empty. On Windows, `sys` is the
+//! `windows` crate itself.
+//!
+//! The stand-ins mirror the paths and signatures of the `windows` crate. Window functions behave as if no window
+//! exists, and default window procedures return zero.
+
+#![allow(dead_code, reason = "which stand-ins are used depends on the enabled features")]
+#![expect(
+    clippy::upper_case_acronyms,
+    non_camel_case_types,
+    non_snake_case,
+    reason = "mirrors the names of the `windows` crate"
+)]
+
+pub mod core {
+    pub use windows_result::{Error, Result, HRESULT};
+
+    /// A pointer to a null-terminated string of 16-bit Unicode characters.
+    #[repr(transparent)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct PCWSTR(pub *const u16);
+
+    impl PCWSTR {
+        pub const fn from_raw(ptr: *const u16) -> Self {
+            Self(ptr)
+        }
+    }
+
+    /// Converts an ASCII string literal to a [`PCWSTR`] pointing to a null-terminated static string.
+    #[cfg_attr(
+        not(feature = "alloc"),
+        expect(unused_macros, reason = "only used by `alloc` feature")
+    )]
+    macro_rules! w {
+        ($s:literal) => {{
+            const INPUT: &[u8] = $s.as_bytes();
+            const OUTPUT: &[u16; INPUT.len() + 1] = &{
+                let mut output = [0; INPUT.len() + 1];
+                let mut i = 0;
+
+                while i < INPUT.len() {
+                    assert!(INPUT[i].is_ascii(), "only ASCII strings are supported");
+
+                    output[i] = INPUT[i] as u16;
+                    i += 1;
+                }
+
+                output
+            };
+
+            $crate::sys::core::PCWSTR::from_raw(OUTPUT.as_ptr())
+        }};
+    }
+
+    #[cfg_attr(
+        not(feature = "alloc"),
+        expect(unused_imports, reason = "only used by `alloc` feature")
+    )]
+    pub(crate) use w;
+}
+
+pub mod Win32 {
+    pub mod Foundation {
+        use crate::sys::core::HRESULT;
+        use ::core::ffi::c_void;
+        use ::core::ptr;
+
+        pub const E_HANDLE: HRESULT = HRESULT(0x8007_0006_u32 as _);
+        pub const E_OUTOFMEMORY: HRESULT = HRESULT(0x8007_000E_u32 as _);
+        pub const E_POINTER: HRESULT = HRESULT(0x8000_4003_u32 as _);
+        pub const E_UNEXPECTED: HRESULT = HRESULT(0x8000_FFFF_u32 as _);
+        pub const ERROR_INVALID_WINDOW_HANDLE: u32 = 1400;
+
+        #[repr(transparent)]
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+        pub struct BOOL(pub i32);
+
+        impl BOOL {
+            pub fn as_bool(self) -> bool {
+                self.0 != 0
+            }
+        }
+
+        #[repr(transparent)]
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        pub struct HWND(pub *mut c_void);
+
+        impl HWND {
+            pub fn is_invalid(&self) -> bool {
+                self.0.is_null()
+            }
+        }
+
+        impl Default for HWND {
+            fn default() -> Self {
+                Self(ptr::null_mut())
+            }
+        }
+
+        #[repr(transparent)]
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+        pub struct LPARAM(pub isize);
+
+        #[repr(transparent)]
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+        pub struct LRESULT(pub isize);
+
+        #[repr(transparent)]
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+        pub struct WPARAM(pub usize);
+    }
+
+    pub mod System {
+        pub mod Memory {
+            #[repr(transparent)]
+            #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+            pub struct AtlThunkData_t(pub isize);
+        }
+    }
+
+    pub mod UI {
+        pub mod WindowsAndMessaging {
+            use crate::sys::core::{Error, Result, HRESULT, PCWSTR};
+            use crate::sys::Win32::Foundation::{BOOL, ERROR_INVALID_WINDOW_HANDLE, HWND, LPARAM, LRESULT, WPARAM};
+
+            pub type DLGPROC = Option<unsafe extern "system" fn(HWND, u32, WPARAM, LPARAM) -> isize>;
+            pub type WNDPROC = Option<unsafe extern "system" fn(HWND, u32, WPARAM, LPARAM) -> LRESULT>;
+
+            #[repr(transparent)]
+            #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+            pub struct WINDOW_LONG_PTR_INDEX(pub i32);
+
+            pub const DWLP_MSGRESULT: u32 = 0u32;
+            pub const GWLP_WNDPROC: WINDOW_LONG_PTR_INDEX = WINDOW_LONG_PTR_INDEX(-4i32);
+            pub const SIZE_MAXIMIZED: u32 = 2u32;
+            pub const WM_ACTIVATE: u32 = 6u32;
+            pub const WM_ACTIVATEAPP: u32 = 28u32;
+            pub const WM_CANCELMODE: u32 = 31u32;
+            pub const WM_CAPTURECHANGED: u32 = 533u32;
+            pub const WM_CHAR: u32 = 258u32;
+            pub const WM_CHARTOITEM: u32 = 47u32;
+            pub const WM_CLOSE: u32 = 16u32;
+            pub const WM_COMMAND: u32 = 273u32;
+            pub const WM_COMPAREITEM: u32 = 57u32;
+            pub const WM_CONTEXTMENU: u32 = 123u32;
+            pub const WM_COPYDATA: u32 = 74u32;
+            pub const WM_CREATE: u32 = 1u32;
+            pub const WM_CTLCOLORBTN: u32 = 309u32;
+            pub const WM_CTLCOLORDLG: u32 = 310u32;
+            pub const WM_CTLCOLOREDIT: u32 = 307u32;
+            pub const WM_CTLCOLORLISTBOX: u32 = 308u32;
+            pub const WM_CTLCOLORMSGBOX: u32 = 306u32;
+            pub const WM_CTLCOLORSCROLLBAR: u32 = 311u32;
+            pub const WM_CTLCOLORSTATIC: u32 = 312u32;
+            pub const WM_DEADCHAR: u32 = 259u32;
+            pub const WM_DESTROY: u32 = 2u32;
+            pub const WM_DISPLAYCHANGE: u32 = 126u32;
+            pub const WM_DPICHANGED: u32 = 736u32;
+            pub const WM_DRAWITEM: u32 = 43u32;
+            pub const WM_DROPFILES: u32 = 563u32;
+            pub const WM_ENABLE: u32 = 10u32;
+            pub const WM_ENTERIDLE: u32 = 289u32;
+            pub const WM_ENTERMENULOOP: u32 = 529u32;
+            pub const WM_ENTERSIZEMOVE: u32 = 561u32;
+            pub const WM_ERASEBKGND: u32 = 20u32;
+            pub const WM_EXITMENULOOP: u32 = 530u32;
+            pub const WM_EXITSIZEMOVE: u32 = 562u32;
+            pub const WM_GETFONT: u32 = 49u32;
+            pub const WM_GETICON: u32 = 127u32;
+            pub const WM_GETMINMAXINFO: u32 = 36u32;
+            pub const WM_GETOBJECT: u32 = 61u32;
+            pub const WM_GETTEXT: u32 = 13u32;
+            pub const WM_GETTEXTLENGTH: u32 = 14u32;
+            pub const WM_HELP: u32 = 83u32;
+            pub const WM_HSCROLL: u32 = 276u32;
+            pub const WM_INITDIALOG: u32 = 272u32;
+            pub const WM_INITMENU: u32 = 278u32;
+            pub const WM_INITMENUPOPUP: u32 = 279u32;
+            pub const WM_KEYDOWN: u32 = 256u32;
+            pub const WM_KEYFIRST: u32 = 256u32;
+            pub const WM_KEYLAST: u32 = 265u32;
+            pub const WM_KEYUP: u32 = 257u32;
+            pub const WM_KILLFOCUS: u32 = 8u32;
+            pub const WM_LBUTTONDBLCLK: u32 = 515u32;
+            pub const WM_LBUTTONDOWN: u32 = 513u32;
+            pub const WM_LBUTTONUP: u32 = 514u32;
+            pub const WM_MBUTTONDBLCLK: u32 = 521u32;
+            pub const WM_MBUTTONDOWN: u32 = 519u32;
+            pub const WM_MBUTTONUP: u32 = 520u32;
+            pub const WM_MEASUREITEM: u32 = 44u32;
+            pub const WM_MENUSELECT: u32 = 287u32;
+            pub const WM_MOUSEACTIVATE: u32 = 33u32;
+            pub const WM_MOUSEFIRST: u32 = 512u32;
+            pub const WM_MOUSEHWHEEL: u32 = 526u32;
+            pub const WM_MOUSELAST: u32 = 526u32;
+            pub const WM_MOUSEMOVE: u32 = 512u32;
+            pub const WM_MOUSEWHEEL: u32 = 522u32;
+            pub const WM_MOVE: u32 = 3u32;
+            pub const WM_MOVING: u32 = 534u32;
+            pub const WM_NCACTIVATE: u32 = 134u32;
+            pub const WM_NCCALCSIZE: u32 = 131u32;
+            pub const WM_NCCREATE: u32 = 129u32;
+            pub const WM_NCDESTROY: u32 = 130u32;
+            pub const WM_NCHITTEST: u32 = 132u32;
+            pub const WM_NCLBUTTONDOWN: u32 = 161u32;
+            pub const WM_NCMOUSEHOVER: u32 = 672u32;
+            pub const WM_NCMOUSELEAVE: u32 = 674u32;
+            pub const WM_NCMOUSEMOVE: u32 = 160u32;
+            pub const WM_NCPAINT: u32 = 133u32;
+            pub const WM_NCXBUTTONDBLCLK: u32 = 173u32;
+            pub const WM_NOTIFY: u32 = 78u32;
+            pub const WM_NULL: u32 = 0u32;
+            pub const WM_PAINT: u32 = 15u32;
+            pub const WM_PARENTNOTIFY: u32 = 528u32;
+            pub const WM_PRINT: u32 = 791u32;
+            pub const WM_PRINTCLIENT: u32 = 792u32;
+            pub const WM_QUERYDRAGICON: u32 = 55u32;
+            pub const WM_QUERYENDSESSION: u32 = 17u32;
+            pub const WM_QUIT: u32 = 18u32;
+            pub const WM_RBUTTONDBLCLK: u32 = 518u32;
+            pub const WM_RBUTTONDOWN: u32 = 516u32;
+            pub const WM_RBUTTONUP: u32 = 517u32;
+            pub const WM_SETCURSOR: u32 = 32u32;
+            pub const WM_SETFOCUS: u32 = 7u32;
+            pub const WM_SETFONT: u32 = 48u32;
+            pub const WM_SETICON: u32 = 128u32;
+            pub const WM_SETREDRAW: u32 = 11u32;
+            pub const WM_SETTEXT: u32 = 12u32;
+            pub const WM_SETTINGCHANGE: u32 = 26u32;
+            pub const WM_SHOWWINDOW: u32 = 24u32;
+            pub const WM_SIZE: u32 = 5u32;
+            pub const WM_SIZING: u32 = 532u32;
+            pub const WM_SYNCPAINT: u32 = 136u32;
+            pub const WM_SYSCHAR: u32 = 262u32;
+            pub const WM_SYSCOLORCHANGE: u32 = 21u32;
+            pub const WM_SYSCOMMAND: u32 = 274u32;
+            pub const WM_SYSKEYDOWN: u32 = 260u32;
+            pub const WM_SYSKEYUP: u32 = 261u32;
+            pub const WM_THEMECHANGED: u32 = 794u32;
+            pub const WM_TIMER: u32 = 275u32;
+            pub const WM_UNICHAR: u32 = 265u32;
+            pub const WM_USER: u32 = 1024u32;
+            pub const WM_VKEYTOITEM: u32 = 46u32;
+            pub const WM_VSCROLL: u32 = 277u32;
+            pub const WM_WINDOWPOSCHANGED: u32 = 71u32;
+            pub const WM_WINDOWPOSCHANGING: u32 = 70u32;
+            pub const WM_XBUTTONDBLCLK: u32 = 525u32;
+            pub const WM_XBUTTONDOWN: u32 = 523u32;
+            pub const WM_XBUTTONUP: u32 = 524u32;
+
+            /// Calls `procedure` directly, since there is no window procedure that needs translating.
+            pub unsafe fn CallWindowProcA(
+                procedure: WNDPROC,
+                window: HWND,
+                message: u32,
+                w_param: WPARAM,
+                l_param: LPARAM,
+            ) -> LRESULT {
+                unsafe { CallWindowProcW(procedure, window, message, w_param, l_param) }
+            }
+
+            /// Calls `procedure` directly, since there is no window procedure that needs translating.
+            pub unsafe fn CallWindowProcW(
+                procedure: WNDPROC,
+                window: HWND,
+                message: u32,
+                w_param: WPARAM,
+                l_param: LPARAM,
+            ) -> LRESULT {
+                match procedure {
+                    None => LRESULT(0),
+                    Some(procedure) => unsafe { procedure(window, message, w_param, l_param) },
+                }
+            }
+
+            pub unsafe fn DefWindowProcA(window: HWND, message: u32, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+                unsafe { DefWindowProcW(window, message, w_param, l_param) }
+            }
+
+            /// Returns zero, which is what `DefWindowProcW` returns for a null window.
+            pub unsafe fn DefWindowProcW(_: HWND, _: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+                LRESULT(0)
+            }
+
+            #[cfg(target_pointer_width = "32")]
+            pub unsafe fn GetWindowLongA(_: HWND, _: WINDOW_LONG_PTR_INDEX) -> i32 {
+                0
+            }
+
+            #[cfg(target_pointer_width = "64")]
+            pub unsafe fn GetWindowLongPtrA(_: HWND, _: WINDOW_LONG_PTR_INDEX) -> isize {
+                0
+            }
+
+            #[cfg(target_pointer_width = "64")]
+            pub unsafe fn GetWindowLongPtrW(_: HWND, _: WINDOW_LONG_PTR_INDEX) -> isize {
+                0
+            }
+
+            #[cfg(target_pointer_width = "32")]
+            pub unsafe fn GetWindowLongW(_: HWND, _: WINDOW_LONG_PTR_INDEX) -> i32 {
+                0
+            }
+
+            pub unsafe fn IsWindow(_: HWND) -> BOOL {
+                BOOL(0)
+            }
+
+            pub unsafe fn PostMessageW(_: HWND, _: u32, _: WPARAM, _: LPARAM) -> Result<()> {
+                Err(Error::from_hresult(HRESULT::from_win32(ERROR_INVALID_WINDOW_HANDLE)))
+            }
+
+            /// Returns the first registered message identifier, since the crate registers only one message.
+            pub unsafe fn RegisterWindowMessageW(_: PCWSTR) -> u32 {
+                0xC000
+            }
+
+            #[cfg(target_pointer_width = "32")]
+            pub unsafe fn SetWindowLongA(_: HWND, _: WINDOW_LONG_PTR_INDEX, _: i32) -> i32 {
+                0
+            }
+
+            #[cfg(target_pointer_width = "64")]
+            pub unsafe fn SetWindowLongPtrA(_: HWND, _: WINDOW_LONG_PTR_INDEX, _: isize) -> isize {
+                0
+            }
+
+            #[cfg(target_pointer_width = "64")]
+            pub unsafe fn SetWindowLongPtrW(_: HWND, _: WINDOW_LONG_PTR_INDEX, _: isize) -> isize {
+                0
+            }
+
+            #[cfg(target_pointer_width = "32")]
+            pub unsafe fn SetWindowLongW(_: HWND, _: WINDOW_LONG_PTR_INDEX, _: i32) -> i32 {
+                0
+            }
+        }
+    }
+}
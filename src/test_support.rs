@@ -0,0 +1,39 @@
+//! Helpers shared by unit tests.
+
+/// The result of messages passed to the default window procedure by the window procedures under test. Tests do not bind
+/// real windows, so such messages reach
+/// [`DefWindowProcW`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-defwindowprocw) with a
+/// null window, which returns zero, as do the stand-ins of other hosts.
+pub(crate) const DEFAULT_RESULT: isize = 0;
+
+/// Runs the test `name`, which is the path of the calling test without the crate name, in a child process, and asserts
+/// that the child process aborts, which is how window procedures report panics. In the child process, `f` is called
+/// instead, which is expected to abort.
+#[cfg(feature = "std")]
+pub(crate) fn assert_aborts(name: &str, f: impl FnOnce()) {
+    use std::process::{Command, Stdio};
+    use std::{env, process};
+
+    const CHILD_VARIABLE: &str = "ATL_THUNK_EXPECT_ABORT";
+
+    if env::var_os(CHILD_VARIABLE).is_some() {
+        f();
+
+        // Exit with the status of failed tests, so a missing abort is not mistaken for one.
+        process::exit(101);
+    }
+
+    let status = Command::new(env::current_exe().unwrap())
+        .args([name, "--exact", "--test-threads=1"])
+        .env(CHILD_VARIABLE, "1")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+
+    // Test failures exit with 101, and a child process that does not run `f` exits successfully.
+    assert!(
+        !status.success() && status.code() != Some(101),
+        "`{name}` does not abort, exit status: {status}",
+    );
+}
@@ -0,0 +1,148 @@
+//! Explicit ownership transfer of ATL thunks between threads.
+
+#[cfg(all(feature = "debug-checks", debug_assertions))]
+use crate::checks;
+#[cfg(feature = "alloc")]
+use crate::context::ContextThunk;
+use crate::AtlThunk;
+#[cfg(feature = "alloc")]
+use crate::WindowHandler;
+
+/// A [`Send`] token that carries the ownership of an [`AtlThunk`] to another thread, see [`AtlThunk::into_token`].
+///
+/// [`AtlThunk`] itself is [`Send`], so the token adds no capability, but it makes the handoff visible in type
+/// signatures, like a channel that transfers thunks from a worker thread to the UI thread.
+///
+/// With the `debug-checks` feature in debug builds, the token records the thread that packs it, and a warning is
+/// written to the debugger output if it is claimed on the same thread.
+#[must_use = "dropping the token frees the ATL thunk"]
+pub struct ThunkToken {
+    thunk: AtlThunk,
+    #[cfg(all(feature = "debug-checks", debug_assertions))]
+    source_thread_id: u32,
+}
+
+impl ThunkToken {
+    /// Consumes the token on the destination thread, and returns the [`AtlThunk`] object.
+    pub fn claim(self) -> AtlThunk {
+        #[cfg(all(feature = "debug-checks", debug_assertions))]
+        checks::warn_if_claimed_on_source_thread(self.source_thread_id);
+
+        self.thunk
+    }
+}
+
+impl AtlThunk {
+    /// Packs the [`AtlThunk`] object into a [`ThunkToken`] for transferring it to another thread.
+    pub fn into_token(self) -> ThunkToken {
+        ThunkToken {
+            thunk: self,
+            #[cfg(all(feature = "debug-checks", debug_assertions))]
+            source_thread_id: checks::current_thread_id(),
+        }
+    }
+}
+
+/// A [`Send`] token that carries a handler to another thread, where it becomes a [`ContextThunk`] object, see
+/// [`ContextThunkToken::claim`].
+///
+/// The handler can be built on a worker thread, while the ATL thunk is only allocated by [`ContextThunkToken::claim`],
+/// on the thread that creates the window.
+#[cfg(feature = "alloc")]
+#[must_use = "dropping the token drops the handler"]
+pub struct ContextThunkToken<H> {
+    handler: H,
+}
+
+#[cfg(feature = "alloc")]
+impl<H> ContextThunkToken<H>
+where
+    H: WindowHandler,
+{
+    /// Creates a new [`ContextThunkToken`] object that carries `handler`.
+    pub fn new(handler: H) -> Self {
+        Self { handler }
+    }
+
+    /// Consumes the token on the destination thread, and creates a [`ContextThunk`] object that owns the handler, see
+    /// [`ContextThunk::for_handler`].
+    #[track_caller]
+    pub fn claim(self) -> crate::sys::core::Result<ContextThunk<H>> {
+        ContextThunk::for_handler(self.handler)
+    }
+
+    /// Returns the handler without allocating an ATL thunk.
+    pub fn into_handler(self) -> H {
+        self.handler
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ThunkToken;
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use crate::AtlThunk;
+
+    #[test]
+    fn test_thunk_token_claim() {
+        fn assert_send<T: Send>(_: &T) {}
+
+        unsafe extern "system" fn callback(first_parameter: HWND, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+            LRESULT(first_parameter.0 as isize + message as isize)
+        }
+
+        let thunk = AtlThunk::try_new_with(callback, HWND(2 as _)).unwrap();
+        let code = thunk.as_window_procedure() as usize;
+        let token: ThunkToken = thunk.into_token();
+
+        assert_send(&token);
+
+        let thunk = token.claim();
+
+        assert_eq!(thunk.as_window_procedure() as usize, code);
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 3, WPARAM(0), LPARAM(0)) }.0,
+            5,
+        );
+    }
+
+    #[cfg(all(feature = "debug-checks", debug_assertions, feature = "std"))]
+    #[test]
+    fn test_thunk_token_source_thread() {
+        use crate::checks;
+        use std::thread;
+
+        let token = thread::spawn(|| AtlThunk::try_new().unwrap().into_token())
+            .join()
+            .unwrap();
+
+        assert!(!checks::warn_if_claimed_on_source_thread(token.source_thread_id));
+
+        let token = AtlThunk::try_new().unwrap().into_token();
+
+        assert!(checks::warn_if_claimed_on_source_thread(token.source_thread_id));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_context_thunk_token_claim() {
+        use super::ContextThunkToken;
+        use std::thread;
+
+        let token = thread::spawn(|| {
+            let value = 3;
+
+            ContextThunkToken::new(move |message: u32, _: WPARAM, _: LPARAM| LRESULT(message as isize * value))
+        })
+        .join()
+        .unwrap();
+
+        let thunk = token.claim().unwrap();
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 2, WPARAM(0), LPARAM(0)) }.0,
+            6,
+        );
+    }
+}
@@ -0,0 +1,389 @@
+//! Handlers that report every dispatched message, for debugging.
+
+#[cfg(feature = "alloc")]
+use crate::context::ContextThunk;
+use crate::sys::Win32::Foundation::{LPARAM, LRESULT, WPARAM};
+use crate::sys::Win32::UI::WindowsAndMessaging::{
+    WM_ACTIVATE, WM_ACTIVATEAPP, WM_CANCELMODE, WM_CAPTURECHANGED, WM_CHAR, WM_CLOSE, WM_COMMAND, WM_CONTEXTMENU,
+    WM_COPYDATA, WM_CREATE, WM_CTLCOLORBTN, WM_CTLCOLORDLG, WM_CTLCOLOREDIT, WM_CTLCOLORSTATIC, WM_DEADCHAR,
+    WM_DESTROY, WM_DISPLAYCHANGE, WM_DPICHANGED, WM_DRAWITEM, WM_DROPFILES, WM_ENABLE, WM_ENTERIDLE, WM_ENTERMENULOOP,
+    WM_ENTERSIZEMOVE, WM_ERASEBKGND, WM_EXITMENULOOP, WM_EXITSIZEMOVE, WM_GETFONT, WM_GETICON, WM_GETMINMAXINFO,
+    WM_GETOBJECT, WM_GETTEXT, WM_GETTEXTLENGTH, WM_HELP, WM_HSCROLL, WM_INITDIALOG, WM_INITMENU, WM_INITMENUPOPUP,
+    WM_KEYDOWN, WM_KEYUP, WM_KILLFOCUS, WM_LBUTTONDBLCLK, WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MBUTTONDBLCLK,
+    WM_MBUTTONDOWN, WM_MBUTTONUP, WM_MEASUREITEM, WM_MENUSELECT, WM_MOUSEACTIVATE, WM_MOUSEHWHEEL, WM_MOUSEMOVE,
+    WM_MOUSEWHEEL, WM_MOVE, WM_MOVING, WM_NCACTIVATE, WM_NCCALCSIZE, WM_NCCREATE, WM_NCDESTROY, WM_NCHITTEST,
+    WM_NCLBUTTONDOWN, WM_NCMOUSEHOVER, WM_NCMOUSELEAVE, WM_NCMOUSEMOVE, WM_NCPAINT, WM_NOTIFY, WM_NULL, WM_PAINT,
+    WM_PARENTNOTIFY, WM_PRINT, WM_PRINTCLIENT, WM_QUERYENDSESSION, WM_QUIT, WM_RBUTTONDBLCLK, WM_RBUTTONDOWN,
+    WM_RBUTTONUP, WM_SETCURSOR, WM_SETFOCUS, WM_SETFONT, WM_SETICON, WM_SETREDRAW, WM_SETTEXT, WM_SETTINGCHANGE,
+    WM_SHOWWINDOW, WM_SIZE, WM_SIZING, WM_SYNCPAINT, WM_SYSCHAR, WM_SYSCOLORCHANGE, WM_SYSCOMMAND, WM_SYSKEYDOWN,
+    WM_SYSKEYUP, WM_THEMECHANGED, WM_TIMER, WM_UNICHAR, WM_USER, WM_VSCROLL, WM_WINDOWPOSCHANGED, WM_WINDOWPOSCHANGING,
+    WM_XBUTTONDBLCLK, WM_XBUTTONDOWN, WM_XBUTTONUP,
+};
+use crate::WindowHandler;
+use core::fmt::{self, Display, Formatter};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Names of common window messages, looked up by [`message_name`]. Messages that share a value with another message
+/// are only listed once, like `WM_SETTINGCHANGE` that shares the value of `WM_WININICHANGE`.
+const MESSAGE_NAMES: &[(u32, &str)] = &[
+    (WM_NULL, "WM_NULL"),
+    (WM_CREATE, "WM_CREATE"),
+    (WM_DESTROY, "WM_DESTROY"),
+    (WM_MOVE, "WM_MOVE"),
+    (WM_SIZE, "WM_SIZE"),
+    (WM_ACTIVATE, "WM_ACTIVATE"),
+    (WM_SETFOCUS, "WM_SETFOCUS"),
+    (WM_KILLFOCUS, "WM_KILLFOCUS"),
+    (WM_ENABLE, "WM_ENABLE"),
+    (WM_SETREDRAW, "WM_SETREDRAW"),
+    (WM_SETTEXT, "WM_SETTEXT"),
+    (WM_GETTEXT, "WM_GETTEXT"),
+    (WM_GETTEXTLENGTH, "WM_GETTEXTLENGTH"),
+    (WM_PAINT, "WM_PAINT"),
+    (WM_CLOSE, "WM_CLOSE"),
+    (WM_QUERYENDSESSION, "WM_QUERYENDSESSION"),
+    (WM_QUIT, "WM_QUIT"),
+    (WM_ERASEBKGND, "WM_ERASEBKGND"),
+    (WM_SYSCOLORCHANGE, "WM_SYSCOLORCHANGE"),
+    (WM_SHOWWINDOW, "WM_SHOWWINDOW"),
+    (WM_SETTINGCHANGE, "WM_SETTINGCHANGE"),
+    (WM_ACTIVATEAPP, "WM_ACTIVATEAPP"),
+    (WM_CANCELMODE, "WM_CANCELMODE"),
+    (WM_SETCURSOR, "WM_SETCURSOR"),
+    (WM_MOUSEACTIVATE, "WM_MOUSEACTIVATE"),
+    (WM_GETMINMAXINFO, "WM_GETMINMAXINFO"),
+    (WM_DRAWITEM, "WM_DRAWITEM"),
+    (WM_MEASUREITEM, "WM_MEASUREITEM"),
+    (WM_SETFONT, "WM_SETFONT"),
+    (WM_GETFONT, "WM_GETFONT"),
+    (WM_GETOBJECT, "WM_GETOBJECT"),
+    (WM_WINDOWPOSCHANGING, "WM_WINDOWPOSCHANGING"),
+    (WM_WINDOWPOSCHANGED, "WM_WINDOWPOSCHANGED"),
+    (WM_COPYDATA, "WM_COPYDATA"),
+    (WM_NOTIFY, "WM_NOTIFY"),
+    (WM_CONTEXTMENU, "WM_CONTEXTMENU"),
+    (WM_HELP, "WM_HELP"),
+    (WM_DISPLAYCHANGE, "WM_DISPLAYCHANGE"),
+    (WM_GETICON, "WM_GETICON"),
+    (WM_SETICON, "WM_SETICON"),
+    (WM_NCCREATE, "WM_NCCREATE"),
+    (WM_NCDESTROY, "WM_NCDESTROY"),
+    (WM_NCCALCSIZE, "WM_NCCALCSIZE"),
+    (WM_NCHITTEST, "WM_NCHITTEST"),
+    (WM_NCPAINT, "WM_NCPAINT"),
+    (WM_NCACTIVATE, "WM_NCACTIVATE"),
+    (WM_SYNCPAINT, "WM_SYNCPAINT"),
+    (WM_NCMOUSEMOVE, "WM_NCMOUSEMOVE"),
+    (WM_NCLBUTTONDOWN, "WM_NCLBUTTONDOWN"),
+    (WM_KEYDOWN, "WM_KEYDOWN"),
+    (WM_KEYUP, "WM_KEYUP"),
+    (WM_CHAR, "WM_CHAR"),
+    (WM_DEADCHAR, "WM_DEADCHAR"),
+    (WM_SYSKEYDOWN, "WM_SYSKEYDOWN"),
+    (WM_SYSKEYUP, "WM_SYSKEYUP"),
+    (WM_SYSCHAR, "WM_SYSCHAR"),
+    (WM_UNICHAR, "WM_UNICHAR"),
+    (WM_INITDIALOG, "WM_INITDIALOG"),
+    (WM_COMMAND, "WM_COMMAND"),
+    (WM_SYSCOMMAND, "WM_SYSCOMMAND"),
+    (WM_TIMER, "WM_TIMER"),
+    (WM_HSCROLL, "WM_HSCROLL"),
+    (WM_VSCROLL, "WM_VSCROLL"),
+    (WM_INITMENU, "WM_INITMENU"),
+    (WM_INITMENUPOPUP, "WM_INITMENUPOPUP"),
+    (WM_MENUSELECT, "WM_MENUSELECT"),
+    (WM_ENTERIDLE, "WM_ENTERIDLE"),
+    (WM_CTLCOLOREDIT, "WM_CTLCOLOREDIT"),
+    (WM_CTLCOLORBTN, "WM_CTLCOLORBTN"),
+    (WM_CTLCOLORDLG, "WM_CTLCOLORDLG"),
+    (WM_CTLCOLORSTATIC, "WM_CTLCOLORSTATIC"),
+    (WM_MOUSEMOVE, "WM_MOUSEMOVE"),
+    (WM_LBUTTONDOWN, "WM_LBUTTONDOWN"),
+    (WM_LBUTTONUP, "WM_LBUTTONUP"),
+    (WM_LBUTTONDBLCLK, "WM_LBUTTONDBLCLK"),
+    (WM_RBUTTONDOWN, "WM_RBUTTONDOWN"),
+    (WM_RBUTTONUP, "WM_RBUTTONUP"),
+    (WM_RBUTTONDBLCLK, "WM_RBUTTONDBLCLK"),
+    (WM_MBUTTONDOWN, "WM_MBUTTONDOWN"),
+    (WM_MBUTTONUP, "WM_MBUTTONUP"),
+    (WM_MBUTTONDBLCLK, "WM_MBUTTONDBLCLK"),
+    (WM_MOUSEWHEEL, "WM_MOUSEWHEEL"),
+    (WM_XBUTTONDOWN, "WM_XBUTTONDOWN"),
+    (WM_XBUTTONUP, "WM_XBUTTONUP"),
+    (WM_XBUTTONDBLCLK, "WM_XBUTTONDBLCLK"),
+    (WM_MOUSEHWHEEL, "WM_MOUSEHWHEEL"),
+    (WM_PARENTNOTIFY, "WM_PARENTNOTIFY"),
+    (WM_ENTERMENULOOP, "WM_ENTERMENULOOP"),
+    (WM_EXITMENULOOP, "WM_EXITMENULOOP"),
+    (WM_SIZING, "WM_SIZING"),
+    (WM_CAPTURECHANGED, "WM_CAPTURECHANGED"),
+    (WM_MOVING, "WM_MOVING"),
+    (WM_ENTERSIZEMOVE, "WM_ENTERSIZEMOVE"),
+    (WM_EXITSIZEMOVE, "WM_EXITSIZEMOVE"),
+    (WM_DROPFILES, "WM_DROPFILES"),
+    (WM_NCMOUSEHOVER, "WM_NCMOUSEHOVER"),
+    // `WM_MOUSEHOVER` and `WM_MOUSELEAVE` are not defined in the `WindowsAndMessaging` module.
+    (WM_NCMOUSEHOVER + 1, "WM_MOUSEHOVER"),
+    (WM_NCMOUSELEAVE, "WM_NCMOUSELEAVE"),
+    (WM_NCMOUSELEAVE + 1, "WM_MOUSELEAVE"),
+    (WM_DPICHANGED, "WM_DPICHANGED"),
+    (WM_THEMECHANGED, "WM_THEMECHANGED"),
+    (WM_PRINT, "WM_PRINT"),
+    (WM_PRINTCLIENT, "WM_PRINTCLIENT"),
+];
+
+/// Returns the name of `message` if it is a common system message, like `"WM_PAINT"`. Messages from `WM_USER` on are
+/// private to window classes or applications, so their names can not be decoded.
+pub const fn message_name(message: u32) -> Option<&'static str> {
+    let mut i = 0;
+
+    while i < MESSAGE_NAMES.len() {
+        let (value, name) = MESSAGE_NAMES[i];
+
+        if value == message {
+            return Some(name);
+        }
+
+        i += 1;
+    }
+
+    None
+}
+
+/// A message reported by a [`TracingHandler`], once before it is passed to the wrapped handler, and once after with the
+/// result.
+///
+/// The [`Display`] implementation formats the message on a single line, using [`message_name`] for known messages, like
+/// `WM_SIZE (0x0005) w_param: 0x0 l_param: 0x1e0280 -> 0x0`.
+#[derive(Clone, Copy, Debug)]
+pub struct TracedMessage {
+    /// The window message.
+    pub message: u32,
+    /// The `WPARAM` argument.
+    pub w_param: WPARAM,
+    /// The `LPARAM` argument.
+    pub l_param: LPARAM,
+    /// The value returned by the wrapped handler, or [`None`] if the message has not been handled yet.
+    pub result: Option<LRESULT>,
+}
+
+impl TracedMessage {
+    /// Returns the name of the message, see [`message_name`].
+    pub const fn name(&self) -> Option<&'static str> {
+        message_name(self.message)
+    }
+}
+
+impl Display for TracedMessage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            None if self.message >= WM_USER => write!(f, "WM_USER + {:#x}", self.message - WM_USER)?,
+            None => f.write_str("unknown")?,
+            Some(name) => f.write_str(name)?,
+        }
+
+        write!(
+            f,
+            " ({:#06x}) w_param: {:#x} l_param: {:#x}",
+            self.message, self.w_param.0, self.l_param.0,
+        )?;
+
+        if let Some(result) = self.result {
+            write!(f, " -> {:#x}", result.0)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A [`WindowHandler`] that reports every message to a tracer before and after passing it to the wrapped handler, see
+/// [`TracedMessage`]. Tracing can be turned off with [`TracingHandler::set_enabled`], which only leaves the cost of
+/// checking a flag.
+///
+/// The tracer decides where the messages go. For example, with the `std` feature, it may print them with
+/// `std::eprintln!`, or forward them to a logging framework.
+pub struct TracingHandler<H, F> {
+    handler: H,
+    tracer: F,
+    enabled: AtomicBool,
+}
+
+impl<H, F> TracingHandler<H, F>
+where
+    H: WindowHandler,
+    F: Fn(&TracedMessage),
+{
+    /// Creates a new [`TracingHandler`] object that wraps `handler`, and reports messages to `tracer`. Tracing is
+    /// initially enabled.
+    pub fn new(handler: H, tracer: F) -> Self {
+        Self {
+            handler,
+            tracer,
+            enabled: AtomicBool::new(true),
+        }
+    }
+}
+
+impl<H, F> TracingHandler<H, F> {
+    /// Returns the wrapped handler.
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    /// Returns whether messages are reported to the tracer.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Turns reporting messages to the tracer on or off. Messages are always passed to the wrapped handler.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+}
+
+impl<H, F> WindowHandler for TracingHandler<H, F>
+where
+    H: WindowHandler,
+    F: Fn(&TracedMessage),
+{
+    fn handle(&self, message: u32, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+        if !self.is_enabled() {
+            return self.handler.handle(message, w_param, l_param);
+        }
+
+        let mut traced = TracedMessage {
+            message,
+            w_param,
+            l_param,
+            result: None,
+        };
+
+        (self.tracer)(&traced);
+
+        let result = self.handler.handle(message, w_param, l_param);
+
+        traced.result = Some(result);
+
+        (self.tracer)(&traced);
+
+        result
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<H, F> ContextThunk<TracingHandler<H, F>>
+where
+    H: WindowHandler,
+    F: Fn(&TracedMessage),
+{
+    /// Creates a new [`ContextThunk`] object that dispatches messages to `handler`, and reports them to `tracer`, see
+    /// [`TracingHandler`].
+    #[track_caller]
+    pub fn for_traced_handler(handler: H, tracer: F) -> crate::sys::core::Result<Self> {
+        Self::for_handler(TracingHandler::new(handler, tracer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{message_name, TracingHandler};
+    use crate::sys::Win32::Foundation::{LPARAM, LRESULT, WPARAM};
+    use crate::sys::Win32::UI::WindowsAndMessaging::{WM_MOUSEMOVE, WM_PAINT, WM_SETTINGCHANGE, WM_USER};
+    use crate::WindowHandler;
+    use core::cell::Cell;
+
+    #[test]
+    fn test_message_name() {
+        assert_eq!(message_name(WM_PAINT), Some("WM_PAINT"));
+        assert_eq!(message_name(WM_MOUSEMOVE), Some("WM_MOUSEMOVE"));
+        assert_eq!(message_name(WM_SETTINGCHANGE), Some("WM_SETTINGCHANGE"));
+        assert_eq!(message_name(WM_USER), None);
+    }
+
+    #[test]
+    fn test_message_names_unique() {
+        for (i, &(message, _)) in super::MESSAGE_NAMES.iter().enumerate() {
+            assert!(super::MESSAGE_NAMES[..i].iter().all(|&(other, _)| other != message));
+        }
+    }
+
+    #[test]
+    fn test_tracing_handler() {
+        let before = Cell::new(0);
+        let after = Cell::new(0);
+
+        let handler = TracingHandler::new(
+            |message: u32, w_param: WPARAM, _: LPARAM| LRESULT(message as isize + w_param.0 as isize),
+            |traced: &super::TracedMessage| match traced.result {
+                None => before.set(before.get() + 1),
+                Some(result) => {
+                    assert_eq!(result.0, traced.message as isize + traced.w_param.0 as isize);
+
+                    after.set(after.get() + 1);
+                }
+            },
+        );
+
+        assert_eq!(handler.handle(WM_PAINT, WPARAM(2), LPARAM(0)).0, WM_PAINT as isize + 2);
+        assert_eq!((before.get(), after.get()), (1, 1));
+
+        handler.set_enabled(false);
+
+        assert!(!handler.is_enabled());
+        assert_eq!(handler.handle(WM_USER, WPARAM(3), LPARAM(0)).0, WM_USER as isize + 3);
+        assert_eq!((before.get(), after.get()), (1, 1));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_traced_message_display() {
+        use super::TracedMessage;
+        use crate::sys::Win32::UI::WindowsAndMessaging::WM_SIZE;
+        use alloc::string::ToString;
+
+        let traced = TracedMessage {
+            message: WM_SIZE,
+            w_param: WPARAM(0),
+            l_param: LPARAM(0x1E_0280),
+            result: Some(LRESULT(0)),
+        };
+
+        assert_eq!(
+            traced.to_string(),
+            "WM_SIZE (0x0005) w_param: 0x0 l_param: 0x1e0280 -> 0x0",
+        );
+
+        let traced = TracedMessage {
+            message: WM_USER + 2,
+            result: None,
+            ..traced
+        };
+
+        assert_eq!(
+            traced.to_string(),
+            "WM_USER + 0x2 (0x0402) w_param: 0x0 l_param: 0x1e0280"
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_context_thunk_for_traced_handler() {
+        use crate::sys::Win32::Foundation::HWND;
+        use crate::ContextThunk;
+
+        let count = Cell::new(0);
+
+        let thunk = ContextThunk::for_traced_handler(
+            |message: u32, _: WPARAM, _: LPARAM| LRESULT(message as _),
+            |_: &super::TracedMessage| count.set(count.get() + 1),
+        )
+        .unwrap();
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), WM_PAINT, WPARAM(0), LPARAM(0)) }.0,
+            WM_PAINT as isize,
+        );
+
+        assert_eq!(count.get(), 2);
+    }
+}
@@ -0,0 +1,95 @@
+//! ATL thunks with uninitialized associated data.
+
+use crate::sys::Win32::System::Memory::AtlThunkData_t;
+use crate::{AtlThunk, FirstParameter, WindowProcedure};
+use core::ptr::NonNull;
+
+/// An allocated ATL thunk whose associated data has not been set. Unlike an [`AtlThunk`] object created by
+/// [`AtlThunk::try_new`], it provides no window procedure, so it is impossible to use the ATL thunk before the
+/// associated data is set.
+pub struct UninitAtlThunk {
+    thunk: AtlThunk,
+}
+
+impl UninitAtlThunk {
+    /// Allocates a new ATL thunk without setting its associated data, see [`AtlThunk::try_new`]. The associated data is
+    /// only initialized once, by [`UninitAtlThunk::init`] or by other means before [`UninitAtlThunk::assume_init`].
+    #[track_caller]
+    pub fn try_new() -> crate::sys::core::Result<Self> {
+        AtlThunk::allocate().map(Self::from_thunk)
+    }
+
+    pub(crate) fn from_thunk(thunk: AtlThunk) -> Self {
+        // Tracked associated data of a reused ATL thunk is stale.
+        #[cfg(feature = "metadata")]
+        let thunk = {
+            let mut thunk = thunk;
+
+            thunk.data = None;
+
+            thunk
+        };
+
+        Self { thunk }
+    }
+
+    /// Returns the raw ATL thunk pointer, whose ownership is still kept by the [`UninitAtlThunk`] object. It can be
+    /// used to initialize the ATL thunk with foreign code before calling [`UninitAtlThunk::assume_init`].
+    pub fn as_raw(&self) -> NonNull<AtlThunkData_t> {
+        self.thunk.raw_thunk_ptr
+    }
+
+    /// Sets the associated data, and returns the initialized [`AtlThunk`] object. See [`AtlThunk::set_data`].
+    pub fn init(mut self, window_procedure: WindowProcedure, first_parameter: impl Into<FirstParameter>) -> AtlThunk {
+        self.thunk.set_data(window_procedure, first_parameter);
+
+        self.thunk
+    }
+
+    /// Returns the [`AtlThunk`] object without setting the associated data. This is the escape hatch for ATL thunks
+    /// initialized by other means, like a foreign
+    /// [`AtlThunk_InitData`](<https://learn.microsoft.com/en-us/windows/win32/api/atlthunk/nf-atlthunk-atlthunk_initdata>)
+    /// call on the pointer returned by [`UninitAtlThunk::as_raw`].
+    ///
+    /// # Safety
+    ///
+    /// The associated data of the ATL thunk must have been initialized.
+    pub unsafe fn assume_init(self) -> AtlThunk {
+        self.thunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UninitAtlThunk;
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use core::ffi::c_void;
+
+    unsafe extern "system" fn callback(first_parameter: HWND, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+        LRESULT(first_parameter.0 as isize * message as isize)
+    }
+
+    #[test]
+    fn test_uninit_thunk_init() {
+        let thunk = UninitAtlThunk::try_new().unwrap().init(callback, HWND(2 as _));
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 3, WPARAM(0), LPARAM(0)) }.0,
+            6,
+        );
+    }
+
+    #[test]
+    fn test_uninit_thunk_assume_init() {
+        let thunk = UninitAtlThunk::try_new().unwrap();
+
+        unsafe { crate::AtlThunk_InitData(thunk.as_raw().as_ptr(), callback as *mut c_void, 5) };
+
+        let thunk = unsafe { thunk.assume_init() };
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 7, WPARAM(0), LPARAM(0)) }.0,
+            35,
+        );
+    }
+}
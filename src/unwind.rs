@@ -0,0 +1,359 @@
+//! Window procedures that catch panics of Rust handler objects.
+
+use crate::context::{self, ContextThunk};
+use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use crate::WindowHandler;
+use std::any::Any;
+use std::boxed::Box;
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
+use std::string::String;
+
+/// Decides what happens after a handler panics, see [`ContextThunk::for_handler_with_panic_policy`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// Aborts the process, which is also what happens to handlers that are not guarded by a panic policy.
+    #[default]
+    Abort,
+    /// Returns the specified value as the result of the message that caused the panic. Following messages are still
+    /// dispatched to the handler.
+    CatchReturn(LRESULT),
+    /// Poisons the thunk, then the current message and all following messages are passed to
+    /// [`DefWindowProcW`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-defwindowprocw)
+    /// without calling the handler, until [`ContextThunk::clear_poison`] is called. Default processing needs the
+    /// window bound with [`ContextThunk::bind_window`].
+    Poison,
+}
+
+/// Information about a panic caught from a handler, see [`ContextThunk::for_handler_with_panic_hook`].
+#[derive(Clone, Copy, Debug)]
+pub struct PanicReport<'a> {
+    message: u32,
+    payload: &'a (dyn Any + Send),
+}
+
+impl<'a> PanicReport<'a> {
+    /// Returns the window message that was being handled when the handler panicked.
+    pub fn message(&self) -> u32 {
+        self.message
+    }
+
+    /// Returns the panic payload.
+    pub fn payload(&self) -> &'a (dyn Any + Send) {
+        self.payload
+    }
+
+    /// Returns the panic message if the payload is a `&str` or a `String`, which is the case for panics raised by
+    /// `panic!` and similar macros.
+    pub fn payload_str(&self) -> Option<&'a str> {
+        self.payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| self.payload.downcast_ref::<String>().map(String::as_str))
+    }
+}
+
+pub(crate) type PanicHook = Box<dyn Fn(&PanicReport<'_>) + Send + Sync>;
+
+/// Window procedure that forwards messages to the handler pointed by the associated data, and handles panics of the
+/// handler according to the panic policy stored in the context header.
+unsafe extern "system" fn guarded_window_procedure<H>(
+    handler: HWND,
+    message: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> LRESULT
+where
+    H: WindowHandler,
+{
+    let handler = handler.0.cast::<H>().cast_const();
+    let header = unsafe { context::context_header(handler) };
+
+    if header.is_poisoned() {
+        return header.default_process(message, w_param, l_param);
+    }
+
+    header.dispatch(|| {
+        match panic::catch_unwind(AssertUnwindSafe(|| {
+            unsafe { &*handler }.handle(message, w_param, l_param)
+        })) {
+            Ok(result) => result,
+            Err(payload) => {
+                if let Some(panic_hook) = &header.panic_hook {
+                    let report = PanicReport {
+                        message,
+                        payload: &*payload,
+                    };
+
+                    // A panicking hook must not unwind into the operating system either.
+                    _ = panic::catch_unwind(AssertUnwindSafe(|| panic_hook(&report)));
+                }
+
+                match header.panic_policy {
+                    PanicPolicy::Abort => process::abort(),
+                    PanicPolicy::CatchReturn(result) => result,
+                    PanicPolicy::Poison => {
+                        header.poison();
+
+                        header.default_process(message, w_param, l_param)
+                    }
+                }
+            }
+        }
+    })
+}
+
+impl<H> ContextThunk<H>
+where
+    H: WindowHandler,
+{
+    /// Creates a new [`ContextThunk`] object that owns `handler`, and dispatches messages to it. If the handler panics,
+    /// the panic is caught and handled according to `panic_policy`.
+    ///
+    /// # Unwind safety
+    ///
+    /// `handler` is not required to be [`RefUnwindSafe`](std::panic::RefUnwindSafe): handlers are called through shared
+    /// references, so almost all of them hold states with interior mutability, like [`Cell`](core::cell::Cell), which
+    /// is not [`RefUnwindSafe`](std::panic::RefUnwindSafe). Requiring it would force every handler into
+    /// [`AssertUnwindSafe`] anyway. Instead, the handler is wrapped with [`AssertUnwindSafe`] internally, which is
+    /// acceptable because unwind safety is not a memory safety property, and states that a handler leaves behind when
+    /// it panics can only be observed in the following ways:
+    ///
+    /// - With [`PanicPolicy::Abort`], nothing can observe them.
+    /// - With [`PanicPolicy::Poison`], the handler is not called again until [`ContextThunk::clear_poison`] is called,
+    ///   which is where the caller takes the responsibility of checking the states.
+    /// - With [`PanicPolicy::CatchReturn`], the handler is called again with whatever states the panic left, which is
+    ///   what choosing this policy opts into.
+    #[track_caller]
+    pub fn for_handler_with_panic_policy(handler: H, panic_policy: PanicPolicy) -> crate::sys::core::Result<Self> {
+        let mut thunk = unsafe { Self::try_new_with_procedure(handler, guarded_window_procedure::<H>) }?;
+
+        thunk.header_mut().panic_policy = panic_policy;
+
+        Ok(thunk)
+    }
+
+    /// Same as [`ContextThunk::for_handler_with_panic_policy`], but also calls `panic_hook` with information about the
+    /// caught panic before the panic policy is applied. Panics of `panic_hook` itself are caught and ignored.
+    #[track_caller]
+    pub fn for_handler_with_panic_hook(
+        handler: H,
+        panic_policy: PanicPolicy,
+        panic_hook: impl Fn(&PanicReport<'_>) + Send + Sync + 'static,
+    ) -> crate::sys::core::Result<Self> {
+        let mut thunk = Self::for_handler_with_panic_policy(handler, panic_policy)?;
+
+        thunk.header_mut().panic_hook = Some(Box::new(panic_hook));
+
+        Ok(thunk)
+    }
+
+    /// Same as [`ContextThunk::for_handler_with_panic_policy`] with [`PanicPolicy::Poison`].
+    #[track_caller]
+    pub fn for_handler_poison_on_panic(handler: H) -> crate::sys::core::Result<Self> {
+        Self::for_handler_with_panic_policy(handler, PanicPolicy::Poison)
+    }
+}
+
+impl<T> ContextThunk<T> {
+    /// Returns the panic policy of the thunk, which is [`PanicPolicy::Abort`] for thunks that are not created with
+    /// [`ContextThunk::for_handler_with_panic_policy`].
+    pub fn panic_policy(&self) -> PanicPolicy {
+        self.header().panic_policy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use crate::sys::Win32::UI::WindowsAndMessaging::WM_USER;
+    use crate::test_support::DEFAULT_RESULT;
+    use crate::{ContextThunk, PanicPolicy};
+    use core::cell::Cell;
+    use std::sync::Mutex;
+    use std::vec::Vec;
+
+    #[test]
+    fn test_context_thunk_poison_on_panic() {
+        let count = Cell::new(0);
+
+        let thunk = ContextThunk::for_handler_poison_on_panic(|message: u32, _: WPARAM, _: LPARAM| {
+            count.set(count.get() + 1);
+
+            assert_ne!(message, WM_USER, "unexpected message");
+
+            LRESULT(message as isize)
+        })
+        .unwrap();
+
+        let window_procedure = thunk.as_window_procedure();
+
+        assert_eq!(thunk.panic_policy(), PanicPolicy::Poison);
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_USER + 1, WPARAM(0), LPARAM(0)) }.0,
+            (WM_USER + 1) as isize,
+        );
+
+        assert!(!thunk.is_poisoned());
+
+        // Messages are passed to the default window procedure after poisoned.
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_USER, WPARAM(0), LPARAM(0)) }.0,
+            DEFAULT_RESULT,
+        );
+
+        assert!(thunk.is_poisoned());
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_USER + 1, WPARAM(0), LPARAM(0)) }.0,
+            0,
+        );
+
+        assert_eq!(count.get(), 2);
+
+        thunk.clear_poison();
+
+        assert!(!thunk.is_poisoned());
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_USER + 1, WPARAM(0), LPARAM(0)) }.0,
+            (WM_USER + 1) as isize,
+        );
+
+        assert_eq!(count.get(), 3);
+    }
+
+    #[test]
+    fn test_context_thunk_catch_return_on_panic() {
+        let thunk = ContextThunk::for_handler_with_panic_policy(
+            |message: u32, _: WPARAM, _: LPARAM| {
+                assert_ne!(message, WM_USER, "unexpected message");
+
+                LRESULT(message as isize)
+            },
+            PanicPolicy::CatchReturn(LRESULT(-1)),
+        )
+        .unwrap();
+
+        let window_procedure = thunk.as_window_procedure();
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_USER, WPARAM(0), LPARAM(0)) }.0,
+            -1,
+        );
+
+        assert!(!thunk.is_poisoned());
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_USER + 1, WPARAM(0), LPARAM(0)) }.0,
+            (WM_USER + 1) as isize,
+        );
+
+        assert_eq!(
+            ContextThunk::for_handler(|_: u32, _: WPARAM, _: LPARAM| LRESULT(0))
+                .unwrap()
+                .panic_policy(),
+            PanicPolicy::Abort
+        );
+    }
+
+    #[test]
+    fn test_context_thunk_panic_with_cell() {
+        let steps = Cell::new(0);
+
+        // `&Cell<u32>` is not `UnwindSafe`, but the handler is still accepted.
+        let thunk = ContextThunk::for_handler_with_panic_policy(
+            |message: u32, _: WPARAM, _: LPARAM| {
+                steps.set(steps.get() + 1);
+
+                assert_ne!(message, WM_USER, "unexpected message");
+
+                steps.set(steps.get() + 1);
+
+                LRESULT(0)
+            },
+            PanicPolicy::CatchReturn(LRESULT(-1)),
+        )
+        .unwrap();
+
+        let window_procedure = thunk.as_window_procedure();
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_USER, WPARAM(0), LPARAM(0)) }.0,
+            -1,
+        );
+
+        // Updates made before the panic are kept.
+        assert_eq!(steps.get(), 1);
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_USER + 1, WPARAM(0), LPARAM(0)) }.0,
+            0,
+        );
+
+        assert_eq!(steps.get(), 3);
+    }
+
+    #[test]
+    fn test_context_thunk_panic_hook() {
+        static REPORTS: Mutex<Vec<(u32, Option<std::string::String>)>> = Mutex::new(Vec::new());
+
+        let thunk = ContextThunk::for_handler_with_panic_hook(
+            |message: u32, _: WPARAM, _: LPARAM| {
+                if message == WM_USER {
+                    panic!("static message");
+                }
+
+                if message == WM_USER + 1 {
+                    panic!("formatted message {}", message - WM_USER);
+                }
+
+                std::panic::panic_any(message)
+            },
+            PanicPolicy::CatchReturn(LRESULT(-1)),
+            |report| {
+                REPORTS
+                    .lock()
+                    .unwrap()
+                    .push((report.message(), report.payload_str().map(Into::into)));
+
+                panic!("panicking hook");
+            },
+        )
+        .unwrap();
+
+        let window_procedure = thunk.as_window_procedure();
+
+        for message in WM_USER..WM_USER + 3 {
+            assert_eq!(
+                unsafe { window_procedure(HWND::default(), message, WPARAM(0), LPARAM(0)) }.0,
+                -1,
+            );
+        }
+
+        assert_eq!(
+            *REPORTS.lock().unwrap(),
+            [
+                (WM_USER, Some("static message".into())),
+                (WM_USER + 1, Some("formatted message 1".into())),
+                (WM_USER + 2, None),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_guarded_handler_while_mutably_borrowed() {
+        crate::test_support::assert_aborts("unwind::tests::test_guarded_handler_while_mutably_borrowed", || {
+            use crate::sys::Win32::UI::WindowsAndMessaging::WM_SETTEXT;
+
+            let mut thunk =
+                ContextThunk::for_handler_poison_on_panic(|_: u32, _: WPARAM, _: LPARAM| LRESULT(1)).unwrap();
+            let window_procedure = thunk.as_window_procedure();
+            let _context = thunk.get_mut();
+
+            // The borrow check is not a panic of the handler, so it is not caught by the panic policy.
+            unsafe { window_procedure(HWND::default(), WM_SETTEXT, WPARAM(0), LPARAM(0)) };
+        });
+    }
+}
@@ -0,0 +1,251 @@
+//! Registration of window classes whose window procedure is an ATL thunk.
+
+use crate::sys::core::PCWSTR;
+use crate::sys::Win32::Foundation::HINSTANCE;
+use crate::sys::Win32::Graphics::Gdi::{COLOR_WINDOW, HBRUSH};
+use crate::sys::Win32::System::LibraryLoader::GetModuleHandleW;
+use crate::sys::Win32::UI::WindowsAndMessaging::{
+    LoadCursorW, RegisterClassExW, UnregisterClassW, HCURSOR, HICON, IDC_ARROW, WNDCLASSEXW, WNDCLASS_STYLES, WNDPROC,
+};
+use crate::AtlThunk;
+use core::marker::PhantomData;
+use core::mem;
+
+/// Parameters of a window class registered by [`AtlThunk::register_class_ex`] or [`register_creation_class`]. Fields
+/// that are [`None`] are filled with defaults.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WindowClassOptions {
+    /// The class styles.
+    pub style: WNDCLASS_STYLES,
+    /// The module that registers the class, defaults to the module that creates the current process.
+    pub instance: Option<HINSTANCE>,
+    /// The class cursor, defaults to the standard arrow cursor.
+    pub cursor: Option<HCURSOR>,
+    /// The class background brush, defaults to the `COLOR_WINDOW` system color.
+    pub background: Option<HBRUSH>,
+    /// The class icon, defaults to the system provided icon.
+    pub icon: Option<HICON>,
+}
+
+/// A window class registered by [`AtlThunk::register_class_ex`] or [`register_creation_class`]. The class is
+/// unregistered on drop, which fails silently if windows of the class still exist.
+#[derive(Debug)]
+pub struct RegisteredClass<'a> {
+    atom: u16,
+    instance: HINSTANCE,
+    thunk: PhantomData<&'a AtlThunk>,
+}
+
+impl RegisteredClass<'_> {
+    /// Returns the class atom, which can be used in place of the class name.
+    pub fn atom(&self) -> u16 {
+        self.atom
+    }
+
+    /// Returns the class atom as a [`PCWSTR`] value, which can be passed to functions like
+    /// [`CreateWindowExW`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-createwindowexw).
+    pub fn as_class_name(&self) -> PCWSTR {
+        PCWSTR(usize::from(self.atom) as _)
+    }
+
+    /// Returns the module that registered the class.
+    pub fn instance(&self) -> HINSTANCE {
+        self.instance
+    }
+}
+
+impl Drop for RegisteredClass<'_> {
+    fn drop(&mut self) {
+        _ = unsafe { UnregisterClassW(self.as_class_name(), self.instance) };
+    }
+}
+
+impl AtlThunk {
+    /// Registers a window class named `class_name` with the wrapped window procedure of the ATL thunk. For more
+    /// information, see document for
+    /// [`RegisterClassExW`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-registerclassexw).
+    ///
+    /// The returned [`RegisteredClass`] object borrows the [`AtlThunk`] object, so the class can not outlive the
+    /// window procedure. All windows of the class share the associated data of the ATL thunk.
+    ///
+    /// # Safety
+    ///
+    /// `class_name` must point to a valid null terminated string. The associated data of the ATL thunk must stay valid
+    /// for as long as windows of the class may receive messages.
+    pub unsafe fn register_class_ex(
+        &self,
+        class_name: PCWSTR,
+        options: &WindowClassOptions,
+    ) -> crate::sys::core::Result<RegisteredClass<'_>> {
+        unsafe { register_class(class_name, options, self.as_wndproc()) }
+    }
+}
+
+/// Registers a window class named `class_name` with [`creation_window_procedure`](crate::creation_window_procedure)
+/// as its window procedure, for creating windows with
+/// [`WindowHandle::create`](crate::WindowHandle::create). Unlike [`AtlThunk::register_class_ex`], the class does not
+/// borrow an ATL thunk, because each window is bound to its own thunk on creation.
+///
+/// # Safety
+///
+/// `class_name` must point to a valid null terminated string. Windows of the class must be created with a creation
+/// parameter that is either null or returned by [`ContextThunk::creation_parameter`](crate::ContextThunk), like
+/// windows created by [`WindowHandle::create`](crate::WindowHandle::create).
+#[cfg(feature = "alloc")]
+pub unsafe fn register_creation_class(
+    class_name: PCWSTR,
+    options: &WindowClassOptions,
+) -> crate::sys::core::Result<RegisteredClass<'static>> {
+    unsafe { register_class(class_name, options, Some(crate::creation_window_procedure)) }
+}
+
+/// Registers a window class with `window_procedure`, see [`AtlThunk::register_class_ex`].
+unsafe fn register_class<'a>(
+    class_name: PCWSTR,
+    options: &WindowClassOptions,
+    window_procedure: WNDPROC,
+) -> crate::sys::core::Result<RegisteredClass<'a>> {
+    let instance = match options.instance {
+        None => unsafe { GetModuleHandleW(None) }?.into(),
+        Some(instance) => instance,
+    };
+
+    let cursor = match options.cursor {
+        None => unsafe { LoadCursorW(None, IDC_ARROW) }?,
+        Some(cursor) => cursor,
+    };
+
+    let window_class = WNDCLASSEXW {
+        cbSize: mem::size_of::<WNDCLASSEXW>() as _,
+        style: options.style,
+        lpfnWndProc: window_procedure,
+        hInstance: instance,
+        hIcon: options.icon.unwrap_or_default(),
+        hCursor: cursor,
+        // System color brushes are specified as the color index plus one.
+        hbrBackground: options.background.unwrap_or(HBRUSH((COLOR_WINDOW.0 + 1) as _)),
+        lpszClassName: class_name,
+        ..WNDCLASSEXW::default()
+    };
+
+    match unsafe { RegisterClassExW(&window_class) } {
+        0 => Err(crate::sys::core::Error::from_win32()),
+        atom => Ok(RegisteredClass {
+            atom,
+            instance,
+            thunk: PhantomData,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WindowClassOptions;
+    use crate::sys::core::w;
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use crate::sys::Win32::UI::WindowsAndMessaging::{DefWindowProcW, CS_HREDRAW};
+    use crate::AtlThunk;
+
+    #[test]
+    fn test_thunk_register_class_ex() {
+        unsafe extern "system" fn callback(window: HWND, message: u32, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+            unsafe { DefWindowProcW(window, message, w_param, l_param) }
+        }
+
+        let thunk = AtlThunk::try_new_with(callback, HWND::default()).unwrap();
+
+        let options = WindowClassOptions {
+            style: CS_HREDRAW,
+            ..WindowClassOptions::default()
+        };
+
+        let class = unsafe { thunk.register_class_ex(w!("atl-thunk-test-register-class-ex"), &options) }.unwrap();
+
+        assert_ne!(class.atom(), 0);
+
+        // Registering the same class again fails.
+        assert!(unsafe { thunk.register_class_ex(w!("atl-thunk-test-register-class-ex"), &options) }.is_err());
+
+        drop(class);
+
+        // The class has been unregistered on drop.
+        drop(unsafe { thunk.register_class_ex(w!("atl-thunk-test-register-class-ex"), &options) }.unwrap());
+    }
+
+    #[test]
+    fn test_thunk_register_class_ex_with_reexports() {
+        use crate::windows::{HBRUSH, HCURSOR, HICON, HINSTANCE, PCWSTR, WNDCLASS_STYLES};
+
+        unsafe extern "system" fn callback(window: HWND, message: u32, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+            unsafe { DefWindowProcW(window, message, w_param, l_param) }
+        }
+
+        let class_name: PCWSTR = w!("atl-thunk-test-reexports");
+        let thunk = AtlThunk::try_new_with(callback, HWND::default()).unwrap();
+
+        let options = WindowClassOptions {
+            style: WNDCLASS_STYLES::default(),
+            instance: None::<HINSTANCE>,
+            cursor: None::<HCURSOR>,
+            background: None::<HBRUSH>,
+            icon: None::<HICON>,
+        };
+
+        let class = unsafe { thunk.register_class_ex(class_name, &options) }.unwrap();
+
+        assert_ne!(class.atom(), 0);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_register_creation_class() {
+        use crate::sys::Win32::UI::WindowsAndMessaging::{HWND_MESSAGE, WINDOW_STYLE};
+        use crate::{SetWindowHandle, WindowHandle, WindowHandler};
+        use core::cell::Cell;
+
+        #[derive(Default)]
+        struct Window {
+            window: Cell<HWND>,
+        }
+
+        impl SetWindowHandle for Window {
+            fn set_window_handle(&self, window: HWND) {
+                self.window.set(window);
+            }
+        }
+
+        impl WindowHandler for Window {
+            fn handle(&self, message: u32, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+                unsafe { DefWindowProcW(self.window.get(), message, w_param, l_param) }
+            }
+        }
+
+        let class_name = w!("atl-thunk-test-register-creation-class");
+        let class = unsafe { super::register_creation_class(class_name, &WindowClassOptions::default()) }.unwrap();
+
+        let windows = [1, 2].map(|_| {
+            unsafe {
+                WindowHandle::create(
+                    class.as_class_name(),
+                    class.instance(),
+                    w!("title"),
+                    WINDOW_STYLE::default(),
+                    HWND_MESSAGE,
+                    Window::default(),
+                )
+            }
+            .unwrap()
+        });
+
+        // Each window of the class is bound to its own context object.
+        for window in &windows {
+            assert_eq!(window.context().window.get(), window.window());
+        }
+
+        assert_ne!(windows[0].window(), windows[1].window());
+
+        // Windows are destroyed before the class is unregistered.
+        drop(windows);
+        drop(class);
+    }
+}
@@ -0,0 +1,168 @@
+//! Owners of window context objects and the ATL thunks that dispatch to them.
+
+use crate::context::{ContextRefMut, ContextThunk};
+use crate::{WindowHandler, WindowProcedure};
+use core::mem::ManuallyDrop;
+
+/// A Rust object backing a window, which owns both the context object and the ATL thunk that dispatches messages to
+/// it, so the window procedure can never outlive the context object.
+///
+/// The context object is heap allocated and never moves, so the pointer held by the ATL thunk stays valid for the whole
+/// lifetime of the [`WindowObject`] object. On drop, the ATL thunk is first reset to the default procedure with the
+/// bound window, then the context object is dropped, and finally the ATL thunk is freed. This way, messages sent while
+/// the context object is being dropped, like the ones caused by destroying the window in its [`Drop`]
+/// implementation, are processed by the default procedure instead of reaching a partially dropped context object.
+///
+/// If the [`WindowObject`] object is dropped by one of its own handlers, the outer dispatches still use the context
+/// object and its header, so neither the context object nor the ATL thunk is freed, and both are leaked instead.
+pub struct WindowObject<T> {
+    thunk: ManuallyDrop<ContextThunk<T>>,
+}
+
+impl<T> WindowObject<T>
+where
+    T: WindowHandler,
+{
+    /// Creates a new [`WindowObject`] object that owns `context`, and dispatches messages to it, see
+    /// [`ContextThunk::for_handler`].
+    #[track_caller]
+    pub fn new(context: T) -> crate::sys::core::Result<Self> {
+        ContextThunk::for_handler(context).map(|thunk| Self {
+            thunk: ManuallyDrop::new(thunk),
+        })
+    }
+}
+
+impl<T> WindowObject<T> {
+    /// Returns a window procedure that dispatches messages to the context object, see
+    /// [`ContextThunk::as_window_procedure`]. The returned function pointer is only valid while the [`WindowObject`]
+    /// object is alive.
+    #[inline(always)]
+    pub fn as_window_procedure(&self) -> WindowProcedure {
+        self.thunk.as_window_procedure()
+    }
+
+    /// Returns a reference to the context object.
+    pub fn context(&self) -> &T {
+        self.thunk.context()
+    }
+
+    /// Mutably borrows the context object, see [`ContextThunk::get_mut`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if a message dispatch is in progress.
+    pub fn context_mut(&mut self) -> ContextRefMut<'_, T> {
+        self.thunk.get_mut()
+    }
+
+    /// Returns the underlying [`ContextThunk`] object, for binding the window or changing other settings.
+    pub fn thunk(&self) -> &ContextThunk<T> {
+        &self.thunk
+    }
+}
+
+impl<T> Drop for WindowObject<T> {
+    fn drop(&mut self) {
+        if self.thunk.header().is_dispatching() {
+            return;
+        }
+
+        // 1. `take_context` resets the ATL thunk before the context object is moved out.
+        // 2. The context object is dropped after nothing can reach it.
+        // 3. The header and the ATL thunk are freed.
+        drop(self.thunk.take_context());
+
+        unsafe { ManuallyDrop::drop(&mut self.thunk) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WindowObject;
+    use crate::sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use crate::sys::Win32::UI::WindowsAndMessaging::WM_USER;
+    use crate::test_support::DEFAULT_RESULT;
+    use crate::{WindowHandler, WindowProcedure};
+    use alloc::rc::Rc;
+    use core::cell::Cell;
+
+    struct Window {
+        window_procedure: Cell<Option<WindowProcedure>>,
+        value: isize,
+        teardown_result: Rc<Cell<Option<isize>>>,
+    }
+
+    impl WindowHandler for Window {
+        fn handle(&self, _: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+            LRESULT(self.value)
+        }
+    }
+
+    impl Drop for Window {
+        fn drop(&mut self) {
+            if let Some(window_procedure) = self.window_procedure.get() {
+                // Like a message sent by destroying the window.
+                let result = unsafe { window_procedure(HWND::default(), WM_USER, WPARAM(0), LPARAM(0)) };
+
+                self.teardown_result.set(Some(result.0));
+            }
+        }
+    }
+
+    #[test]
+    fn test_window_object() {
+        let teardown_result = Rc::new(Cell::new(None));
+
+        let mut object = WindowObject::new(Window {
+            window_procedure: Cell::new(None),
+            value: 2,
+            teardown_result: Rc::clone(&teardown_result),
+        })
+        .unwrap();
+
+        object
+            .context()
+            .window_procedure
+            .set(Some(object.as_window_procedure()));
+
+        assert_eq!(
+            unsafe { object.as_window_procedure()(HWND::default(), WM_USER, WPARAM(0), LPARAM(0)) }.0,
+            2,
+        );
+
+        object.context_mut().value = 3;
+
+        assert_eq!(
+            unsafe { object.as_window_procedure()(HWND::default(), WM_USER, WPARAM(0), LPARAM(0)) }.0,
+            3,
+        );
+
+        drop(object);
+
+        // Messages received while the context object is dropped are passed to the default window procedure.
+        assert_eq!(teardown_result.get(), Some(DEFAULT_RESULT));
+    }
+
+    #[test]
+    fn test_window_object_drop_while_dispatching() {
+        let counter = Rc::new(());
+        let captured = Rc::clone(&counter);
+        let object =
+            WindowObject::new(move |_: u32, _: WPARAM, _: LPARAM| LRESULT(Rc::strong_count(&captured) as _)).unwrap();
+        let window_procedure = object.as_window_procedure();
+
+        // Window procedures abort on panics, so the dispatch is simulated without one.
+        let header = unsafe { &*core::ptr::from_ref(object.thunk().header()) };
+
+        header.dispatch(|| drop(object));
+
+        // The context object is leaked, and still handles messages.
+        assert_eq!(Rc::strong_count(&counter), 2);
+
+        assert_eq!(
+            unsafe { window_procedure(HWND::default(), WM_USER, WPARAM(0), LPARAM(0)) }.0,
+            2,
+        );
+    }
+}
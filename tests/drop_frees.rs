@@ -0,0 +1,90 @@
+//! Checks that dropping ATL thunks frees them, by repeating create and drop cycles and watching the committed memory
+//! of the process. This is an integration test so that no other tests allocate memory in the same process while it is
+//! measured.
+
+#![cfg(windows)]
+
+use atl_thunk::AtlThunk;
+use std::mem;
+use windows::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use windows::Win32::System::ProcessStatus::{GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS};
+use windows::Win32::System::Threading::GetCurrentProcess;
+
+const CYCLES: usize = 100_000;
+
+/// Committed memory may grow a little for reasons unrelated to ATL thunks. Leaking every ATL thunk commits at least
+/// `CYCLES` times the size of a code stub, which is well above this bound.
+const MAX_GROWTH: usize = 1024 * 1024;
+
+unsafe extern "system" fn callback(first_parameter: HWND, message: u32, _: WPARAM, _: LPARAM) -> LRESULT {
+    LRESULT(first_parameter.0 as isize + message as isize)
+}
+
+fn committed_memory() -> usize {
+    let mut counters = PROCESS_MEMORY_COUNTERS::default();
+
+    unsafe {
+        GetProcessMemoryInfo(
+            GetCurrentProcess(),
+            &mut counters,
+            mem::size_of::<PROCESS_MEMORY_COUNTERS>() as _,
+        )
+    }
+    .unwrap();
+
+    counters.PagefileUsage
+}
+
+fn assert_bounded(mut cycle: impl FnMut(usize)) {
+    // Let allocators reach a steady state before measuring.
+    (0..CYCLES / 10).for_each(&mut cycle);
+
+    #[cfg(feature = "stats")]
+    let live = atl_thunk::stats().live;
+
+    let before = committed_memory();
+
+    (0..CYCLES).for_each(&mut cycle);
+
+    let after = committed_memory();
+
+    assert!(
+        after.saturating_sub(before) < MAX_GROWTH,
+        "committed memory grows from {before} to {after} bytes",
+    );
+
+    #[cfg(feature = "stats")]
+    assert_eq!(atl_thunk::stats().live, live);
+}
+
+// Both variants are run by a single test, so they are not measured concurrently.
+#[test]
+fn test_drop_frees_thunks() {
+    assert_bounded(|i| {
+        let thunk = AtlThunk::try_new_with(callback, HWND(i as _)).unwrap();
+
+        assert_eq!(
+            unsafe { thunk.as_window_procedure()(HWND::default(), 1, WPARAM(0), LPARAM(0)) }.0,
+            i as isize + 1,
+        );
+    });
+
+    // Updating the associated data and cloning go through the `metadata` tracking paths.
+    #[cfg(feature = "metadata")]
+    assert_bounded(|i| {
+        let mut thunk = AtlThunk::try_new_with(callback, HWND::default()).unwrap();
+
+        thunk.set_data(callback, HWND(i as _));
+
+        let clone = thunk.clone_with(HWND(i as _)).unwrap();
+
+        thunk.set_first_parameter(HWND(1 as _));
+
+        assert_eq!(
+            unsafe { clone.as_window_procedure()(HWND::default(), 1, WPARAM(0), LPARAM(0)) }.0,
+            i as isize + 1,
+        );
+
+        drop((thunk, clone));
+    });
+}